@@ -0,0 +1,4 @@
+ALTER TABLE Documents
+    ADD COLUMN title TEXT,
+    ADD COLUMN author TEXT,
+    ADD COLUMN notes TEXT;
\ No newline at end of file
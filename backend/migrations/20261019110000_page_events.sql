@@ -0,0 +1,12 @@
+CREATE TABLE PageEvents (
+    id uuid PRIMARY KEY NOT NULL,
+    document uuid NOT NULL,
+    page INTEGER NOT NULL,
+    occurred_on timestamptz NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT fk_page_events_document FOREIGN KEY(document) REFERENCES Documents(id)
+);
+
+CREATE INDEX page_events_document_occurred_on ON PageEvents(document, occurred_on);
+
+ALTER TABLE Documents ADD COLUMN page_count INTEGER;
\ No newline at end of file
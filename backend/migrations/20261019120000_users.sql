@@ -0,0 +1,45 @@
+CREATE TABLE Users (
+    id uuid PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    added_on timestamptz NOT NULL DEFAULT NOW()
+);
+
+-- Sessions are looked up by the SHA-256 hash of the token stored in the session cookie
+CREATE TABLE Sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id uuid NOT NULL,
+    added_on timestamptz NOT NULL DEFAULT NOW(),
+    expires_on timestamptz NOT NULL,
+
+    CONSTRAINT fk_sessions_user FOREIGN KEY(user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE ReadingProgress (
+    reader uuid NOT NULL,
+    document uuid NOT NULL,
+    current_page INTEGER NOT NULL DEFAULT 1,
+
+    PRIMARY KEY (reader, document),
+    CONSTRAINT fk_reading_progress_reader FOREIGN KEY(reader) REFERENCES Users(id) ON DELETE CASCADE,
+    CONSTRAINT fk_reading_progress_document FOREIGN KEY(document) REFERENCES Documents(id)
+);
+
+-- Progress made before accounts existed. It is handed over to the first account
+-- which is created, together with any other data which has no owner.
+CREATE TABLE UnclaimedReadingProgress AS
+    SELECT id AS document, current_page FROM Documents WHERE current_page > 1;
+
+ALTER TABLE Documents
+    DROP COLUMN current_page,
+    ADD COLUMN owner uuid,
+    ADD CONSTRAINT fk_documents_owner FOREIGN KEY(owner) REFERENCES Users(id);
+
+ALTER TABLE Bookmarks
+    ADD COLUMN owner uuid,
+    ADD CONSTRAINT fk_bookmarks_owner FOREIGN KEY(owner) REFERENCES Users(id);
+
+ALTER TABLE PageEvents
+    ADD COLUMN reader uuid,
+    ADD CONSTRAINT fk_page_events_reader FOREIGN KEY(reader) REFERENCES Users(id);
\ No newline at end of file
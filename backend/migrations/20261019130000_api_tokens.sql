@@ -0,0 +1,13 @@
+CREATE TABLE ApiTokens (
+    id uuid PRIMARY KEY NOT NULL,
+    owner uuid NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    added_on timestamptz NOT NULL DEFAULT NOW(),
+    expires_on timestamptz,
+    last_used_on timestamptz,
+    revoked_on timestamptz,
+
+    CONSTRAINT fk_api_tokens_owner FOREIGN KEY(owner) REFERENCES Users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE ShareLinks (
+    id uuid PRIMARY KEY NOT NULL,
+    document uuid NOT NULL,
+    owner uuid NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    page INTEGER,
+    added_on timestamptz NOT NULL DEFAULT NOW(),
+    expires_on timestamptz,
+    revoked_on timestamptz,
+
+    CONSTRAINT fk_share_links_document FOREIGN KEY(document) REFERENCES Documents(id) ON DELETE CASCADE,
+    CONSTRAINT fk_share_links_owner FOREIGN KEY(owner) REFERENCES Users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE Documents ADD COLUMN content_hash TEXT;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Scopes are stored as a space separated list, the same way as on SQLite
+ALTER TABLE ApiTokens ALTER COLUMN scopes TYPE TEXT USING array_to_string(scopes, ' ');
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Folder a document was imported from, relative to the imported directory
+ALTER TABLE Documents ADD COLUMN collection TEXT;
+
+-- Imports skip files which the owner already has
+CREATE INDEX documents_owner_content_hash ON Documents(owner, content_hash);
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Outcome of every file taken from the watched inbox directory
+CREATE TABLE IngestionEvents (
+    id uuid PRIMARY KEY NOT NULL,
+    file_name TEXT NOT NULL,
+    -- imported, skipped or failed
+    outcome TEXT NOT NULL,
+    document uuid,
+    reason TEXT,
+    occurred_on timestamptz NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT fk_ingestion_events_document FOREIGN KEY(document) REFERENCES Documents(id) ON DELETE SET NULL
+);
+
+CREATE INDEX ingestion_events_occurred_on ON IngestionEvents(occurred_on);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE Documents ADD COLUMN encrypted_password TEXT;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Size of the stored document in bytes, filled in for older documents when the server starts
+ALTER TABLE Documents ADD COLUMN byte_size BIGINT;
\ No newline at end of file
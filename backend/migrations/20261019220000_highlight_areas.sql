@@ -0,0 +1,2 @@
+-- Area of the page an imported highlight covers, as `left bottom right top` in PDF points
+ALTER TABLE Bookmarks ADD COLUMN highlight_area TEXT;
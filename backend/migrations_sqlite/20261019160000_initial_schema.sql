@@ -0,0 +1,107 @@
+-- The schema of the Postgres migrations as of this one. Ids are stored as 16 byte
+-- blobs, and timestamps as RFC 3339 text in UTC so that they sort chronologically.
+
+CREATE TABLE Users (
+    id BLOB PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    added_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+-- Sessions are looked up by the SHA-256 hash of the token stored in the session cookie
+CREATE TABLE Sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL,
+    added_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    expires_on TEXT NOT NULL,
+
+    CONSTRAINT fk_sessions_user FOREIGN KEY(user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE Documents (
+    id BLOB PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    added_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    title TEXT,
+    author TEXT,
+    notes TEXT,
+    page_count INTEGER,
+    owner BLOB,
+    content_hash TEXT,
+
+    CONSTRAINT fk_documents_owner FOREIGN KEY(owner) REFERENCES Users(id)
+);
+
+CREATE TABLE Bookmarks (
+    id BLOB PRIMARY KEY NOT NULL,
+    description TEXT NOT NULL,
+    added_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    page INTEGER NOT NULL,
+    document BLOB NOT NULL,
+    deleted_on TEXT,
+    owner BLOB,
+
+    CONSTRAINT fk_bookmarks_document FOREIGN KEY(document) REFERENCES Documents(id),
+    CONSTRAINT fk_bookmarks_owner FOREIGN KEY(owner) REFERENCES Users(id)
+);
+
+CREATE TABLE PageEvents (
+    id BLOB PRIMARY KEY NOT NULL,
+    document BLOB NOT NULL,
+    page INTEGER NOT NULL,
+    occurred_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    reader BLOB,
+
+    CONSTRAINT fk_page_events_document FOREIGN KEY(document) REFERENCES Documents(id),
+    CONSTRAINT fk_page_events_reader FOREIGN KEY(reader) REFERENCES Users(id)
+);
+
+CREATE INDEX page_events_document_occurred_on ON PageEvents(document, occurred_on);
+
+CREATE TABLE ReadingProgress (
+    reader BLOB NOT NULL,
+    document BLOB NOT NULL,
+    current_page INTEGER NOT NULL DEFAULT 1,
+
+    PRIMARY KEY (reader, document),
+    CONSTRAINT fk_reading_progress_reader FOREIGN KEY(reader) REFERENCES Users(id) ON DELETE CASCADE,
+    CONSTRAINT fk_reading_progress_document FOREIGN KEY(document) REFERENCES Documents(id)
+);
+
+-- Only ever filled when upgrading a Postgres installation from before accounts
+-- existed. It is kept so that setting up the first account works the same way.
+CREATE TABLE UnclaimedReadingProgress (
+    document BLOB NOT NULL,
+    current_page INTEGER NOT NULL
+);
+
+CREATE TABLE ApiTokens (
+    id BLOB PRIMARY KEY NOT NULL,
+    owner BLOB NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    -- Space separated
+    scopes TEXT NOT NULL,
+    added_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    expires_on TEXT,
+    last_used_on TEXT,
+    revoked_on TEXT,
+
+    CONSTRAINT fk_api_tokens_owner FOREIGN KEY(owner) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE ShareLinks (
+    id BLOB PRIMARY KEY NOT NULL,
+    document BLOB NOT NULL,
+    owner BLOB NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    page INTEGER,
+    added_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    expires_on TEXT,
+    revoked_on TEXT,
+
+    CONSTRAINT fk_share_links_document FOREIGN KEY(document) REFERENCES Documents(id) ON DELETE CASCADE,
+    CONSTRAINT fk_share_links_owner FOREIGN KEY(owner) REFERENCES Users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Outcome of every file taken from the watched inbox directory
+CREATE TABLE IngestionEvents (
+    id BLOB PRIMARY KEY NOT NULL,
+    file_name TEXT NOT NULL,
+    -- imported, skipped or failed
+    outcome TEXT NOT NULL,
+    document BLOB,
+    reason TEXT,
+    occurred_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+
+    CONSTRAINT fk_ingestion_events_document FOREIGN KEY(document) REFERENCES Documents(id) ON DELETE SET NULL
+);
+
+CREATE INDEX ingestion_events_occurred_on ON IngestionEvents(occurred_on);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE Bookmarks ADD COLUMN imported BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
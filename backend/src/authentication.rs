@@ -0,0 +1,323 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use anyhow::Context;
+use argon2::password_hash::{self, SaltString};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::Utc;
+use futures::future::LocalBoxFuture;
+use once_cell::sync::Lazy;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::database::{self, DbPool, ScopeList};
+use crate::error::{internal_error, ApiError};
+
+pub const SESSION_COOKIE_NAME: &str = "pdfreader_session";
+/// Prefix of personal API tokens, which makes them easy to recognise in configuration
+/// files and secret scanners.
+pub const API_TOKEN_PREFIX: &str = "pdfr_";
+
+/// What a request is allowed to do. Requests authenticated with a session cookie
+/// have every scope the user is entitled to, while API tokens are limited to the
+/// scopes they were created with.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    /// The scope required to make a request with the given method
+    fn required_for(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => Self::Read,
+            _ => Self::Write,
+        }
+    }
+}
+
+/// The user making the current request. Inserted into the request extensions by
+/// [`RequireAuthentication`], and available to any handler behind it as an extractor.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub id: Uuid,
+    pub username: String,
+    pub is_admin: bool,
+    pub scopes: Vec<TokenScope>,
+    /// Whether the request was authenticated with an API token rather than a session
+    pub with_token: bool,
+}
+
+impl AuthenticatedUser {
+    fn new(id: Uuid, username: String, is_admin: bool, scopes: Option<Vec<String>>) -> Self {
+        let with_token = scopes.is_some();
+        let scopes = match scopes {
+            Some(scopes) => scopes.iter().filter_map(|s| TokenScope::parse(s)).collect(),
+            None => vec![TokenScope::Read, TokenScope::Write, TokenScope::Admin],
+        };
+        // Scopes can only narrow what a user may do, never widen it
+        let scopes = scopes
+            .into_iter()
+            .filter(|s| *s != TokenScope::Admin || is_admin)
+            .collect();
+
+        Self {
+            id,
+            username,
+            is_admin,
+            scopes,
+            with_token,
+        }
+    }
+
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Fails unless the user is an administrator, and the request is allowed to act as one.
+    pub fn require_admin(&self) -> Result<(), ApiError> {
+        match self.has_scope(TokenScope::Admin) {
+            true => Ok(()),
+            false => Err(ApiError::forbidden("This action requires an administrator")),
+        }
+    }
+
+    /// Fails when the request was made with an API token instead of a session.
+    pub fn require_session(&self) -> Result<(), ApiError> {
+        match self.with_token {
+            true => Err(ApiError::forbidden("This action requires logging in")),
+            false => Ok(()),
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or_else(|| ApiError::unauthorized("Authentication required")),
+        )
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, anyhow::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .context("Failed to hash password")?;
+
+    Ok(hash.to_string())
+}
+
+/// Used in place of a real hash when the user does not exist, so that the time
+/// it takes to reject a login does not reveal which usernames are taken.
+static DUMMY_PASSWORD_HASH: Lazy<String> =
+    Lazy::new(|| hash_password("not a real password").expect("Failed to hash dummy password"));
+
+pub fn verify_password(
+    expected_hash: Option<&str>,
+    candidate: &str,
+) -> Result<bool, anyhow::Error> {
+    let hash = PasswordHash::new(expected_hash.unwrap_or(&DUMMY_PASSWORD_HASH))
+        .context("Stored password hash is not valid")?;
+
+    match Argon2::default().verify_password(candidate.as_bytes(), &hash) {
+        Ok(()) => Ok(expected_hash.is_some()),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(e).context("Failed to verify password"),
+    }
+}
+
+/// Generates a random token suitable for session cookies and similar secrets.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Tokens are only ever stored hashed, so that a leaked database can not be used
+/// to take over sessions.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Middleware which rejects any request that does not carry either a valid session
+/// cookie or an API token with a scope that permits the request method.
+pub struct RequireAuthentication;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireAuthentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireAuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAuthenticationMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequireAuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireAuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let user = match authenticate(&req).await {
+                Ok(user) => user,
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            };
+            match user {
+                Some(user) if !user.has_scope(TokenScope::required_for(req.method())) => {
+                    let error = ApiError::forbidden(
+                        "The API token does not have the scope required for this request",
+                    );
+                    Ok(req.error_response(error).map_into_right_body())
+                }
+                Some(user) => {
+                    req.extensions_mut().insert(user);
+                    service
+                        .call(req)
+                        .await
+                        .map(ServiceResponse::map_into_left_body)
+                }
+                None => Ok(req
+                    .error_response(ApiError::unauthorized("Authentication required"))
+                    .map_into_right_body()),
+            }
+        })
+    }
+}
+
+async fn authenticate(req: &ServiceRequest) -> Result<Option<AuthenticatedUser>, ApiError> {
+    let pool = req.app_data::<web::Data<DbPool>>().ok_or_else(|| {
+        ApiError::internal(
+            "Database is not configured",
+            anyhow::anyhow!("No database pool in application data"),
+        )
+    })?;
+
+    if let Some(authorization) = req.headers().get(header::AUTHORIZATION) {
+        let Some(token) = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return Ok(None);
+        };
+        return authenticate_token(pool, token.trim()).await;
+    }
+
+    let Some(cookie) = req.cookie(SESSION_COOKIE_NAME) else {
+        return Ok(None);
+    };
+
+    let user: Option<(Uuid, String, bool)> = database::query_as(
+        "SELECT u.id, u.username, u.is_admin FROM Sessions s
+        JOIN Users u ON u.id = s.user_id
+        WHERE s.id = $1 AND s.expires_on > $2",
+    )
+    .bind(hash_token(cookie.value()))
+    .bind(Utc::now())
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to look up session"))?;
+
+    Ok(user.map(|(id, username, is_admin)| AuthenticatedUser::new(id, username, is_admin, None)))
+}
+
+async fn authenticate_token(
+    pool: &DbPool,
+    token: &str,
+) -> Result<Option<AuthenticatedUser>, ApiError> {
+    if !token.starts_with(API_TOKEN_PREFIX) {
+        return Ok(None);
+    }
+
+    let token_hash = hash_token(token);
+    let now = Utc::now();
+    let user: Option<(Uuid, String, bool, ScopeList)> = database::query_as(
+        "SELECT u.id, u.username, u.is_admin, t.scopes FROM ApiTokens t
+        JOIN Users u ON u.id = t.owner
+        WHERE t.token_hash = $1
+            AND t.revoked_on IS NULL
+            AND (t.expires_on IS NULL OR t.expires_on > $2)",
+    )
+    .bind(&token_hash)
+    .bind(now)
+    .fetch_optional(pool)
+    .await
+    .map_err(internal_error("Failed to look up API token"))?;
+
+    if user.is_some() {
+        database::query("UPDATE ApiTokens SET last_used_on = $1 WHERE token_hash = $2")
+            .bind(now)
+            .bind(&token_hash)
+            .execute(pool)
+            .await
+            .map_err(internal_error("Failed to record use of API token"))?;
+    }
+
+    Ok(user.map(|(id, username, is_admin, scopes)| {
+        AuthenticatedUser::new(id, username, is_admin, Some(scopes.0))
+    }))
+}
@@ -0,0 +1,771 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::Pdfium;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool, DbTransaction};
+use crate::encryption::FileEncryption;
+use crate::error::{error_chain_fmt, ApiError};
+use crate::indexer::{Indexer, IndexerError};
+use crate::routes::documents::post::{read_pdf_file, AddDocumentError, AnnotationImport, PdfFile};
+use crate::secrets::SecretError;
+use crate::startup::{bind_pdfium, PdfiumUnavailable};
+
+/// Version of the archive layout. Archives of a newer version than this are refused.
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const INDEX_FILE: &str = "index/pages.jsonl";
+
+/// Describes the contents of a backup archive. It is the last entry of the archive,
+/// so that the checksums can be computed while the other entries are written.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created_on: DateTime<Utc>,
+    pub server_version: String,
+    /// Database system the backup was made from. Rows are stored as JSON, so they
+    /// can be restored into either.
+    pub database: String,
+    pub document_count: usize,
+    /// Whether the index was left out, so that it has to be rebuilt from the documents
+    pub rebuild_index: bool,
+    /// Every other entry of the archive, by path
+    pub files: BTreeMap<String, ArchivedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchivedFile {
+    pub size: u64,
+    /// Hex encoded SHA-256
+    pub sha256: String,
+}
+
+/// What was restored from a backup
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RestoreSummary {
+    pub documents: usize,
+    /// When the backup was made
+    pub created_on: DateTime<Utc>,
+    /// Whether the index was rebuilt from the documents, rather than restored
+    pub rebuilt_index: bool,
+}
+
+#[derive(Clone, Copy)]
+enum ColumnType {
+    Uuid,
+    Text,
+    Integer,
+    BigInt,
+    Boolean,
+    Timestamp,
+}
+
+struct Table {
+    name: &'static str,
+    columns: &'static [(&'static str, ColumnType)],
+}
+
+/// Every table which is backed up, in an order which satisfies the foreign keys.
+/// Sessions are left out, so everyone has to log in again after a restore.
+const TABLES: &[Table] = {
+    use ColumnType::*;
+    &[
+        Table {
+            name: "Users",
+            columns: &[
+                ("id", Uuid),
+                ("username", Text),
+                ("password_hash", Text),
+                ("is_admin", Boolean),
+                ("added_on", Timestamp),
+            ],
+        },
+        Table {
+            name: "Documents",
+            columns: &[
+                ("id", Uuid),
+                ("name", Text),
+                ("added_on", Timestamp),
+                ("title", Text),
+                ("author", Text),
+                ("notes", Text),
+                ("page_count", Integer),
+                ("owner", Uuid),
+                ("content_hash", Text),
+                ("collection", Text),
+                ("encrypted_password", Text),
+                ("byte_size", BigInt),
+            ],
+        },
+        Table {
+            name: "Bookmarks",
+            columns: &[
+                ("id", Uuid),
+                ("description", Text),
+                ("added_on", Timestamp),
+                ("page", Integer),
+                ("document", Uuid),
+                ("deleted_on", Timestamp),
+                ("owner", Uuid),
+                ("imported", Boolean),
+                ("highlight_area", Text),
+            ],
+        },
+        Table {
+            name: "PageEvents",
+            columns: &[
+                ("id", Uuid),
+                ("document", Uuid),
+                ("page", Integer),
+                ("occurred_on", Timestamp),
+                ("reader", Uuid),
+            ],
+        },
+        Table {
+            name: "ReadingProgress",
+            columns: &[
+                ("reader", Uuid),
+                ("document", Uuid),
+                ("current_page", Integer),
+            ],
+        },
+        Table {
+            name: "UnclaimedReadingProgress",
+            columns: &[("document", Uuid), ("current_page", Integer)],
+        },
+        Table {
+            name: "ApiTokens",
+            columns: &[
+                ("id", Uuid),
+                ("owner", Uuid),
+                ("name", Text),
+                ("token_hash", Text),
+                ("scopes", Text),
+                ("added_on", Timestamp),
+                ("expires_on", Timestamp),
+                ("last_used_on", Timestamp),
+                ("revoked_on", Timestamp),
+            ],
+        },
+        Table {
+            name: "ShareLinks",
+            columns: &[
+                ("id", Uuid),
+                ("document", Uuid),
+                ("owner", Uuid),
+                ("token_hash", Text),
+                ("password_hash", Text),
+                ("page", Integer),
+                ("added_on", Timestamp),
+                ("expires_on", Timestamp),
+                ("revoked_on", Timestamp),
+            ],
+        },
+        Table {
+            name: "IngestionEvents",
+            columns: &[
+                ("id", Uuid),
+                ("file_name", Text),
+                ("outcome", Text),
+                ("document", Uuid),
+                ("reason", Text),
+                ("occurred_on", Timestamp),
+            ],
+        },
+    ]
+};
+
+/// A line of `index/pages.jsonl`
+#[derive(Serialize, Deserialize)]
+struct IndexedPage {
+    document: Uuid,
+    page: u64,
+    body: String,
+}
+
+/// Directory within the storage location for preparing or unpacking an archive.
+/// It is removed with everything in it when dropped.
+pub struct ScratchDirectory(PathBuf);
+
+impl ScratchDirectory {
+    pub fn create(config: &Settings) -> io::Result<Self> {
+        let path = config
+            .storage_location
+            .join(format!(".scratch-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDirectory {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            log::error!("Failed to remove {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Writes the database contents, the stored documents and, unless `include_index`
+/// is false, the indexed text of every page to a gzipped tar archive at `destination`.
+/// The text of the pages is encrypted with the server key when storage is.
+pub async fn create_backup(
+    pool: &DbPool,
+    config: &Settings,
+    indexer: &Indexer,
+    destination: &Path,
+    include_index: bool,
+) -> Result<Manifest, BackupError> {
+    log::info!("Backing up the library to {}", destination.display());
+    let scratch = ScratchDirectory::create(config)?;
+    std::fs::create_dir_all(scratch.path().join("database"))?;
+    std::fs::create_dir_all(scratch.path().join("documents"))?;
+
+    // Every table is read from the same snapshot, and the documents of that
+    // snapshot are set aside before it ends
+    let mut tx = pool.begin().await?;
+    database::query(tx.backend().repeatable_read())
+        .execute(&mut tx)
+        .await?;
+    let mut entries = Vec::new();
+    let mut document_ids = Vec::new();
+    for table in TABLES {
+        let rows = export_table(&mut tx, table).await?;
+        if table.name == "Documents" {
+            document_ids = self::document_ids(&rows);
+        }
+        let name = format!("database/{}.jsonl", table.name);
+        let path = scratch.path().join(&name);
+        write_json_lines(File::create(&path)?, rows)?;
+        entries.push((name, path));
+    }
+
+    if include_index {
+        let mut pages = Vec::new();
+        for document in &document_ids {
+            for (page, body) in indexer.document_pages(document)? {
+                pages.push(IndexedPage {
+                    document: *document,
+                    page,
+                    body,
+                });
+            }
+        }
+        std::fs::create_dir_all(scratch.path().join("index"))?;
+        let mut file = FileEncryption::load(config)?.create(&scratch.path().join(INDEX_FILE))?;
+        write_json_lines(&mut file, pages)?;
+        file.finish()?;
+        entries.push((INDEX_FILE.to_owned(), scratch.path().join(INDEX_FILE)));
+    }
+
+    for document in &document_ids {
+        let name = format!("documents/{document}.pdf");
+        let path = config
+            .documents_storage_path()
+            .join(document.to_string())
+            .with_extension("pdf");
+        if !path.exists() {
+            return Err(BackupError::MissingDocument(*document));
+        }
+        // A link keeps the document for the archive even when it is deleted in the
+        // meantime, without taking up its space twice
+        let archived = scratch.path().join(&name);
+        if std::fs::hard_link(&path, &archived).is_err() {
+            std::fs::copy(&path, &archived)?;
+        }
+        entries.push((name, archived));
+    }
+    tx.commit().await?;
+
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        created_on: Utc::now(),
+        server_version: env!("CARGO_PKG_VERSION").to_owned(),
+        database: pool.backend().system().to_owned(),
+        document_count: document_ids.len(),
+        rebuild_index: !include_index,
+        files: BTreeMap::new(),
+    };
+    let destination = destination.to_owned();
+    let manifest = web::block(move || write_archive(&destination, entries, manifest))
+        .await
+        .map_err(io::Error::other)??;
+
+    log::info!(
+        "Backed up {} documents and {} files",
+        manifest.document_count,
+        manifest.files.len()
+    );
+    Ok(manifest)
+}
+
+/// Replaces the library with the one in the archive at `archive`. Every checksum is
+/// verified before anything is changed. A library which has documents is only
+/// replaced when `force` is set.
+///
+/// The restored documents and index are prepared next to the live ones, and only
+/// take their place once the database is committed, so that a restore which fails
+/// leaves the library as it was.
+pub async fn restore_backup(
+    pool: &DbPool,
+    config: &Settings,
+    indexer: &Indexer,
+    pdfium: &Lazy<Pdfium>,
+    archive: &Path,
+    force: bool,
+) -> Result<RestoreSummary, BackupError> {
+    log::info!("Restoring the library from {}", archive.display());
+    let scratch = ScratchDirectory::create(config)?;
+    let manifest = {
+        let archive = archive.to_owned();
+        let destination = scratch.path().to_owned();
+        web::block(move || unpack_archive(&archive, &destination))
+            .await
+            .map_err(io::Error::other)??
+    };
+
+    let encryption = FileEncryption::load(config)?;
+    let mut tx = pool.begin().await?;
+    let (existing,): (i64,) = database::query_as("SELECT COUNT(*) FROM Documents")
+        .fetch_one(&mut tx)
+        .await?;
+    if existing > 0 && !force {
+        return Err(BackupError::LibraryNotEmpty(existing));
+    }
+
+    let documents = read_json_lines::<Map<String, Value>>(
+        &scratch.path().join("database/Documents.jsonl"),
+        &encryption,
+    )?;
+    let document_ids = document_ids(&documents);
+    let restored_documents = scratch.path().join("documents");
+    std::fs::create_dir_all(&restored_documents)?;
+    if let Some(missing) = document_ids
+        .iter()
+        .find(|id| !restored_documents.join(format!("{id}.pdf")).exists())
+    {
+        return Err(BackupError::MissingDocument(*missing));
+    }
+    let pages = if manifest.rebuild_index {
+        log::info!("Rebuilding the index of {} documents", document_ids.len());
+        read_restored_pages(pdfium, &encryption, &restored_documents, &documents)?
+    } else {
+        read_json_lines::<IndexedPage>(&scratch.path().join(INDEX_FILE), &encryption)?
+    };
+
+    database::query("DELETE FROM Sessions")
+        .execute(&mut tx)
+        .await?;
+    for table in TABLES.iter().rev() {
+        database::query(&format!("DELETE FROM {}", table.name))
+            .execute(&mut tx)
+            .await?;
+    }
+    for table in TABLES {
+        let path = scratch
+            .path()
+            .join(format!("database/{}.jsonl", table.name));
+        let rows = read_json_lines::<Map<String, Value>>(&path, &encryption)?;
+        import_table(&mut tx, table, rows).await?;
+    }
+
+    // The new pages are only visible once the writer is committed, and are rolled
+    // back when it is dropped before
+    let mut writer = indexer.get_writer().await?;
+    writer.delete_all()?;
+    for page in &pages {
+        writer.index_page(&page.document, page.page, &page.body)?;
+    }
+
+    tx.commit().await?;
+    if let Err(e) = replace_documents(config, &restored_documents) {
+        log::error!("The database was restored, but the documents could not be put in place");
+        return Err(e.into());
+    }
+    writer.commit()?;
+    log::info!(
+        "Restored {} documents from a backup made on {}",
+        document_ids.len(),
+        manifest.created_on
+    );
+    Ok(RestoreSummary {
+        documents: document_ids.len(),
+        created_on: manifest.created_on,
+        rebuilt_index: manifest.rebuild_index,
+    })
+}
+
+/// Ids of the rows of the Documents table
+fn document_ids(rows: &[Map<String, Value>]) -> Vec<Uuid> {
+    rows.iter()
+        .filter_map(|row| row.get("id").and_then(Value::as_str))
+        .filter_map(|id| Uuid::parse_str(id).ok())
+        .collect()
+}
+
+/// Reads the text of every page of the restored documents, for an archive which
+/// was made without the index
+fn read_restored_pages(
+    pdfium: &Lazy<Pdfium>,
+    encryption: &FileEncryption,
+    restored_documents: &Path,
+    documents: &[Map<String, Value>],
+) -> Result<Vec<IndexedPage>, BackupError> {
+    let pdfium = bind_pdfium(pdfium)?;
+    let encrypted_passwords = documents
+        .iter()
+        .filter_map(|row| {
+            let id = row.get("id").and_then(Value::as_str)?;
+            let password = row.get("encrypted_password").and_then(Value::as_str)?;
+            Some((Uuid::parse_str(id).ok()?, password.to_owned()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut pages = Vec::new();
+    for document in document_ids(documents) {
+        let path = restored_documents
+            .join(document.to_string())
+            .with_extension("pdf");
+        let password = match (encrypted_passwords.get(&document), encryption.key()) {
+            (Some(encrypted), Some(key)) => Some(key.decrypt_string(encrypted)?),
+            _ => None,
+        };
+        let file = PdfFile {
+            path: &path,
+            encryption,
+            password: password.as_deref(),
+        };
+        match read_pdf_file(pdfium, &file, &document, AnnotationImport::default()) {
+            Ok(read) => {
+                pages.extend(
+                    read.pages
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, body)| IndexedPage {
+                            document,
+                            page: index as u64 + 1,
+                            body,
+                        }),
+                )
+            }
+            // Its password was not kept, so its pages cannot be read
+            Err(AddDocumentError::IncorrectPassword) => {
+                log::warn!(
+                    "Document {document} is protected by a password, and is left out of the index"
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(pages)
+}
+
+/// Puts the restored documents in place of the stored ones by swapping the
+/// directories. The old documents are only removed once the new ones are in place.
+fn replace_documents(config: &Settings, restored_documents: &Path) -> io::Result<()> {
+    let documents_path = config.documents_storage_path();
+    let replaced = ScratchDirectory::create(config)?;
+    let old_documents = replaced.path().join("documents");
+    std::fs::rename(&documents_path, &old_documents)?;
+    if let Err(e) = std::fs::rename(restored_documents, &documents_path) {
+        if let Err(e) = std::fs::rename(&old_documents, &documents_path) {
+            log::error!(
+                "Failed to put the old documents back, they are kept in {}",
+                old_documents.display()
+            );
+            std::mem::forget(replaced);
+            return Err(e);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+async fn export_table(
+    tx: &mut DbTransaction<'_>,
+    table: &Table,
+) -> Result<Vec<Map<String, Value>>, BackupError> {
+    let columns = table
+        .columns
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    let rows = database::query(&format!(
+        "SELECT {} FROM {}",
+        columns.join(", "),
+        table.name
+    ))
+    .fetch_all(&mut *tx)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            let mut values = Map::new();
+            for (name, column_type) in table.columns {
+                let value = match column_type {
+                    ColumnType::Uuid => json!(row.try_get::<Option<Uuid>>(name)?),
+                    ColumnType::Text => json!(row.try_get::<Option<String>>(name)?),
+                    ColumnType::Integer => json!(row.try_get::<Option<i32>>(name)?),
+                    ColumnType::BigInt => json!(row.try_get::<Option<i64>>(name)?),
+                    ColumnType::Boolean => json!(row.try_get::<Option<bool>>(name)?),
+                    ColumnType::Timestamp => {
+                        json!(row.try_get::<Option<DateTime<Utc>>>(name)?)
+                    }
+                };
+                values.insert((*name).to_owned(), value);
+            }
+            Ok(values)
+        })
+        .collect()
+}
+
+async fn import_table(
+    tx: &mut DbTransaction<'_>,
+    table: &Table,
+    rows: Vec<Map<String, Value>>,
+) -> Result<(), BackupError> {
+    for mut row in rows {
+        // Columns added after the backup was made are left to their defaults
+        let columns = table
+            .columns
+            .iter()
+            .filter(|(name, _)| row.contains_key(*name))
+            .collect::<Vec<_>>();
+        let names = columns.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        let placeholders = (1..=columns.len())
+            .map(|i| format!("${i}"))
+            .collect::<Vec<_>>();
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table.name,
+            names.join(", "),
+            placeholders.join(", ")
+        );
+
+        let mut query = database::query(&statement);
+        for (name, column_type) in columns {
+            let value = row.remove(*name).unwrap_or(Value::Null);
+            query = bind_value(query, *column_type, value)
+                .map_err(|e| BackupError::InvalidArchive(format!("{}.{name}: {e}", table.name)))?;
+        }
+        query.execute(&mut *tx).await?;
+    }
+    Ok(())
+}
+
+fn bind_value(
+    query: database::Query<'_>,
+    column_type: ColumnType,
+    value: Value,
+) -> Result<database::Query<'_>, serde_json::Error> {
+    Ok(match column_type {
+        ColumnType::Uuid => query.bind(serde_json::from_value::<Option<Uuid>>(value)?),
+        ColumnType::Text => query.bind(serde_json::from_value::<Option<String>>(value)?),
+        ColumnType::Integer => query.bind(serde_json::from_value::<Option<i32>>(value)?),
+        ColumnType::BigInt => query.bind(serde_json::from_value::<Option<i64>>(value)?),
+        ColumnType::Boolean => query.bind(serde_json::from_value::<Option<bool>>(value)?),
+        ColumnType::Timestamp => {
+            query.bind(serde_json::from_value::<Option<DateTime<Utc>>>(value)?)
+        }
+    })
+}
+
+fn write_json_lines<T: Serialize>(file: impl Write, values: Vec<T>) -> Result<(), BackupError> {
+    let mut file = BufWriter::new(file);
+    for value in values {
+        serde_json::to_writer(&mut file, &value)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Reads a file of the archive, which is decrypted when it was encrypted
+fn read_json_lines<T: for<'de> Deserialize<'de>>(
+    path: &Path,
+    encryption: &FileEncryption,
+) -> Result<Vec<T>, BackupError> {
+    let file = encryption.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => {
+            BackupError::InvalidArchive(format!("{} is missing", path.display()))
+        }
+        _ => e.into(),
+    })?;
+    BufReader::new(file)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Passes through what is read, hashing it on the way
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn write_archive(
+    destination: &Path,
+    entries: Vec<(String, PathBuf)>,
+    mut manifest: Manifest,
+) -> Result<Manifest, BackupError> {
+    let file = File::create(destination)?;
+    let mut archive =
+        tar::Builder::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+
+    for (name, source) in entries {
+        let file = File::open(&source)?;
+        let size = file.metadata()?.len();
+        let mut reader = HashingReader {
+            inner: file,
+            hasher: Sha256::new(),
+        };
+        archive.append_data(&mut entry_header(size, &manifest), &name, &mut reader)?;
+        let sha256 = hex::encode(reader.hasher.finalize());
+        manifest.files.insert(name, ArchivedFile { size, sha256 });
+    }
+
+    let contents = serde_json::to_vec_pretty(&manifest)?;
+    archive.append_data(
+        &mut entry_header(contents.len() as u64, &manifest),
+        MANIFEST_FILE,
+        contents.as_slice(),
+    )?;
+    archive
+        .into_inner()?
+        .finish()?
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+
+    Ok(manifest)
+}
+
+fn entry_header(size: u64, manifest: &Manifest) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o600);
+    header.set_mtime(manifest.created_on.timestamp().max(0) as u64);
+    header
+}
+
+/// Unpacks the archive into `destination`, and checks the format version and
+/// the checksum of every file listed in the manifest
+fn unpack_archive(archive: &Path, destination: &Path) -> Result<Manifest, BackupError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(File::open(archive)?)));
+    let entries = archive
+        .entries()
+        .map_err(|e| BackupError::InvalidArchive(e.to_string()))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| BackupError::InvalidArchive(e.to_string()))?;
+        // Entries which would end up outside of the destination are skipped
+        if !entry
+            .unpack_in(destination)
+            .map_err(|e| BackupError::InvalidArchive(e.to_string()))?
+        {
+            return Err(BackupError::InvalidArchive(
+                "The archive contains paths outside of it".to_owned(),
+            ));
+        }
+    }
+
+    let manifest = std::fs::read(destination.join(MANIFEST_FILE))
+        .map_err(|_| BackupError::InvalidArchive(format!("{MANIFEST_FILE} is missing")))?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)
+        .map_err(|e| BackupError::InvalidArchive(format!("{MANIFEST_FILE}: {e}")))?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(BackupError::UnsupportedVersion(manifest.format_version));
+    }
+
+    for (name, expected) in &manifest.files {
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(BackupError::InvalidArchive(format!("Invalid path {name}")));
+        }
+        let mut file = File::open(destination.join(relative))
+            .map_err(|_| BackupError::InvalidArchive(format!("{name} is missing")))?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut file, &mut hasher)?;
+        if size != expected.size || hex::encode(hasher.finalize()) != expected.sha256 {
+            return Err(BackupError::ChecksumMismatch(name.clone()));
+        }
+    }
+
+    Ok(manifest)
+}
+
+#[derive(thiserror::Error)]
+pub enum BackupError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Index(#[from] IndexerError),
+    #[error("Failed to rebuild the index")]
+    Indexing(#[from] AddDocumentError),
+    #[error(transparent)]
+    Pdfium(#[from] PdfiumUnavailable),
+    #[error("Failed to read the passwords of protected documents")]
+    Secret(#[from] SecretError),
+    #[error("Document {0} is missing from storage")]
+    MissingDocument(Uuid),
+    #[error("Not a valid backup archive: {0}")]
+    InvalidArchive(String),
+    #[error("The backup has format version {0}, which is newer than this server supports")]
+    UnsupportedVersion(u32),
+    #[error("{0} does not match its checksum, the archive is corrupt")]
+    ChecksumMismatch(String),
+    #[error("The library has {0} documents, and would be overwritten by the backup")]
+    LibraryNotEmpty(i64),
+}
+
+impl std::fmt::Debug for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<BackupError> for ApiError {
+    fn from(e: BackupError) -> Self {
+        match e {
+            BackupError::InvalidArchive(_)
+            | BackupError::UnsupportedVersion(_)
+            | BackupError::ChecksumMismatch(_) => ApiError::validation(e.to_string()),
+            BackupError::LibraryNotEmpty(_) => ApiError::conflict(e.to_string()),
+            BackupError::Pdfium(e) => e.into(),
+            e => ApiError::internal("Failed to back up or restore the library", e),
+        }
+    }
+}
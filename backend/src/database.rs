@@ -1,22 +1,582 @@
 use crate::configuration::Settings;
-use sqlx::{postgres::PgConnectOptions, ConnectOptions, PgPool};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgArguments, PgConnectOptions, PgPool, PgQueryResult, PgRow};
+use sqlx::sqlite::{
+    SqliteArguments, SqliteConnectOptions, SqlitePool, SqliteQueryResult, SqliteRow,
+};
+use sqlx::types::Type;
+use sqlx::{
+    Arguments, ConnectOptions, Decode, Encode, FromRow, PgConnection, Postgres, Row, Sqlite,
+    SqliteConnection, Transaction,
+};
+use std::marker::PhantomData;
 use std::str::FromStr;
 
-pub fn get_connection_pool(settings: &Settings) -> PgPool {
-    log::info!("Setting up connection pool");
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!();
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+/// The database systems the server can store its data in. Which one is used
+/// depends on the scheme of the configured URL. Queries are written so that they
+/// run on both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Postgres,
+    Sqlite,
+}
+
+impl Backend {
+    /// The backend a database URL is for, if it is one of the supported ones
+    pub fn of_url(url: &str) -> Option<Self> {
+        match url.split_once(':') {
+            Some(("postgres" | "postgresql", _)) => Some(Self::Postgres),
+            Some(("sqlite", _)) => Some(Self::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Name of the database system, as used by the OpenTelemetry conventions
+    pub fn system(self) -> &'static str {
+        match self {
+            Self::Postgres => "postgresql",
+            Self::Sqlite => "sqlite",
+        }
+    }
+
+    pub fn migrator(self) -> &'static Migrator {
+        match self {
+            Self::Postgres => &POSTGRES_MIGRATOR,
+            Self::Sqlite => &SQLITE_MIGRATOR,
+        }
+    }
+
+    /// Keeps other transactions from writing to the Users table until the current
+    /// one ends. SQLite has no table locks, but a write takes the lock of the whole
+    /// database.
+    pub fn lock_users(self) -> &'static str {
+        match self {
+            Self::Postgres => "LOCK TABLE Users IN EXCLUSIVE MODE",
+            Self::Sqlite => "UPDATE Users SET id = id WHERE FALSE",
+        }
+    }
+
+    /// Makes every query of the current transaction read from the same snapshot of
+    /// the database, and must come before them. A transaction of SQLite keeps the
+    /// snapshot of its first read anyway.
+    pub fn repeatable_read(self) -> &'static str {
+        match self {
+            Self::Postgres => "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY",
+            Self::Sqlite => "SELECT 1",
+        }
+    }
+}
+
+pub enum DbConnectOptions {
+    Postgres(PgConnectOptions),
+    Sqlite(SqliteConnectOptions),
+}
+
+/// Parses the configured database URL. Any path is a valid SQLite database, so
+/// the scheme decides which database system is used.
+pub fn connect_options(settings: &Settings) -> Result<DbConnectOptions, sqlx::Error> {
     let connection_string = settings.get_connection_string_with_db();
-    let mut options =
-        PgConnectOptions::from_str(&connection_string).expect("Failed to parse database url");
-    options.log_statements(log::LevelFilter::Debug);
+    match Backend::of_url(&connection_string) {
+        Some(Backend::Postgres) => Ok(DbConnectOptions::Postgres(PgConnectOptions::from_str(
+            &connection_string,
+        )?)),
+        Some(Backend::Sqlite) => Ok(DbConnectOptions::Sqlite(
+            SqliteConnectOptions::from_str(&connection_string)?
+                .create_if_missing(true)
+                .foreign_keys(true)
+                .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+                // Writers take turns, so wait for the one holding the lock rather than failing
+                .busy_timeout(std::time::Duration::from_secs(30)),
+        )),
+        None => Err(sqlx::Error::Configuration(
+            "the URL must start with postgres: or sqlite:".into(),
+        )),
+    }
+}
+
+/// A pool of connections to the configured database
+#[derive(Clone, Debug)]
+pub enum DbPool {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+impl DbPool {
+    pub fn connect_lazy_with(options: DbConnectOptions) -> Self {
+        match options {
+            DbConnectOptions::Postgres(options) => {
+                Self::Postgres(PgPool::connect_lazy_with(options))
+            }
+            DbConnectOptions::Sqlite(options) => {
+                Self::Sqlite(SqlitePool::connect_lazy_with(options))
+            }
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            Self::Postgres(_) => Backend::Postgres,
+            Self::Sqlite(_) => Backend::Sqlite,
+        }
+    }
+
+    pub async fn begin(&self) -> Result<DbTransaction<'static>, sqlx::Error> {
+        Ok(match self {
+            Self::Postgres(pool) => DbTransaction::Postgres(Box::new(pool.begin().await?)),
+            Self::Sqlite(pool) => DbTransaction::Sqlite(pool.begin().await?),
+        })
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Self::Postgres(pool) => pool.close().await,
+            Self::Sqlite(pool) => pool.close().await,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Self::Postgres(pool) => pool.is_closed(),
+            Self::Sqlite(pool) => pool.is_closed(),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::Postgres(pool) => pool.size(),
+            Self::Sqlite(pool) => pool.size(),
+        }
+    }
+
+    pub fn num_idle(&self) -> usize {
+        match self {
+            Self::Postgres(pool) => pool.num_idle(),
+            Self::Sqlite(pool) => pool.num_idle(),
+        }
+    }
+}
+
+/// A transaction on the configured database. The one of Postgres is much larger,
+/// so it is kept on the heap.
+pub enum DbTransaction<'c> {
+    Postgres(Box<Transaction<'c, Postgres>>),
+    Sqlite(Transaction<'c, Sqlite>),
+}
+
+impl DbTransaction<'_> {
+    pub fn backend(&self) -> Backend {
+        match self {
+            Self::Postgres(_) => Backend::Postgres,
+            Self::Sqlite(_) => Backend::Sqlite,
+        }
+    }
+
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(transaction) => transaction.commit().await,
+            Self::Sqlite(transaction) => transaction.commit().await,
+        }
+    }
+
+    pub async fn rollback(self) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(transaction) => transaction.rollback().await,
+            Self::Sqlite(transaction) => transaction.rollback().await,
+        }
+    }
+}
+
+/// What a query is run on: a pool, or the connection of a transaction
+pub enum DbExecutor<'e> {
+    PostgresPool(&'e PgPool),
+    SqlitePool(&'e SqlitePool),
+    PostgresConnection(&'e mut PgConnection),
+    SqliteConnection(&'e mut SqliteConnection),
+}
+
+impl<'e> From<&'e DbPool> for DbExecutor<'e> {
+    fn from(pool: &'e DbPool) -> Self {
+        match pool {
+            DbPool::Postgres(pool) => Self::PostgresPool(pool),
+            DbPool::Sqlite(pool) => Self::SqlitePool(pool),
+        }
+    }
+}
+
+impl<'e> From<&'e mut DbTransaction<'_>> for DbExecutor<'e> {
+    fn from(transaction: &'e mut DbTransaction<'_>) -> Self {
+        match transaction {
+            DbTransaction::Postgres(transaction) => Self::PostgresConnection(transaction),
+            DbTransaction::Sqlite(transaction) => Self::SqliteConnection(transaction),
+        }
+    }
+}
+
+/// A value which can be bound to a query on either database
+pub trait Param<'q>: Send + Sync {
+    fn add_postgres(&self, arguments: &mut PgArguments);
+    fn add_sqlite(&self, arguments: &mut SqliteArguments<'q>);
+}
+
+impl<'q, T> Param<'q> for T
+where
+    T: Encode<'q, Postgres>
+        + Type<Postgres>
+        + Encode<'q, Sqlite>
+        + Type<Sqlite>
+        + Clone
+        + Send
+        + Sync
+        + 'q,
+{
+    fn add_postgres(&self, arguments: &mut PgArguments) {
+        arguments.add(self.clone());
+    }
+
+    fn add_sqlite(&self, arguments: &mut SqliteArguments<'q>) {
+        arguments.add(self.clone());
+    }
+}
+
+/// A row read by [`query`], for columns which are not known until runtime
+pub enum DbRow {
+    Postgres(PgRow),
+    Sqlite(SqliteRow),
+}
+
+impl DbRow {
+    pub fn try_get<'r, T>(&'r self, column: &str) -> Result<T, sqlx::Error>
+    where
+        T: Decode<'r, Postgres> + Type<Postgres> + Decode<'r, Sqlite> + Type<Sqlite>,
+    {
+        match self {
+            Self::Postgres(row) => row.try_get(column),
+            Self::Sqlite(row) => row.try_get(column),
+        }
+    }
+}
+
+pub struct DbQueryResult {
+    rows_affected: u64,
+}
+
+impl DbQueryResult {
+    pub fn rows_affected(&self) -> u64 {
+        self.rows_affected
+    }
+}
+
+impl From<PgQueryResult> for DbQueryResult {
+    fn from(result: PgQueryResult) -> Self {
+        Self {
+            rows_affected: result.rows_affected(),
+        }
+    }
+}
+
+impl From<SqliteQueryResult> for DbQueryResult {
+    fn from(result: SqliteQueryResult) -> Self {
+        Self {
+            rows_affected: result.rows_affected(),
+        }
+    }
+}
+
+/// Runs the sqlx query built for the database of `$executor`, converting what it
+/// returns with `$convert`
+macro_rules! run_on {
+    ($executor:expr, $query:ident, $method:ident, $convert:expr) => {
+        match $executor.into() {
+            DbExecutor::PostgresPool(pool) => $query.postgres().$method(pool).await.map($convert),
+            DbExecutor::PostgresConnection(connection) => {
+                $query.postgres().$method(connection).await.map($convert)
+            }
+            DbExecutor::SqlitePool(pool) => $query.sqlite().$method(pool).await.map($convert),
+            DbExecutor::SqliteConnection(connection) => {
+                $query.sqlite().$method(connection).await.map($convert)
+            }
+        }
+    };
+}
+
+/// A statement and the values bound to it, which is turned into an sqlx query for
+/// the database it runs on
+pub struct Query<'q> {
+    sql: &'q str,
+    arguments: Vec<Box<dyn Param<'q> + 'q>>,
+}
+
+/// Like `sqlx::query`, for whichever database is configured
+pub fn query(sql: &str) -> Query<'_> {
+    Query {
+        sql,
+        arguments: Vec::new(),
+    }
+}
+
+impl<'q> Query<'q> {
+    pub fn bind(mut self, value: impl Param<'q> + 'q) -> Self {
+        self.arguments.push(Box::new(value));
+        self
+    }
+
+    pub fn sql(&self) -> &'q str {
+        self.sql
+    }
+
+    fn postgres_arguments(&self) -> PgArguments {
+        let mut arguments = PgArguments::default();
+        for value in &self.arguments {
+            value.add_postgres(&mut arguments);
+        }
+        arguments
+    }
+
+    fn sqlite_arguments(&self) -> SqliteArguments<'q> {
+        let mut arguments = SqliteArguments::default();
+        for value in &self.arguments {
+            value.add_sqlite(&mut arguments);
+        }
+        arguments
+    }
+
+    fn postgres(&self) -> sqlx::query::Query<'q, Postgres, PgArguments> {
+        sqlx::query_with(self.sql, self.postgres_arguments())
+    }
+
+    fn sqlite(&self) -> sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>> {
+        sqlx::query_with(self.sql, self.sqlite_arguments())
+    }
+
+    pub async fn execute<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<DbQueryResult, sqlx::Error> {
+        run_on!(executor, self, execute, DbQueryResult::from)
+    }
+
+    pub async fn fetch_all<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<Vec<DbRow>, sqlx::Error> {
+        match executor.into() {
+            DbExecutor::PostgresPool(pool) => {
+                let rows = self.postgres().fetch_all(pool).await?;
+                Ok(rows.into_iter().map(DbRow::Postgres).collect())
+            }
+            DbExecutor::PostgresConnection(connection) => {
+                let rows = self.postgres().fetch_all(connection).await?;
+                Ok(rows.into_iter().map(DbRow::Postgres).collect())
+            }
+            DbExecutor::SqlitePool(pool) => {
+                let rows = self.sqlite().fetch_all(pool).await?;
+                Ok(rows.into_iter().map(DbRow::Sqlite).collect())
+            }
+            DbExecutor::SqliteConnection(connection) => {
+                let rows = self.sqlite().fetch_all(connection).await?;
+                Ok(rows.into_iter().map(DbRow::Sqlite).collect())
+            }
+        }
+    }
+}
+
+/// Rows which can be read from either database
+pub trait FromDbRow:
+    for<'r> FromRow<'r, PgRow> + for<'r> FromRow<'r, SqliteRow> + Send + Unpin
+{
+}
+
+impl<T> FromDbRow for T where
+    T: for<'r> FromRow<'r, PgRow> + for<'r> FromRow<'r, SqliteRow> + Send + Unpin
+{
+}
+
+/// A query whose rows are read into `O`
+pub struct QueryAs<'q, O> {
+    query: Query<'q>,
+    output: PhantomData<O>,
+}
+
+/// Like `sqlx::query_as`, for whichever database is configured
+pub fn query_as<O: FromDbRow>(sql: &str) -> QueryAs<'_, O> {
+    QueryAs {
+        query: query(sql),
+        output: PhantomData,
+    }
+}
+
+impl<'q, O: FromDbRow> QueryAs<'q, O> {
+    pub fn bind(mut self, value: impl Param<'q> + 'q) -> Self {
+        self.query = self.query.bind(value);
+        self
+    }
+
+    fn postgres(&self) -> sqlx::query::QueryAs<'q, Postgres, O, PgArguments> {
+        sqlx::query_as_with(self.query.sql, self.query.postgres_arguments())
+    }
+
+    fn sqlite(&self) -> sqlx::query::QueryAs<'q, Sqlite, O, SqliteArguments<'q>> {
+        sqlx::query_as_with(self.query.sql, self.query.sqlite_arguments())
+    }
+
+    pub async fn fetch_one<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<O, sqlx::Error> {
+        run_on!(executor, self, fetch_one, std::convert::identity)
+    }
+
+    pub async fn fetch_optional<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<Option<O>, sqlx::Error> {
+        run_on!(executor, self, fetch_optional, std::convert::identity)
+    }
 
-    PgPool::connect_lazy_with(options)
+    pub async fn fetch_all<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<Vec<O>, sqlx::Error> {
+        run_on!(executor, self, fetch_all, std::convert::identity)
+    }
 }
 
-pub async fn initialize_database(pool: &PgPool) {
+/// A query which reads the first column of each row into `O`
+pub struct QueryScalar<'q, O> {
+    query: QueryAs<'q, (O,)>,
+}
+
+/// Like `sqlx::query_scalar`, for whichever database is configured
+pub fn query_scalar<O>(sql: &str) -> QueryScalar<'_, O>
+where
+    (O,): FromDbRow,
+{
+    QueryScalar {
+        query: query_as(sql),
+    }
+}
+
+impl<'q, O> QueryScalar<'q, O>
+where
+    (O,): FromDbRow,
+{
+    pub fn bind(mut self, value: impl Param<'q> + 'q) -> Self {
+        self.query = self.query.bind(value);
+        self
+    }
+
+    pub async fn fetch_one<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<O, sqlx::Error> {
+        Ok(self.query.fetch_one(executor).await?.0)
+    }
+
+    pub async fn fetch_optional<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<Option<O>, sqlx::Error> {
+        Ok(self
+            .query
+            .fetch_optional(executor)
+            .await?
+            .map(|(value,)| value))
+    }
+
+    pub async fn fetch_all<'e>(
+        self,
+        executor: impl Into<DbExecutor<'e>>,
+    ) -> Result<Vec<O>, sqlx::Error> {
+        let rows = self.query.fetch_all(executor).await?;
+        Ok(rows.into_iter().map(|(value,)| value).collect())
+    }
+}
+
+pub fn get_connection_pool(settings: &Settings) -> DbPool {
+    log::info!("Setting up connection pool");
+    let options = match connect_options(settings).expect("Failed to parse database url") {
+        DbConnectOptions::Postgres(mut options) => {
+            options.log_statements(log::LevelFilter::Debug);
+            DbConnectOptions::Postgres(options)
+        }
+        DbConnectOptions::Sqlite(mut options) => {
+            options.log_statements(log::LevelFilter::Debug);
+            DbConnectOptions::Sqlite(options)
+        }
+    };
+
+    DbPool::connect_lazy_with(options)
+}
+
+pub async fn initialize_database(pool: &DbPool) {
     log::info!("Migrating database");
-    sqlx::migrate!()
-        .run(pool)
-        .await
-        .expect("Failed to run migration");
+    let migrated = match pool {
+        DbPool::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await,
+        DbPool::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await,
+    };
+    migrated.expect("Failed to run migration");
     log::info!("Migration complete");
 }
+
+/// Error codes of a unique constraint violation on Postgres and on SQLite
+const UNIQUE_VIOLATIONS: [&str; 2] = ["23505", "2067"];
+
+pub fn is_unique_violation(error: &sqlx::Error) -> bool {
+    matches!(error, sqlx::Error::Database(e) if e.code().is_some_and(|code| UNIQUE_VIOLATIONS.contains(&code.as_ref())))
+}
+
+/// The scopes of an API token. They are stored as a space separated list, like
+/// the `scope` parameter of OAuth, since SQLite has no array type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScopeList(pub Vec<String>);
+
+impl From<ScopeList> for Vec<String> {
+    fn from(scopes: ScopeList) -> Self {
+        scopes.0
+    }
+}
+
+/// Stores a [`ScopeList`] as text on `$db`
+macro_rules! scope_list_as_text {
+    ($db:ty) => {
+        impl Type<$db> for ScopeList {
+            fn type_info() -> <$db as sqlx::Database>::TypeInfo {
+                <String as Type<$db>>::type_info()
+            }
+
+            fn compatible(ty: &<$db as sqlx::Database>::TypeInfo) -> bool {
+                <String as Type<$db>>::compatible(ty)
+            }
+        }
+
+        impl<'r> Decode<'r, $db> for ScopeList {
+            fn decode(
+                value: <$db as sqlx::database::HasValueRef<'r>>::ValueRef,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                let scopes = <&'r str as Decode<'r, $db>>::decode(value)?;
+                Ok(Self(scopes.split_whitespace().map(str::to_owned).collect()))
+            }
+        }
+
+        impl<'q> Encode<'q, $db> for ScopeList {
+            fn encode_by_ref(
+                &self,
+                buf: &mut <$db as sqlx::database::HasArguments<'q>>::ArgumentBuffer,
+            ) -> sqlx::encode::IsNull {
+                <String as Encode<'q, $db>>::encode(self.0.join(" "), buf)
+            }
+        }
+    };
+}
+
+scope_list_as_text!(Postgres);
+scope_list_as_text!(Sqlite);
+
+/// Selects documents together with the reading progress of the user bound to `$1`.
+/// Further conditions should be added by the caller using the `d` alias.
+pub const SELECT_DOCUMENTS: &str = "SELECT d.*, COALESCE(p.current_page, 1) AS current_page
+    FROM Documents d
+    LEFT JOIN ReadingProgress p ON p.document = d.id AND p.reader = $1";
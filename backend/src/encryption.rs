@@ -0,0 +1,589 @@
+//! Encryption of the files the server stores, the documents and the index.
+//!
+//! Every file gets its own random data key, which is stored at the start of the file
+//! wrapped by the server key (see [`SecretKey`]). Rotating the server key therefore
+//! only rewrites the headers. The contents follow as chunks of [`CHUNK_SIZE`] bytes,
+//! each sealed with AES-256-GCM on its own, so that a range of a document can be
+//! read without decrypting all of it.
+//!
+//! ```text
+//! MAGIC | wrapped data key | chunk 0 + tag | chunk 1 + tag | ... | last chunk + tag
+//! ```
+//!
+//! The nonce of a chunk is its index, and the last chunk is marked in its associated
+//! data, so that chunks cannot be reordered and the file cannot be cut short. Files
+//! without the magic bytes are plain, which is how files stored before encryption
+//! was enabled are still read.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use walkdir::WalkDir;
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::error::error_chain_fmt;
+use crate::secrets::{SecretError, SecretKey, KEY_LENGTH};
+
+const MAGIC: &[u8; 8] = b"PDFRENC1";
+/// Nonce, key and tag of the data key sealed with the server key
+const WRAPPED_KEY_LENGTH: u64 = 12 + KEY_LENGTH as u64 + TAG_LENGTH;
+const HEADER_LENGTH: u64 = MAGIC.len() as u64 + WRAPPED_KEY_LENGTH;
+/// Plaintext bytes in every chunk but the last
+pub const CHUNK_SIZE: u64 = 64 * 1024;
+const TAG_LENGTH: u64 = 16;
+const SEALED_CHUNK_SIZE: u64 = CHUNK_SIZE + TAG_LENGTH;
+/// Added to the name of a file while an encrypted copy of it is written
+const ENCRYPTING_EXTENSION: &str = "encrypting";
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Size of the contents of an encrypted file of `stored_length` bytes
+fn plaintext_length(stored_length: u64) -> io::Result<u64> {
+    let body = stored_length
+        .checked_sub(HEADER_LENGTH)
+        .filter(|body| *body >= TAG_LENGTH)
+        .ok_or_else(|| invalid_data("Encrypted file is truncated"))?;
+    let chunks = body.div_ceil(SEALED_CHUNK_SIZE);
+    let last = body - (chunks - 1) * SEALED_CHUNK_SIZE;
+    if last < TAG_LENGTH {
+        return Err(invalid_data("Encrypted file is truncated"));
+    }
+    Ok(body - chunks * TAG_LENGTH)
+}
+
+fn chunk_count(plaintext_length: u64) -> u64 {
+    plaintext_length.div_ceil(CHUNK_SIZE).max(1)
+}
+
+/// Whether `stored` is the contents of an encrypted file
+pub fn is_encrypted(stored: &[u8]) -> bool {
+    stored.starts_with(MAGIC)
+}
+
+/// Whether the file starts with the magic bytes of an encrypted file
+fn has_magic(reader: &mut impl Read) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    reader.take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+    Ok(magic == MAGIC)
+}
+
+/// The key the contents of a single file are encrypted with
+struct DataKey {
+    cipher: Aes256Gcm,
+}
+
+impl DataKey {
+    /// A new random key, and the header of a file encrypted with it
+    fn generate(key: &SecretKey) -> io::Result<(Self, Vec<u8>)> {
+        let data_key = Aes256Gcm::generate_key(&mut OsRng);
+        let wrapped = key.encrypt(&data_key).map_err(io::Error::other)?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&wrapped);
+        let cipher = Aes256Gcm::new(&data_key);
+        Ok((Self { cipher }, header))
+    }
+
+    /// Reads the data key from the header, which the reader is positioned at
+    fn read(key: &SecretKey, reader: &mut impl Read) -> io::Result<Self> {
+        let data_key = unwrap_data_key(key, &read_header(reader)?)?;
+        Ok(Self {
+            cipher: Aes256Gcm::new_from_slice(&data_key)
+                .map_err(|_| invalid_data("Invalid data key"))?,
+        })
+    }
+
+    fn nonce(index: u64) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&index.to_be_bytes());
+        nonce
+    }
+
+    fn seal(&self, index: u64, last: bool, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        let payload = Payload {
+            msg: chunk,
+            aad: &[last as u8],
+        };
+        self.cipher
+            .encrypt(Nonce::from_slice(&Self::nonce(index)), payload)
+            .map_err(|_| io::Error::other("Failed to encrypt"))
+    }
+
+    fn open(&self, index: u64, last: bool, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let payload = Payload {
+            msg: sealed,
+            aad: &[last as u8],
+        };
+        self.cipher
+            .decrypt(Nonce::from_slice(&Self::nonce(index)), payload)
+            .map_err(|_| invalid_data("Encrypted file is corrupt"))
+    }
+}
+
+fn read_header(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = vec![0; HEADER_LENGTH as usize];
+    reader.read_exact(&mut header)?;
+    if !header.starts_with(MAGIC) {
+        return Err(invalid_data("File is not encrypted"));
+    }
+    Ok(header)
+}
+
+fn unwrap_data_key(key: &SecretKey, header: &[u8]) -> io::Result<Vec<u8>> {
+    key.decrypt(&header[MAGIC.len()..])
+        .map_err(|_| invalid_data("File is encrypted with another key"))
+}
+
+/// Encrypts a stream of bytes, without doing any IO itself, so that it can be used
+/// from both blocking and async code. The header is returned by [`Self::new`].
+pub struct Encryptor {
+    key: DataKey,
+    buffer: Vec<u8>,
+    index: u64,
+}
+
+impl Encryptor {
+    pub fn new(key: &SecretKey) -> io::Result<(Self, Vec<u8>)> {
+        let (key, header) = DataKey::generate(key)?;
+        let encryptor = Self {
+            key,
+            buffer: Vec::with_capacity(CHUNK_SIZE as usize),
+            index: 0,
+        };
+        Ok((encryptor, header))
+    }
+
+    /// Adds `data`, and returns the chunks which are complete
+    pub fn update(&mut self, mut data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut sealed = Vec::new();
+        while !data.is_empty() {
+            // A full chunk is only sealed once more data follows, as the last
+            // chunk is sealed differently
+            if self.buffer.len() as u64 == CHUNK_SIZE {
+                sealed.extend(self.key.seal(self.index, false, &self.buffer)?);
+                self.buffer.clear();
+                self.index += 1;
+            }
+            let take = data.len().min(CHUNK_SIZE as usize - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+        Ok(sealed)
+    }
+
+    /// Returns the last chunk
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        self.key.seal(self.index, true, &self.buffer)
+    }
+}
+
+/// Writes a stored file, encrypting it when a key is given.
+/// [`Self::finish`] must be called once everything is written.
+pub struct StoredFileWriter<W: Write> {
+    inner: W,
+    encryptor: Option<Encryptor>,
+    finished: bool,
+}
+
+impl<W: Write> StoredFileWriter<W> {
+    pub fn new(key: Option<&SecretKey>, mut inner: W) -> io::Result<Self> {
+        let encryptor = match key {
+            Some(key) => {
+                let (encryptor, header) = Encryptor::new(key)?;
+                inner.write_all(&header)?;
+                Some(encryptor)
+            }
+            None => None,
+        };
+        Ok(Self {
+            inner,
+            encryptor,
+            finished: false,
+        })
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        if let Some(encryptor) = self.encryptor.take() {
+            self.inner.write_all(&encryptor.finish()?)?;
+        }
+        self.inner.flush()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for StoredFileWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::other("Stored file was already finished"));
+        }
+        match &mut self.encryptor {
+            Some(encryptor) => self.inner.write_all(&encryptor.update(data)?)?,
+            None => self.inner.write_all(data)?,
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the contents of an encrypted file, decrypting one chunk at a time
+pub struct DecryptingReader<R> {
+    inner: R,
+    key: DataKey,
+    size: u64,
+    position: u64,
+    chunk: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> DecryptingReader<R> {
+    pub fn new(key: &SecretKey, mut inner: R) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(0))?;
+        let data_key = DataKey::read(key, &mut inner)?;
+        let size = plaintext_length(inner.seek(SeekFrom::End(0))?)?;
+        Ok(Self {
+            inner,
+            key: data_key,
+            size,
+            position: 0,
+            chunk: None,
+        })
+    }
+
+    /// Size of the decrypted contents
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn load_chunk(&mut self, index: u64) -> io::Result<&[u8]> {
+        if !matches!(&self.chunk, Some((loaded, _)) if *loaded == index) {
+            let last = index + 1 == chunk_count(self.size);
+            let length = if last {
+                self.size - index * CHUNK_SIZE + TAG_LENGTH
+            } else {
+                SEALED_CHUNK_SIZE
+            };
+            let mut sealed = vec![0; length as usize];
+            self.inner
+                .seek(SeekFrom::Start(HEADER_LENGTH + index * SEALED_CHUNK_SIZE))?;
+            self.inner.read_exact(&mut sealed)?;
+            self.chunk = Some((index, self.key.open(index, last, &sealed)?));
+        }
+        Ok(self
+            .chunk
+            .as_ref()
+            .map(|(_, chunk)| chunk.as_slice())
+            .unwrap_or_default())
+    }
+}
+
+impl<R: Read + Seek> Read for DecryptingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buffer.is_empty() {
+            return Ok(0);
+        }
+        let position = self.position;
+        let chunk = self.load_chunk(position / CHUNK_SIZE)?;
+        let start = (position % CHUNK_SIZE) as usize;
+        let read = buffer.len().min(chunk.len() - start);
+        buffer[..read].copy_from_slice(&chunk[start..start + read]);
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptingReader<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position =
+            position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek"))?;
+        Ok(self.position)
+    }
+}
+
+/// A stored file opened for reading, which is decrypted when it is encrypted
+pub enum StoredFile {
+    Plain(File),
+    Encrypted(Box<DecryptingReader<File>>),
+}
+
+impl StoredFile {
+    /// Size of the contents, which is less than the size on disk when encrypted
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            Self::Plain(file) => Ok(file.metadata()?.len()),
+            Self::Encrypted(reader) => Ok(reader.size()),
+        }
+    }
+}
+
+impl Read for StoredFile {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.read(buffer),
+            Self::Encrypted(reader) => reader.read(buffer),
+        }
+    }
+}
+
+impl Seek for StoredFile {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Plain(file) => file.seek(position),
+            Self::Encrypted(reader) => reader.seek(position),
+        }
+    }
+}
+
+/// The key stored files are read with, and whether files are encrypted when they
+/// are written
+#[derive(Clone, Default)]
+pub struct FileEncryption {
+    key: Option<SecretKey>,
+    encrypt: bool,
+}
+
+impl FileEncryption {
+    pub fn new(key: Option<SecretKey>, encrypt: bool) -> Result<Self, SecretError> {
+        if encrypt && key.is_none() {
+            return Err(SecretError::MissingKey);
+        }
+        Ok(Self { key, encrypt })
+    }
+
+    /// Encrypts with the server key when `encrypt_storage` is set
+    pub fn load(config: &Settings) -> Result<Self, SecretError> {
+        Self::new(SecretKey::load(config)?, config.encrypt_storage)
+    }
+
+    /// The server key, which is also used for other secrets
+    pub fn key(&self) -> Option<&SecretKey> {
+        self.key.as_ref()
+    }
+
+    /// The key new files are encrypted with, if they are encrypted
+    pub fn write_key(&self) -> Option<&SecretKey> {
+        self.key.as_ref().filter(|_| self.encrypt)
+    }
+
+    fn read_key(&self) -> io::Result<&SecretKey> {
+        self.key
+            .as_ref()
+            .ok_or_else(|| invalid_data("File is encrypted, but no server key is configured"))
+    }
+
+    pub fn open(&self, path: &Path) -> io::Result<StoredFile> {
+        let mut file = File::open(path)?;
+        let encrypted = has_magic(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+        if !encrypted {
+            return Ok(StoredFile::Plain(file));
+        }
+        Ok(StoredFile::Encrypted(Box::new(DecryptingReader::new(
+            self.read_key()?,
+            file,
+        )?)))
+    }
+
+    /// Decrypts the contents of an encrypted file which were read into memory
+    pub fn decrypt(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = DecryptingReader::new(self.read_key()?, io::Cursor::new(stored))?;
+        let mut contents = Vec::with_capacity(reader.size() as usize);
+        reader.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Reads all of the contents of a stored file
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let mut contents = Vec::with_capacity(file.size()? as usize);
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    pub fn create(&self, path: &Path) -> io::Result<StoredFileWriter<File>> {
+        StoredFileWriter::new(self.write_key(), File::create(path)?)
+    }
+
+    /// Copies a plain file into storage, and returns the number of bytes copied
+    pub fn store(&self, source: &Path, destination: &Path) -> io::Result<u64> {
+        let mut writer = self.create(destination)?;
+        let copied = io::copy(&mut File::open(source)?, &mut writer)?;
+        writer.finish()?;
+        writer.inner_mut().sync_all()?;
+        Ok(copied)
+    }
+}
+
+/// What was done to the files and secrets of the library by [`rotate_key`]
+#[derive(Debug, Default)]
+pub struct KeyRotation {
+    /// Files whose data key was wrapped with the new key
+    pub rewrapped: usize,
+    /// Plain files which were encrypted
+    pub encrypted: usize,
+    pub passwords: usize,
+}
+
+/// Moves the library from the configured server key to `new_key`. The data keys of
+/// encrypted files are wrapped with the new key, and the kept document passwords
+/// are encrypted with it. When `encrypt_storage` is set, files stored before it was
+/// set are encrypted as well. The server must not be running.
+///
+/// Files and passwords which already use the new key are left alone, so the
+/// rotation can be run again when it was interrupted.
+pub async fn rotate_key(
+    pool: &DbPool,
+    config: &Settings,
+    new_key: &SecretKey,
+) -> Result<KeyRotation, KeyRotationError> {
+    let old_key = SecretKey::load(config)?;
+    let mut rotation = KeyRotation::default();
+
+    let mut files = Vec::new();
+    for directory in [
+        config.documents_storage_path(),
+        config.documents_contents_path(),
+    ] {
+        for entry in WalkDir::new(directory) {
+            let entry = entry.map_err(io::Error::from)?;
+            // Lock files of the index are empty, and removed when it is closed
+            let skipped = entry
+                .path()
+                .extension()
+                .is_some_and(|e| e == "lock" || e == ENCRYPTING_EXTENSION);
+            if entry.file_type().is_file() && !skipped {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    for file in files {
+        match rewrap_file(old_key.as_ref(), new_key, &file)? {
+            Rewrap::Rewrapped => rotation.rewrapped += 1,
+            Rewrap::Current => {}
+            Rewrap::Plain if config.encrypt_storage => {
+                encrypt_file(new_key, &file)?;
+                rotation.encrypted += 1;
+            }
+            Rewrap::Plain => {}
+        }
+    }
+
+    let passwords: Vec<(uuid::Uuid, String)> = database::query_as(
+        "SELECT id, encrypted_password FROM Documents WHERE encrypted_password IS NOT NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut tx = pool.begin().await?;
+    for (document, encrypted) in passwords {
+        if new_key.decrypt_string(&encrypted).is_ok() {
+            continue;
+        }
+        let password = old_key
+            .as_ref()
+            .ok_or(SecretError::MissingKey)?
+            .decrypt_string(&encrypted)?;
+        database::query("UPDATE Documents SET encrypted_password = $1 WHERE id = $2")
+            .bind(new_key.encrypt_string(&password)?)
+            .bind(document)
+            .execute(&mut tx)
+            .await?;
+        rotation.passwords += 1;
+    }
+    tx.commit().await?;
+
+    log::info!(
+        "Rotated the server key: {} files rewrapped, {} files encrypted, {} passwords",
+        rotation.rewrapped,
+        rotation.encrypted,
+        rotation.passwords
+    );
+    Ok(rotation)
+}
+
+enum Rewrap {
+    Rewrapped,
+    /// The file already uses the new key
+    Current,
+    Plain,
+}
+
+/// Wraps the data key of an encrypted file with the new key. Only the header is
+/// rewritten, the contents stay as they are.
+fn rewrap_file(
+    old_key: Option<&SecretKey>,
+    new_key: &SecretKey,
+    path: &Path,
+) -> Result<Rewrap, KeyRotationError> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    if !has_magic(&mut file)? {
+        return Ok(Rewrap::Plain);
+    }
+    file.seek(SeekFrom::Start(0))?;
+    let header = read_header(&mut file)?;
+    if unwrap_data_key(new_key, &header).is_ok() {
+        return Ok(Rewrap::Current);
+    }
+    let old_key = old_key.ok_or(SecretError::MissingKey)?;
+    let data_key = unwrap_data_key(old_key, &header)
+        .map_err(|_| KeyRotationError::UnknownKey(path.to_owned()))?;
+
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&new_key.encrypt(&data_key)?);
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.sync_data()?;
+    Ok(Rewrap::Rewrapped)
+}
+
+/// Replaces a plain file with an encrypted copy
+fn encrypt_file(key: &SecretKey, path: &Path) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(ENCRYPTING_EXTENSION);
+    let encrypted = path.with_file_name(name);
+    let result = FileEncryption::new(Some(key.clone()), true)
+        .map_err(io::Error::other)
+        .and_then(|encryption| encryption.store(path, &encrypted))
+        .and_then(|_| std::fs::rename(&encrypted, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&encrypted);
+    }
+    result
+}
+
+#[derive(thiserror::Error)]
+pub enum KeyRotationError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+    #[error("{0} is encrypted with neither the configured key nor the new one")]
+    UnknownKey(PathBuf),
+}
+
+impl std::fmt::Debug for KeyRotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use lopdf::{dictionary, Object, ObjectId, StringFormat};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::error_chain_fmt;
+use crate::indexer::{Indexer, IndexerError};
+use crate::models::{
+    Bookmark, Document, ExportedBookmark, ExportedDocument, Highlight, HighlightArea,
+};
+
+/// Longest snippet of page text included with a bookmark, in characters
+pub const MAX_SNIPPET_LENGTH: usize = 300;
+
+#[derive(Deserialize, Serialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// Groups bookmarks by document, in the order of `documents`, with the text of each
+/// bookmarked page taken from the index. Documents without bookmarks are left out.
+pub fn collect_bookmarks(
+    indexer: &Indexer,
+    documents: Vec<Document>,
+    bookmarks: Vec<Bookmark>,
+) -> Result<Vec<ExportedDocument>, IndexerError> {
+    let mut bookmarks_by_document: HashMap<_, Vec<Bookmark>> = HashMap::new();
+    for bookmark in bookmarks {
+        bookmarks_by_document
+            .entry(bookmark.document)
+            .or_default()
+            .push(bookmark);
+    }
+
+    let mut exported = Vec::new();
+    for document in documents {
+        let Some(mut bookmarks) = bookmarks_by_document.remove(&document.id) else {
+            continue;
+        };
+        bookmarks.sort_by_key(|b| (b.page, b.added_on));
+        let pages: HashMap<u64, String> =
+            indexer.document_pages(&document.id)?.into_iter().collect();
+        let bookmarks = bookmarks
+            .into_iter()
+            .map(|b| ExportedBookmark {
+                snippet: u64::try_from(b.page)
+                    .ok()
+                    .and_then(|page| pages.get(&page))
+                    .and_then(|text| snippet(text)),
+                page: b.page,
+                description: b.description,
+                added_on: b.added_on,
+            })
+            .collect();
+        exported.push(ExportedDocument {
+            id: document.id,
+            title: document.title.unwrap_or_else(|| document.name.clone()),
+            name: document.name,
+            bookmarks,
+        });
+    }
+    Ok(exported)
+}
+
+/// The start of the text of a page, on a single line
+fn snippet(text: &str) -> Option<String> {
+    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if words.is_empty() {
+        return None;
+    }
+    match words.char_indices().nth(MAX_SNIPPET_LENGTH) {
+        Some((end, _)) => Some(format!("{}…", words[..end].trim_end())),
+        None => Some(words),
+    }
+}
+
+pub fn render(documents: &[ExportedDocument], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Markdown => render_markdown(documents),
+        // Serializing these types cannot fail
+        ExportFormat::Json => serde_json::to_string_pretty(documents).unwrap_or_default(),
+        ExportFormat::Csv => render_csv(documents),
+    }
+}
+
+fn render_markdown(documents: &[ExportedDocument]) -> String {
+    let mut markdown = String::from("# Bookmarks\n");
+    for document in documents {
+        let _ = write!(markdown, "\n## {}\n", document.title);
+        for bookmark in &document.bookmarks {
+            let _ = write!(
+                markdown,
+                "\n### Page {}: {}\n\n*Added {}*\n",
+                bookmark.page,
+                // A heading ends at the end of the line
+                bookmark.description.replace(['\r', '\n'], " "),
+                bookmark.added_on.format("%Y-%m-%d %H:%M UTC")
+            );
+            if let Some(snippet) = &bookmark.snippet {
+                let _ = write!(markdown, "\n> {snippet}\n");
+            }
+        }
+    }
+    markdown
+}
+
+fn render_csv(documents: &[ExportedDocument]) -> String {
+    let mut csv = String::from("document_id,title,page,description,added_on,snippet\r\n");
+    for document in documents {
+        for bookmark in &document.bookmarks {
+            let fields = [
+                document.id.to_string(),
+                document.title.clone(),
+                bookmark.page.to_string(),
+                bookmark.description.clone(),
+                bookmark.added_on.to_rfc3339(),
+                bookmark.snippet.clone().unwrap_or_default(),
+            ];
+            let line = fields
+                .iter()
+                .map(|f| csv_field(f))
+                .collect::<Vec<_>>()
+                .join(",");
+            csv.push_str(&line);
+            csv.push_str("\r\n");
+        }
+    }
+    csv
+}
+
+/// Quotes a field when it contains a separator, a quote or a line break. Fields
+/// which a spreadsheet would take for a formula, including ones starting with a
+/// tab or a carriage return, are prefixed with a `'`, so that opening an export
+/// does not run what someone typed in a bookmark.
+fn csv_field(field: &str) -> String {
+    let field = match field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        true => format!("'{field}"),
+        false => field.to_owned(),
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Adds the bookmarks to the outline of a PDF, after the entries it already has, and
+/// the highlights as annotations of their pages, so that desktop readers show them.
+/// Notes of pages the PDF does not have are left out. Documents protected by a
+/// password are refused, as they would have to be written back without their
+/// protection.
+pub fn add_notes(
+    pdf: &[u8],
+    bookmarks: &[Bookmark],
+    highlights: &[Highlight],
+) -> Result<Vec<u8>, OutlineError> {
+    let mut document = lopdf::Document::load_mem(pdf)?;
+    if document.is_encrypted() {
+        return Err(OutlineError::Protected);
+    }
+    let pages = document.get_pages();
+    add_outline_entries(&mut document, &pages, bookmarks)?;
+    for highlight in highlights {
+        if let Some(page) = u32::try_from(highlight.page)
+            .ok()
+            .and_then(|p| pages.get(&p))
+        {
+            add_highlight(&mut document, *page, highlight)?;
+        }
+    }
+
+    let mut output = Vec::with_capacity(pdf.len());
+    document.save_to(&mut output).map_err(lopdf::Error::from)?;
+    Ok(output)
+}
+
+fn add_outline_entries(
+    document: &mut lopdf::Document,
+    pages: &BTreeMap<u32, ObjectId>,
+    bookmarks: &[Bookmark],
+) -> Result<(), lopdf::Error> {
+    for bookmark in bookmarks {
+        let Some(page) = u32::try_from(bookmark.page)
+            .ok()
+            .and_then(|p| pages.get(&p))
+        else {
+            continue;
+        };
+        let title = format!("p. {}: {}", bookmark.page, bookmark.description);
+        document.add_bookmark(lopdf::Bookmark::new(title, [0.0, 0.0, 0.0], 0, *page), None);
+    }
+
+    if let Some(outline) = document.build_outline() {
+        let catalog = document.trailer.get(b"Root")?.as_reference()?;
+        let existing = document
+            .get_dictionary(catalog)?
+            .get(b"Outlines")
+            .and_then(Object::as_reference);
+        match existing {
+            Ok(existing) => append_outline(document, existing, outline)?,
+            // An outline stored directly in the catalog is replaced, which no
+            // writer we know of does
+            Err(_) => document
+                .get_object_mut(catalog)?
+                .as_dict_mut()?
+                .set("Outlines", Object::Reference(outline)),
+        }
+    }
+    Ok(())
+}
+
+/// Moves the top level entries of `added` to the end of the `existing` outline
+fn append_outline(
+    document: &mut lopdf::Document,
+    existing: ObjectId,
+    added: ObjectId,
+) -> Result<(), lopdf::Error> {
+    let (first, last, count) = {
+        let added = document.get_dictionary(added)?;
+        (
+            added.get(b"First")?.as_reference()?,
+            added.get(b"Last")?.as_reference()?,
+            added.get(b"Count").and_then(Object::as_i64).unwrap_or(0),
+        )
+    };
+
+    let mut entry = Some(first);
+    while let Some(id) = entry {
+        let dictionary = document.get_object_mut(id)?.as_dict_mut()?;
+        dictionary.set("Parent", Object::Reference(existing));
+        entry = dictionary.get(b"Next").and_then(Object::as_reference).ok();
+    }
+
+    let root = document.get_object_mut(existing)?.as_dict_mut()?;
+    let previous_last = root.get(b"Last").and_then(Object::as_reference).ok();
+    let previous_count = root.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+    root.set("Last", Object::Reference(last));
+    root.set("Count", previous_count.abs() + count);
+    match previous_last {
+        Some(previous_last) => {
+            document
+                .get_object_mut(previous_last)?
+                .as_dict_mut()?
+                .set("Next", Object::Reference(first));
+            document
+                .get_object_mut(first)?
+                .as_dict_mut()?
+                .set("Prev", Object::Reference(previous_last));
+        }
+        None => root.set("First", Object::Reference(first)),
+    }
+
+    document.objects.remove(&added);
+    Ok(())
+}
+
+/// Adds a yellow highlight annotation over the area of a page, with the description
+/// as its text
+fn add_highlight(
+    document: &mut lopdf::Document,
+    page: ObjectId,
+    highlight: &Highlight,
+) -> Result<(), lopdf::Error> {
+    let HighlightArea {
+        left,
+        bottom,
+        right,
+        top,
+    } = highlight.area;
+    let annotation = document.add_object(dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Highlight",
+        "Rect" => vec![left.into(), bottom.into(), right.into(), top.into()],
+        "QuadPoints" => [left, top, right, top, left, bottom, right, bottom]
+            .into_iter()
+            .map(Object::Real)
+            .collect::<Vec<_>>(),
+        "Contents" => text_string(&highlight.description),
+        "C" => vec![1.into(), 1.into(), 0.into()],
+        // Printed along with the page
+        "F" => 4,
+        "P" => page,
+    });
+
+    let page = document.get_object_mut(page)?.as_dict_mut()?;
+    match page.get_mut(b"Annots") {
+        Ok(Object::Array(annotations)) => annotations.push(Object::Reference(annotation)),
+        Ok(Object::Reference(annotations)) => {
+            let annotations = *annotations;
+            document
+                .get_object_mut(annotations)?
+                .as_array_mut()?
+                .push(Object::Reference(annotation));
+        }
+        _ => page.set("Annots", vec![Object::Reference(annotation)]),
+    }
+    Ok(())
+}
+
+/// Encodes a text string of a PDF, as UTF-16 unless it is plain ASCII
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut encoded = vec![0xfe, 0xff];
+    encoded.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(encoded, StringFormat::Hexadecimal)
+}
+
+#[derive(thiserror::Error)]
+pub enum OutlineError {
+    #[error("Notes cannot be added to a document protected by a password")]
+    Protected,
+    #[error("Failed to edit the document")]
+    Pdf(#[from] lopdf::Error),
+}
+
+impl std::fmt::Debug for OutlineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use actix_web::web;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::Pdfium;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::encryption::FileEncryption;
+use crate::error::{error_chain_fmt, ApiError};
+use crate::indexer::Indexer;
+use crate::metrics::METRICS;
+use crate::routes::documents::post::{
+    extract_annotations, extract_pages, index_pages, insert_imported_bookmarks, open_pdf,
+    AddDocumentError, AnnotationImport, PdfFile,
+};
+use crate::secrets::SecretError;
+use crate::startup::{bind_pdfium, PdfiumUnavailable};
+use crate::usage::check_quota;
+
+/// What happened to each file of an import
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Every PDF which was found, ordered by path
+    pub files: Vec<ImportedFile>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImportedFile {
+    /// Path relative to the imported directory
+    pub path: String,
+    pub outcome: ImportOutcome,
+    /// The new document, or the existing one when the file was skipped as a duplicate
+    pub document: Option<Uuid>,
+    /// Why the file was skipped or failed
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOutcome {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+impl ImportOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Imported => "imported",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for ImportOutcome {
+    type Error = String;
+
+    fn try_from(outcome: String) -> Result<Self, Self::Error> {
+        [Self::Imported, Self::Skipped, Self::Failed]
+            .into_iter()
+            .find(|o| o.as_str() == outcome)
+            .ok_or_else(|| format!("Unknown import outcome {outcome}"))
+    }
+}
+
+impl ImportedFile {
+    fn new(path: String, outcome: ImportOutcome) -> Self {
+        Self {
+            path,
+            outcome,
+            document: None,
+            reason: None,
+        }
+    }
+}
+
+/// Number of files imported at the same time when not configured
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+/// Shared by the files of one import
+struct ImportContext<'a> {
+    pool: &'a DbPool,
+    config: &'a Settings,
+    indexer: &'a Indexer,
+    pdfium: &'static Pdfium,
+    owner: Uuid,
+    /// Notes in the files to add as bookmarks
+    import: AnnotationImport,
+    /// Whether files which would go over the quotas of the owner are refused
+    enforce_quota: bool,
+}
+
+/// A PDF within the imported directory
+struct FoundFile {
+    path: PathBuf,
+    /// Content hash and size, or why the file could not be read
+    contents: io::Result<(String, u64)>,
+    /// Whether the file is a copy of one found before it
+    is_copy: bool,
+}
+
+/// Adds every PDF within `root` and its subdirectories to the library of `owner`.
+/// The folder of a file relative to `root` becomes the collection of its document.
+/// Files which the owner already has are skipped, and up to `jobs` files are read
+/// and indexed at the same time.
+pub async fn import_directory(
+    pool: &DbPool,
+    config: &Settings,
+    indexer: &Indexer,
+    pdfium: &'static Lazy<Pdfium>,
+    root: &Path,
+    owner: Uuid,
+    jobs: usize,
+) -> Result<ImportReport, ImportError> {
+    if !root.is_dir() {
+        return Err(ImportError::NotADirectory(root.to_owned()));
+    }
+    let pdfium = bind_pdfium(pdfium)?;
+    log::info!("Importing documents from {}", root.display());
+
+    let walk_root = root.to_owned();
+    let files = web::block(move || find_pdf_files(&walk_root))
+        .await
+        .map_err(io::Error::other)??;
+    log::info!("Found {} PDF files to import", files.len());
+
+    let context = ImportContext {
+        pool,
+        config,
+        indexer,
+        pdfium,
+        owner,
+        import: AnnotationImport::default(),
+        enforce_quota: false,
+    };
+    let mut results = futures::stream::iter(files)
+        .map(|file| import_file(&context, root, file))
+        .buffer_unordered(jobs.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let count = |outcome| results.iter().filter(|f| f.outcome == outcome).count();
+    let report = ImportReport {
+        imported: count(ImportOutcome::Imported),
+        skipped: count(ImportOutcome::Skipped),
+        failed: count(ImportOutcome::Failed),
+        files: results,
+    };
+    log::info!(
+        "Import of {} complete: {} imported, {} skipped, {} failed",
+        root.display(),
+        report.imported,
+        report.skipped,
+        report.failed
+    );
+    Ok(report)
+}
+
+/// Adds a single file to the library of `owner`, unless the owner already has it,
+/// the way an upload would: the quotas of the owner are enforced, and the notes
+/// asked for are added as bookmarks. The document is not put in a collection.
+pub async fn import_single_file(
+    pool: &DbPool,
+    config: &Settings,
+    indexer: &Indexer,
+    pdfium: &'static Pdfium,
+    path: &Path,
+    owner: Uuid,
+    import: AnnotationImport,
+) -> ImportedFile {
+    let context = ImportContext {
+        pool,
+        config,
+        indexer,
+        pdfium,
+        owner,
+        import,
+        enforce_quota: true,
+    };
+    let hash_path = path.to_owned();
+    let contents = web::block(move || hash_file(&hash_path))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)));
+    let file = FoundFile {
+        path: path.to_owned(),
+        contents,
+        is_copy: false,
+    };
+    import_file(&context, path.parent().unwrap_or(path), file).await
+}
+
+/// The account named `username`, or the first administrator when there is no name
+pub async fn find_owner(
+    pool: &DbPool,
+    username: Option<&str>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let owner: Option<(Uuid,)> = match username {
+        Some(username) => {
+            database::query_as("SELECT id FROM Users WHERE username = $1")
+                .bind(username)
+                .fetch_optional(pool)
+                .await?
+        }
+        None => {
+            database::query_as("SELECT id FROM Users WHERE is_admin ORDER BY added_on LIMIT 1")
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+    Ok(owner.map(|(id,)| id))
+}
+
+/// Finds every PDF in order of their paths, and hashes them, so that of the
+/// identical files the first one is imported no matter how the imports interleave
+fn find_pdf_files(root: &Path) -> io::Result<Vec<FoundFile>> {
+    let mut files = Vec::new();
+    let mut hashes = HashSet::new();
+    for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+        let entry = entry?;
+        let is_pdf = entry
+            .path()
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+        if !entry.file_type().is_file() || !is_pdf {
+            continue;
+        }
+        let contents = hash_file(entry.path());
+        let is_copy = match &contents {
+            Ok((hash, _)) => !hashes.insert(hash.clone()),
+            Err(_) => false,
+        };
+        files.push(FoundFile {
+            path: entry.into_path(),
+            contents,
+            is_copy,
+        });
+    }
+    Ok(files)
+}
+
+#[tracing::instrument(skip_all, fields(path = %file.path.display()))]
+async fn import_file(context: &ImportContext<'_>, root: &Path, file: FoundFile) -> ImportedFile {
+    let relative = file.path.strip_prefix(root).unwrap_or(&file.path);
+    let display_path = relative.to_string_lossy().into_owned();
+    let mut result = ImportedFile::new(display_path.clone(), ImportOutcome::Failed);
+
+    let imported = match file.contents {
+        Ok(_) if file.is_copy => Ok(Imported::Duplicate(None)),
+        Ok((content_hash, byte_size)) => {
+            try_import_file(context, relative, &file.path, content_hash, byte_size).await
+        }
+        Err(e) => Err(e.into()),
+    };
+    match imported {
+        Ok(Imported::New(id)) => {
+            log::info!("Imported {} as document {}", display_path, id);
+            result.outcome = ImportOutcome::Imported;
+            result.document = Some(id);
+        }
+        Ok(Imported::Duplicate(existing)) => {
+            let reason = match existing {
+                Some(_) => "The document is already in the library",
+                None => "The same file was found elsewhere in the directory",
+            };
+            log::info!("Skipped {}: {}", display_path, reason);
+            result.outcome = ImportOutcome::Skipped;
+            result.document = existing;
+            result.reason = Some(reason.to_owned());
+        }
+        Err(e) => {
+            log::warn!("Failed to import {}: {:?}", display_path, e);
+            result.reason = Some(e.to_string());
+        }
+    }
+    result
+}
+
+enum Imported {
+    New(Uuid),
+    /// The file is the same as an existing document, or as another file of the import
+    Duplicate(Option<Uuid>),
+}
+
+async fn try_import_file(
+    context: &ImportContext<'_>,
+    relative: &Path,
+    path: &Path,
+    content_hash: String,
+    byte_size: u64,
+) -> Result<Imported, ImportError> {
+    let name = relative
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let collection = relative
+        .parent()
+        .map(|folder| {
+            folder
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .filter(|folder| !folder.is_empty());
+
+    let existing: Option<(Uuid,)> =
+        database::query_as("SELECT id FROM Documents WHERE owner = $1 AND content_hash = $2")
+            .bind(context.owner)
+            .bind(&content_hash)
+            .fetch_optional(context.pool)
+            .await?;
+    if let Some((existing,)) = existing {
+        return Ok(Imported::Duplicate(Some(existing)));
+    }
+
+    let id = Uuid::new_v4();
+    let destination = context
+        .config
+        .documents_storage_path()
+        .join(id.to_string())
+        .with_extension("pdf");
+    let document = NewDocument {
+        name,
+        collection,
+        content_hash,
+        byte_size,
+    };
+    let result = add_document(context, id, path, &destination, &document).await;
+    if result.is_err() {
+        if let Err(e) = std::fs::remove_file(&destination) {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::error!("Failed to delete {}: {}", destination.display(), e);
+            }
+        }
+    }
+    result?;
+
+    METRICS.upload_bytes.inc_by(byte_size);
+    Ok(Imported::New(id))
+}
+
+/// What is stored about a file once it has been imported
+struct NewDocument {
+    name: String,
+    /// Folder of the file relative to the imported directory, if not at its root
+    collection: Option<String>,
+    content_hash: String,
+    byte_size: u64,
+}
+
+/// Copies the file into storage, encrypting it when enabled, and reads its text, and
+/// only then adds it to the database and the index, so that nothing is left behind
+/// when the file is broken
+async fn add_document(
+    context: &ImportContext<'_>,
+    id: Uuid,
+    source: &Path,
+    destination: &Path,
+    document: &NewDocument,
+) -> Result<(), ImportError> {
+    let pdfium = context.pdfium;
+    let import = context.import;
+    let encryption = FileEncryption::load(context.config)?;
+    let (source, stored) = (source.to_owned(), destination.to_owned());
+    let (pages, annotations) = web::block(move || {
+        encryption.store(&source, &stored)?;
+        let file = PdfFile {
+            path: &stored,
+            encryption: &encryption,
+            password: None,
+        };
+        let pdf = open_pdf(pdfium, &file)?;
+        Ok::<_, ImportError>((extract_pages(&pdf)?, extract_annotations(&pdf, import)))
+    })
+    .await
+    .map_err(io::Error::other)??;
+
+    let mut tx = context.pool.begin().await?;
+    database::query(
+        "INSERT INTO Documents (id, name, owner, content_hash, collection, page_count, byte_size)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(id)
+    .bind(&document.name)
+    .bind(context.owner)
+    .bind(&document.content_hash)
+    .bind(&document.collection)
+    .bind(pages.len() as i32)
+    .bind(document.byte_size as i64)
+    .execute(&mut tx)
+    .await?;
+    if context.enforce_quota {
+        check_quota(&mut tx, context.config, context.owner)
+            .await
+            .map_err(AddDocumentError::from)?;
+    }
+    insert_imported_bookmarks(id, &annotations, context.owner, &mut tx).await?;
+    tx.commit().await?;
+
+    if let Err(e) = index_pages(context.indexer, &id, &pages).await {
+        database::query("DELETE FROM Documents WHERE id = $1")
+            .bind(id)
+            .execute(context.pool)
+            .await?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let byte_size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((hex::encode(hasher.finalize()), byte_size))
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{} is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Document(#[from] AddDocumentError),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+    #[error(transparent)]
+    Pdfium(#[from] PdfiumUnavailable),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<ImportError> for ApiError {
+    fn from(e: ImportError) -> Self {
+        match e {
+            ImportError::NotADirectory(_) => ApiError::invalid_field("path", e.to_string()),
+            ImportError::Pdfium(e) => e.into(),
+            e => ApiError::internal("Failed to import documents", e),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use actix_web::web;
+use chrono::Utc;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::Pdfium;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::import::{find_owner, import_single_file, ImportOutcome, ImportedFile};
+use crate::indexer::Indexer;
+use crate::routes::documents::post::AnnotationImport;
+use crate::shutdown::Shutdown;
+use crate::startup::bind_pdfium;
+
+/// Subdirectories of the inbox which handled files are moved to
+pub const PROCESSED_DIRECTORY: &str = "processed";
+pub const FAILED_DIRECTORY: &str = "failed";
+
+/// How often the files waiting in the inbox are checked for having stopped growing
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the inbox directory, and adds the PDFs which appear in it to the library
+/// of the inbox owner the same way an upload would. Files which were put in the
+/// inbox while the server was not running are added when it starts.
+pub struct Inbox {
+    watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl Inbox {
+    /// Starts watching the configured inbox, if there is one
+    pub fn start(
+        config: &Settings,
+        pool: DbPool,
+        indexer: web::Data<Indexer>,
+        pdfium: &'static Lazy<Pdfium>,
+        shutdown: Arc<Shutdown>,
+    ) -> io::Result<Option<Self>> {
+        let Some(location) = config.inbox_location.clone() else {
+            return Ok(None);
+        };
+        std::fs::create_dir_all(location.join(PROCESSED_DIRECTORY))?;
+        std::fs::create_dir_all(location.join(FAILED_DIRECTORY))?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            match event {
+                Ok(event) => {
+                    for path in event.paths {
+                        // Only fails once the inbox has stopped
+                        let _ = sender.send(path);
+                    }
+                }
+                Err(e) => log::error!("Failed to watch the inbox: {}", e),
+            }
+        })
+        .map_err(io::Error::other)?;
+        watcher
+            .watch(&location, RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)?;
+        log::info!("Watching {} for new documents", location.display());
+
+        let worker = Worker {
+            settle_time: Duration::from_secs(config.inbox_settle_seconds),
+            owner: config.inbox_owner.clone(),
+            config: config.clone(),
+            location,
+            pool,
+            indexer,
+            pdfium,
+            shutdown,
+            pending: HashMap::new(),
+        };
+        let task = tokio::spawn(worker.run(receiver));
+        Ok(Some(Self { watcher, task }))
+    }
+
+    /// Waits for the inbox to stop after a shutdown was requested. A file which is
+    /// being added is finished first.
+    pub async fn stopped(self) {
+        drop(self.watcher);
+        if let Err(e) = self.task.await {
+            log::error!("The inbox stopped unexpectedly: {}", e);
+        }
+    }
+}
+
+/// Size and modification time of a file the last time it was checked
+type FileState = (u64, Option<SystemTime>);
+
+struct PendingFile {
+    state: Option<FileState>,
+    unchanged_since: Instant,
+}
+
+struct Worker {
+    location: PathBuf,
+    owner: Option<String>,
+    settle_time: Duration,
+    config: Settings,
+    pool: DbPool,
+    indexer: web::Data<Indexer>,
+    /// Only bound once the first file is imported
+    pdfium: &'static Lazy<Pdfium>,
+    shutdown: Arc<Shutdown>,
+    pending: HashMap<PathBuf, PendingFile>,
+}
+
+impl Worker {
+    async fn run(mut self, mut events: UnboundedReceiver<PathBuf>) {
+        match std::fs::read_dir(&self.location) {
+            Ok(entries) => {
+                for entry in entries.filter_map(Result::ok) {
+                    self.track(entry.path());
+                }
+            }
+            Err(e) => log::error!("Failed to read {}: {}", self.location.display(), e),
+        }
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        while !self.shutdown.is_requested() {
+            tokio::select! {
+                Some(path) = events.recv() => self.track(path),
+                _ = interval.tick() => self.add_settled_files().await,
+            }
+        }
+        log::info!("Stopped watching the inbox");
+    }
+
+    /// Starts waiting for `path` to stop growing, if it is a PDF in the inbox
+    fn track(&mut self, path: PathBuf) {
+        let is_pdf = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+        if !is_pdf || path.parent() != Some(self.location.as_path()) || !path.is_file() {
+            return;
+        }
+        self.pending.entry(path).or_insert_with(|| PendingFile {
+            state: None,
+            unchanged_since: Instant::now(),
+        });
+    }
+
+    async fn add_settled_files(&mut self) {
+        let mut settled = Vec::new();
+        self.pending.retain(|path, pending| {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                // Removed or moved away before it settled
+                return false;
+            };
+            let state = Some((metadata.len(), metadata.modified().ok()));
+            if state != pending.state {
+                pending.state = state;
+                pending.unchanged_since = Instant::now();
+                return true;
+            }
+            if pending.unchanged_since.elapsed() < self.settle_time {
+                return true;
+            }
+            settled.push(path.clone());
+            false
+        });
+
+        settled.sort();
+        for path in settled {
+            if self.shutdown.is_requested() {
+                // Left in the inbox, to be added when the server starts again
+                return;
+            }
+            self.add_file(&path).await;
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_file(&self, path: &Path) {
+        let file_name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let failed = |reason: String| ImportedFile {
+            path: file_name.clone(),
+            outcome: ImportOutcome::Failed,
+            document: None,
+            reason: Some(reason),
+        };
+
+        let import = AnnotationImport {
+            annotations: self.config.inbox_import_annotations,
+            outline: self.config.inbox_import_outline,
+        };
+        let result = match find_owner(&self.pool, self.owner.as_deref()).await {
+            Ok(Some(owner)) => {
+                let Ok(upload) = self.shutdown.begin_upload(owner) else {
+                    return;
+                };
+                let result = match bind_pdfium(self.pdfium) {
+                    Ok(pdfium) => {
+                        import_single_file(
+                            &self.pool,
+                            &self.config,
+                            &self.indexer,
+                            pdfium,
+                            path,
+                            owner,
+                            import,
+                        )
+                        .await
+                    }
+                    Err(e) => {
+                        log::error!("Cannot add {} from the inbox: {}", file_name, e);
+                        failed(e.to_string())
+                    }
+                };
+                upload.finish();
+                result
+            }
+            Ok(None) => failed("There is no account to add the document to".to_owned()),
+            Err(e) => {
+                log::error!("Failed to look up the inbox owner: {}", e);
+                failed("Failed to look up the account to add the document to".to_owned())
+            }
+        };
+
+        let directory = match result.outcome {
+            ImportOutcome::Failed => FAILED_DIRECTORY,
+            ImportOutcome::Imported | ImportOutcome::Skipped => PROCESSED_DIRECTORY,
+        };
+        if let Err(e) = move_file(path, &self.location.join(directory)) {
+            log::error!("Failed to move {} to {}: {}", path.display(), directory, e);
+        }
+
+        let recorded = database::query(
+            "INSERT INTO IngestionEvents (id, file_name, outcome, document, reason, occurred_on)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(&file_name)
+        .bind(result.outcome.as_str())
+        .bind(result.document)
+        .bind(&result.reason)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = recorded {
+            log::error!("Failed to record the ingestion of {}: {}", file_name, e);
+        }
+    }
+}
+
+/// Moves a file into `directory`, keeping its name unless a file with the same
+/// name was moved there before
+fn move_file(path: &Path, directory: &Path) -> io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut destination = directory.join(file_name.as_ref());
+    if destination.exists() {
+        destination = directory.join(format!(
+            "{}-{}",
+            Utc::now().format("%Y%m%d%H%M%S%3f"),
+            file_name
+        ));
+    }
+    std::fs::rename(path, destination)
+}
@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use tantivy::{
-    collector::TopDocs,
-    directory::MmapDirectory,
+    collector::{DocSetCollector, TopDocs},
+    directory::{
+        error::{DeleteError, LockError, OpenReadError, OpenWriteError},
+        AntiCallToken, Directory, DirectoryLock, FileHandle, Lock, MmapDirectory, OwnedBytes,
+        TerminatingWrite, WatchCallback, WatchHandle, WritePtr,
+    },
     doc,
     query::{
         BooleanQuery, ConstScoreQuery, EnableScoring, FuzzyTermQuery, Occur, Query, QueryClone,
@@ -14,6 +21,8 @@ use tantivy::{
 use tokio::sync::{Mutex, MutexGuard};
 use uuid::Uuid;
 
+use crate::encryption::{is_encrypted, FileEncryption, StoredFileWriter};
+
 struct IndexFields {
     body: Field,
     document_id: Field,
@@ -26,19 +35,24 @@ pub struct Indexer {
     reader: IndexReader,
     schema: Schema,
     writer: Mutex<IndexWriter>,
+    closed: AtomicBool,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct SearchResult {
     pub score: f32,
     pub page: u64,
     pub snippet: Option<String>,
 }
 
+/// Exclusive access to the index for adding pages. Pages which are not committed
+/// are rolled back when the writer is dropped, so that an interrupted indexing does
+/// not leave them to be committed along with the next document.
 pub struct Writer<'a> {
     fields: &'a IndexFields,
     writer: MutexGuard<'a, IndexWriter>,
     reader: &'a IndexReader,
+    committed: bool,
 }
 
 impl<'a> Writer<'a> {
@@ -59,14 +73,136 @@ impl<'a> Writer<'a> {
         Ok(())
     }
 
+    /// Removes every page of every document. Used when the library is replaced
+    /// by a backup.
+    pub fn delete_all(&mut self) -> Result<(), IndexerError> {
+        self.writer.delete_all_documents()?;
+        Ok(())
+    }
+
     pub fn commit(mut self) -> Result<(), IndexerError> {
         self.writer.commit()?;
+        self.committed = true;
         self.reader.reload()?;
 
         Ok(())
     }
 }
 
+impl<'a> Drop for Writer<'a> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        log::warn!("Rolling back uncommitted changes to the index");
+        if let Err(e) = self.writer.rollback() {
+            log::error!("Failed to roll back index changes: {}", e);
+        }
+    }
+}
+
+/// Keeps the files of the index encrypted on disk, see [`crate::encryption`]. Files
+/// are decrypted into memory when they are opened, rather than mapped. Files which
+/// are not encrypted are read as they are.
+#[derive(Clone)]
+struct EncryptedDirectory {
+    inner: MmapDirectory,
+    encryption: FileEncryption,
+}
+
+impl std::fmt::Debug for EncryptedDirectory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedDirectory")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A file of the index which is encrypted as it is written
+struct EncryptingWrite(StoredFileWriter<WritePtr>);
+
+impl Write for EncryptingWrite {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl TerminatingWrite for EncryptingWrite {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.0.finish()?;
+        self.0.inner_mut().terminate_ref(token)
+    }
+}
+
+impl Directory for EncryptedDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let handle = self.inner.get_file_handle(path)?;
+        let stored = handle
+            .read_bytes(0..handle.len())
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_owned()))?;
+        if !is_encrypted(stored.as_slice()) {
+            return Ok(handle);
+        }
+        let contents = self
+            .encryption
+            .decrypt(stored.as_slice())
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_owned()))?;
+        Ok(Arc::new(OwnedBytes::new(contents)))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.inner.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.inner.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let inner = self.inner.open_write(path)?;
+        let Some(key) = self.encryption.write_key() else {
+            return Ok(inner);
+        };
+        let writer = StoredFileWriter::new(Some(key), inner)
+            .map_err(|e| OpenWriteError::wrap_io_error(e, path.to_owned()))?;
+        let writer: Box<dyn TerminatingWrite> = Box::new(EncryptingWrite(writer));
+        Ok(BufWriter::new(writer))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let stored = self.inner.atomic_read(path)?;
+        if !is_encrypted(&stored) {
+            return Ok(stored);
+        }
+        self.encryption
+            .decrypt(&stored)
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_owned()))
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut writer = StoredFileWriter::new(self.encryption.write_key(), Vec::new())?;
+        writer.write_all(data)?;
+        writer.finish()?;
+        self.inner.atomic_write(path, &writer.into_inner())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.inner.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        self.inner.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.inner.watch(watch_callback)
+    }
+}
+
 impl Indexer {
     const DOCUMENT_FIELD_NAME: &str = "document";
     const BODY_FIELD_NAME: &str = "body";
@@ -82,6 +218,14 @@ impl Indexer {
     }
 
     pub fn new(index_directory: PathBuf) -> Result<Self, IndexerError> {
+        Self::with_encryption(index_directory, FileEncryption::default())
+    }
+
+    /// Opens the index, encrypting the files written to it when encryption is enabled
+    pub fn with_encryption(
+        index_directory: PathBuf,
+        encryption: FileEncryption,
+    ) -> Result<Self, IndexerError> {
         log::info!("Setting up Indexer");
         if !index_directory.exists() {
             log::debug!(
@@ -94,8 +238,10 @@ impl Indexer {
             "Opening index directory {}",
             index_directory.to_string_lossy()
         );
-        let directory =
-            MmapDirectory::open(index_directory).expect("Failed to open index directory");
+        let directory = EncryptedDirectory {
+            inner: MmapDirectory::open(index_directory).expect("Failed to open index directory"),
+            encryption,
+        };
         log::debug!("Build schema");
         let schema = Indexer::build_schema();
         log::debug!("Opening index with schema");
@@ -108,6 +254,7 @@ impl Indexer {
         log::debug!("Index setup complete.");
         Ok(Indexer {
             writer: Mutex::new(index.writer(50_000_000)?),
+            closed: AtomicBool::new(false),
             reader,
             fields: IndexFields {
                 body: schema
@@ -125,27 +272,84 @@ impl Indexer {
         })
     }
 
-    pub async fn get_writer(&self) -> Result<Writer, IndexerError> {
+    pub async fn get_writer(&self) -> Result<Writer<'_>, IndexerError> {
         let writer = self.writer.lock().await;
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(IndexerError::Closed);
+        }
         Ok(Writer {
             reader: &self.reader,
             fields: &self.fields,
             writer,
+            committed: false,
         })
     }
 
+    /// Waits for indexing in progress to end, and keeps the index from being
+    /// written to afterwards. Called when the server shuts down.
+    pub async fn close(&self) -> Result<(), IndexerError> {
+        let mut writer = self.writer.lock().await;
+        self.closed.store(true, Ordering::SeqCst);
+        // Writers roll back what they did not commit, so this only ensures that
+        // nothing half-done is left in the index when the process exits
+        writer.rollback()?;
+        log::info!("Index closed");
+        Ok(())
+    }
+
+    /// Checks that the index can be read from disk and searched
+    pub fn check(&self) -> Result<(), IndexerError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(IndexerError::Closed);
+        }
+        self.index.searchable_segment_metas()?;
+        self.reader.searcher();
+        Ok(())
+    }
+
+    fn document_term(&self, doc_id: &Uuid) -> Term {
+        let mut binding = Uuid::encode_buffer();
+        let doc_id = doc_id.as_simple().encode_lower(&mut binding);
+        Term::from_facet(
+            self.fields.document_id,
+            &Facet::from(&format!("/documents/{doc_id}")),
+        )
+    }
+
+    /// The indexed text of every page of a document, ordered by page number
+    pub fn document_pages(&self, doc_id: &Uuid) -> Result<Vec<(u64, String)>, IndexerError> {
+        let query = TermQuery::new(self.document_term(doc_id), schema::IndexRecordOption::Basic);
+        let searcher = self.reader.searcher();
+
+        let mut pages = Vec::new();
+        for doc_address in searcher.search(&query, &DocSetCollector)? {
+            let doc = searcher.doc(doc_address)?;
+            let page = doc
+                .get_first(self.fields.page)
+                .and_then(|field| field.as_u64())
+                .ok_or(IndexerError::InvalidDocument)?;
+            let body = doc
+                .get_first(self.fields.body)
+                .and_then(|field| field.as_text())
+                .unwrap_or_default();
+            pages.push((page, body.to_owned()));
+        }
+        pages.sort_by_key(|(page, _)| *page);
+
+        Ok(pages)
+    }
+
+    #[tracing::instrument(
+        skip(self, query),
+        fields(document_id = %doc_id, result_count = tracing::field::Empty)
+    )]
     pub fn search_document(
         &self,
         doc_id: &Uuid,
         query: &str,
     ) -> Result<Vec<SearchResult>, IndexerError> {
-        let mut binding = Uuid::encode_buffer();
-        let doc_id = doc_id.as_simple().encode_lower(&mut binding);
-        let facet_term = Term::from_facet(
-            self.fields.document_id,
-            &Facet::from(&format!("/documents/{doc_id}")),
-        );
-        let doc_query = TermQuery::new(facet_term, schema::IndexRecordOption::Basic);
+        let doc_query =
+            TermQuery::new(self.document_term(doc_id), schema::IndexRecordOption::Basic);
         let doc_query = ConstScoreQuery::new(Box::new(doc_query), 0f32);
         doc_query
             .weight(EnableScoring::Disabled(&self.schema))
@@ -209,6 +413,7 @@ impl Indexer {
             results.push(res);
         }
 
+        tracing::Span::current().record("result_count", results.len() as u64);
         Ok(results)
     }
 }
@@ -221,6 +426,8 @@ pub enum IndexerError {
     OpenIndexError(#[from] tantivy::error::TantivyError),
     #[error("Invalid document index")]
     InvalidDocument,
+    #[error("The index is closed")]
+    Closed,
 }
 
 impl std::fmt::Debug for IndexerError {
@@ -1,8 +1,23 @@
+pub mod authentication;
+pub mod backup;
 pub mod configuration;
 pub mod database;
+pub mod encryption;
 pub mod error;
+pub mod export;
+pub mod import;
+pub mod inbox;
 pub mod indexer;
+pub mod metrics;
 pub mod models;
+pub mod openapi;
+pub mod request_id;
 pub mod routes;
+pub mod secrets;
+pub mod shutdown;
 pub mod startup;
+pub mod statistics;
+pub mod storage;
 pub mod telemetry;
+pub mod tls;
+pub mod usage;
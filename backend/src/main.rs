@@ -1,19 +1,224 @@
-use pdf_reader::configuration::get_configuration;
-use pdf_reader::startup::Application;
-use pdf_reader::telemetry::{get_subscriber, init_subscriber};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use pdf_reader::backup::{create_backup, restore_backup};
+use pdf_reader::configuration::{load_configuration, Settings};
+use pdf_reader::database::{get_connection_pool, initialize_database, DbPool};
+use pdf_reader::encryption::{rotate_key, FileEncryption};
+use pdf_reader::import::{default_jobs, find_owner, import_directory, ImportOutcome};
+use pdf_reader::indexer::Indexer;
+use pdf_reader::secrets::SecretKey;
+use pdf_reader::startup::{Application, PDFIUM};
+use pdf_reader::telemetry::{get_subscriber, init_subscriber, shutdown_subscriber};
+
+#[derive(Parser)]
+#[command(name = "pdfreader", version, about = "Self-hosted PDF reader")]
+struct Cli {
+    /// Configuration file in TOML or YAML format. Defaults to $PDF_READER_CONFIG_FILE,
+    /// or pdfreader.toml in the working directory. PDF_READER_* environment variables
+    /// override anything set in the file.
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the server. This is the default when no command is given.
+    Serve,
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Write the database, the documents and the index to a single archive. The
+    /// server must not be running, use the admin API to back up a running server.
+    Backup {
+        /// Where to write the archive
+        output: PathBuf,
+        /// Leave the index out, so that it is rebuilt from the documents on restore
+        #[arg(long)]
+        without_index: bool,
+    },
+    /// Add every PDF within a directory and its subdirectories to the library. Folders
+    /// become collections, and files which are already in the library are skipped.
+    Import {
+        directory: PathBuf,
+        /// Account to add the documents to. Defaults to the first administrator.
+        #[arg(long)]
+        owner: Option<String>,
+        /// How many files are read and indexed at the same time. Defaults to the
+        /// number of CPUs.
+        #[arg(long)]
+        jobs: Option<usize>,
+    },
+    /// Replace the library with the contents of a backup archive
+    Restore {
+        archive: PathBuf,
+        /// Replace the library even when it already has documents
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move the stored files and kept passwords from the configured server key to a
+    /// new one, and encrypt the files stored before encrypt_storage was set. Configure
+    /// the new key afterwards. The server must not be running.
+    RotateKey {
+        /// File with the new key as 64 hex digits. Give the configured key to only
+        /// encrypt the files which are not encrypted yet.
+        new_key_file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective configuration, with secrets redacted, and check that it is valid
+    Check,
+}
 
 #[actix_web::main]
-async fn main() -> Result<(), std::io::Error> {
-    let subscriber = get_subscriber("pdf_reader".into(), "debug".into(), std::io::stdout);
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let configuration = load_configuration(cli.config.as_deref())?;
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(configuration).await,
+        Command::Config(ConfigCommand::Check) => check_configuration(&configuration),
+        Command::Backup {
+            output,
+            without_index,
+        } => backup(configuration, output, without_index).await,
+        Command::Restore { archive, force } => restore(configuration, archive, force).await,
+        Command::RotateKey { new_key_file } => rotate(configuration, new_key_file).await,
+        Command::Import {
+            directory,
+            owner,
+            jobs,
+        } => import(configuration, directory, owner, jobs).await,
+    }
+}
+
+async fn serve(configuration: Settings) -> anyhow::Result<()> {
+    let subscriber = get_subscriber(
+        "pdf_reader".into(),
+        "debug".into(),
+        std::io::stdout,
+        configuration.otlp_endpoint.clone(),
+    );
     init_subscriber(subscriber);
-    println!("Launching backend");
 
     log::info!("Logging initialized");
+    configuration.validate()?;
 
-    let configuration = get_configuration();
-
-    Application::build(configuration, None)
+    let result = Application::build(configuration, None)
         .await?
         .run_until_stopped()
-        .await
+        .await;
+
+    shutdown_subscriber();
+    Ok(result?)
+}
+
+fn check_configuration(configuration: &Settings) -> anyhow::Result<()> {
+    print!("{}", toml::to_string(&configuration.redacted())?);
+    configuration.validate()?;
+    eprintln!("Configuration is valid");
+    Ok(())
+}
+
+/// Opens the database and the index the way the server does. Only one process can
+/// write to the index, so this fails while the server is running.
+async fn open_library(configuration: &Settings) -> anyhow::Result<(DbPool, Indexer)> {
+    let subscriber = get_subscriber("pdf_reader".into(), "info".into(), std::io::stderr, None);
+    init_subscriber(subscriber);
+
+    Application::ensure_storage_path(configuration).await;
+    let pool = get_connection_pool(configuration);
+    initialize_database(&pool).await;
+    let encryption = FileEncryption::load(configuration)?;
+    let indexer = Indexer::with_encryption(configuration.documents_contents_path(), encryption)
+        .context(
+            "Failed to open the index. Is the server running? Use the admin API to back up a running server",
+        )?;
+    Ok((pool, indexer))
+}
+
+async fn backup(
+    configuration: Settings,
+    output: PathBuf,
+    without_index: bool,
+) -> anyhow::Result<()> {
+    let (pool, indexer) = open_library(&configuration).await?;
+    let manifest = create_backup(&pool, &configuration, &indexer, &output, !without_index).await?;
+    eprintln!(
+        "Backed up {} documents to {}",
+        manifest.document_count,
+        output.display()
+    );
+    Ok(())
+}
+
+async fn restore(configuration: Settings, archive: PathBuf, force: bool) -> anyhow::Result<()> {
+    let (pool, indexer) = open_library(&configuration).await?;
+    let summary = restore_backup(&pool, &configuration, &indexer, &PDFIUM, &archive, force).await?;
+    eprintln!(
+        "Restored {} documents from a backup made on {}",
+        summary.documents, summary.created_on
+    );
+    Ok(())
+}
+
+async fn rotate(configuration: Settings, new_key_file: PathBuf) -> anyhow::Result<()> {
+    // Opening the index takes its lock, which keeps a running server from using
+    // the files while they are rewritten
+    let (pool, _indexer) = open_library(&configuration).await?;
+    let key = std::fs::read_to_string(&new_key_file)
+        .with_context(|| format!("Failed to read {}", new_key_file.display()))?;
+    let new_key = SecretKey::from_hex(&key)?;
+    let rotation = rotate_key(&pool, &configuration, &new_key).await?;
+    eprintln!(
+        "Moved {} files and {} passwords to the new key, and encrypted {} files. Configure the new key before starting the server.",
+        rotation.rewrapped, rotation.passwords, rotation.encrypted
+    );
+    Ok(())
+}
+
+async fn import(
+    configuration: Settings,
+    directory: PathBuf,
+    owner: Option<String>,
+    jobs: Option<usize>,
+) -> anyhow::Result<()> {
+    let (pool, indexer) = open_library(&configuration).await?;
+    let owner = find_owner(&pool, owner.as_deref())
+        .await?
+        .context("No account to add the documents to")?;
+
+    let report = import_directory(
+        &pool,
+        &configuration,
+        &indexer,
+        &PDFIUM,
+        &directory,
+        owner,
+        jobs.unwrap_or_else(default_jobs),
+    )
+    .await?;
+    for file in report
+        .files
+        .iter()
+        .filter(|f| f.outcome != ImportOutcome::Imported)
+    {
+        println!(
+            "{:?}\t{}\t{}",
+            file.outcome,
+            file.path,
+            file.reason.as_deref().unwrap_or_default()
+        );
+    }
+    println!(
+        "Imported {}, skipped {}, failed {}",
+        report.imported, report.skipped, report.failed
+    );
+    Ok(())
 }
@@ -0,0 +1,226 @@
+use std::future::{ready, Ready};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, HttpResponse};
+use futures::future::LocalBoxFuture;
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::error::{internal_error, ApiError};
+
+/// Every metric exposed on `/metrics`. Counters and histograms are updated where
+/// the work happens, while gauges are sampled when the metrics are scraped.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub upload_bytes: IntCounter,
+    pub indexing_duration: Histogram,
+    pub indexing_pages_per_second: Histogram,
+    pub search_duration: Histogram,
+    pub search_results: Histogram,
+    index_size: IntGauge,
+    documents: IntGauge,
+    db_connections: IntGauge,
+    db_idle_connections: IntGauge,
+}
+
+pub static METRICS: Lazy<Metrics> =
+    Lazy::new(|| Metrics::new().expect("Failed to register metrics"));
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("pdfreader".to_owned()), None)?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of handled HTTP requests"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["method", "route"],
+        )?;
+        let upload_bytes = IntCounter::new(
+            "upload_bytes_total",
+            "Number of bytes received in uploaded documents",
+        )?;
+        let indexing_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "indexing_duration_seconds",
+                "Time taken to index a document",
+            )
+            .buckets(exponential_buckets(0.1, 2.0, 12)?),
+        )?;
+        let indexing_pages_per_second = Histogram::with_opts(
+            HistogramOpts::new(
+                "indexing_pages_per_second",
+                "Indexing throughput of each indexed document",
+            )
+            .buckets(exponential_buckets(1.0, 2.0, 12)?),
+        )?;
+        let search_duration = Histogram::with_opts(HistogramOpts::new(
+            "search_duration_seconds",
+            "Time taken to search a document",
+        ))?;
+        let search_results = Histogram::with_opts(
+            HistogramOpts::new("search_results", "Number of results returned by a search")
+                .buckets(vec![0.0, 1.0, 2.0, 5.0, 10.0]),
+        )?;
+        let index_size = IntGauge::new("index_size_bytes", "Size of the search index on disk")?;
+        let documents = IntGauge::new("documents", "Number of documents in the library")?;
+        let db_connections =
+            IntGauge::new("db_pool_connections", "Number of open database connections")?;
+        let db_idle_connections = IntGauge::new(
+            "db_pool_idle_connections",
+            "Number of open database connections which are not in use",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(upload_bytes.clone()))?;
+        registry.register(Box::new(indexing_duration.clone()))?;
+        registry.register(Box::new(indexing_pages_per_second.clone()))?;
+        registry.register(Box::new(search_duration.clone()))?;
+        registry.register(Box::new(search_results.clone()))?;
+        registry.register(Box::new(index_size.clone()))?;
+        registry.register(Box::new(documents.clone()))?;
+        registry.register(Box::new(db_connections.clone()))?;
+        registry.register(Box::new(db_idle_connections.clone()))?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            upload_bytes,
+            indexing_duration,
+            indexing_pages_per_second,
+            search_duration,
+            search_results,
+            index_size,
+            documents,
+            db_connections,
+            db_idle_connections,
+        })
+    }
+}
+
+async fn get_metrics(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+) -> Result<HttpResponse, ApiError> {
+    let (document_count,): (i64,) = database::query_as("SELECT COUNT(*) FROM Documents")
+        .fetch_one(pool.get_ref())
+        .await
+        .map_err(internal_error("Failed to count documents"))?;
+    METRICS.documents.set(document_count);
+    METRICS.db_connections.set(pool.size().into());
+    METRICS.db_idle_connections.set(pool.num_idle() as i64);
+
+    let index_path = config.documents_contents_path();
+    let index_size = web::block(move || directory_size(&index_path))
+        .await
+        .map_err(internal_error("Failed to measure index size"))?
+        .map_err(internal_error("Failed to measure index size"))?;
+    METRICS.index_size.set(index_size as i64);
+
+    let body = TextEncoder::new()
+        .encode_to_string(&METRICS.registry.gather())
+        .map_err(internal_error("Failed to encode metrics"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
+
+fn directory_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let metadata = entry?.metadata()?;
+        size += match metadata.is_dir() {
+            true => 0,
+            false => metadata.len(),
+        };
+    }
+
+    Ok(size)
+}
+
+pub fn setup_metrics_service() -> actix_web::Resource {
+    web::resource("/metrics").route(web::get().to(get_metrics))
+}
+
+/// Middleware which counts and times every request, labelled by the route pattern
+/// which handled it rather than the literal path, to keep the number of series bounded.
+pub struct RecordMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RecordMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RecordMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RecordMetricsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RecordMetricsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RecordMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let started = Instant::now();
+            let method = req.method().to_string();
+            let route = req.match_pattern();
+
+            let result = service.call(req).await;
+
+            let status = match &result {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            let route = route.unwrap_or_else(|| "unmatched".to_owned());
+            METRICS
+                .http_requests
+                .with_label_values(&[&method, &route, status.as_str()])
+                .inc();
+            METRICS
+                .http_request_duration
+                .with_label_values(&[&method, &route])
+                .observe(started.elapsed().as_secs_f64());
+
+            result
+        })
+    }
+}
@@ -1,21 +1,43 @@
-use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Serialize, sqlx::FromRow)]
+use crate::authentication::TokenScope;
+use crate::database::ScopeList;
+use crate::import::ImportOutcome;
+
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
 pub struct Document {
     pub id: Uuid,
     pub name: String,
     pub added_on: DateTime<Utc>,
     pub current_page: i32,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub notes: Option<String>,
+    pub page_count: Option<i32>,
+    pub owner: Option<Uuid>,
+    pub content_hash: Option<String>,
+    /// Folder the document was imported from, such as `papers/2023`
+    pub collection: Option<String>,
 }
 
-#[derive(Deserialize)]
+/// Partial update of a document. Fields which are left out are not changed,
+/// and an empty string clears the optional metadata fields.
+#[derive(Deserialize, Serialize, ToSchema, Default)]
 pub struct UpdateDocumentRequest {
-    pub current_page: i32,
+    pub current_page: Option<i32>,
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub notes: Option<String>,
+    pub collection: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
 pub struct Bookmark {
     pub id: Uuid,
     pub document: Uuid,
@@ -23,10 +45,279 @@ pub struct Bookmark {
     pub page: i32,
     pub deleted_on: Option<DateTime<Utc>>,
     pub description: String,
+    pub owner: Option<Uuid>,
+    /// Read from an annotation or the outline of the PDF when it was uploaded
+    pub imported: bool,
+    /// Area of the page which the bookmark highlights, as `left bottom right top`
+    /// in PDF points
+    pub highlight_area: Option<String>,
+}
+
+/// A bookmark which highlights an area of its page
+pub struct Highlight {
+    pub page: i32,
+    pub description: String,
+    pub area: HighlightArea,
+}
+
+/// Rectangle of a page in PDF points, measured from its bottom left corner. It is
+/// stored as its four sides separated by spaces, in the order of a PDF `Rect`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HighlightArea {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+impl HighlightArea {
+    pub fn parse(text: &str) -> Option<Self> {
+        let sides = text
+            .split_whitespace()
+            .map(|side| side.parse::<f32>().ok().filter(|side| side.is_finite()))
+            .collect::<Option<Vec<_>>>()?;
+        match sides[..] {
+            [left, bottom, right, top] if left < right && bottom < top => Some(Self {
+                left,
+                bottom,
+                right,
+                top,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for HighlightArea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.left, self.bottom, self.right, self.top
+        )
+    }
+}
+
+/// The bookmarks of a document, as exported
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ExportedDocument {
+    pub id: Uuid,
+    /// Title of the document, or its file name when it has none
+    pub title: String,
+    pub name: String,
+    pub bookmarks: Vec<ExportedBookmark>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ExportedBookmark {
+    pub page: i32,
+    pub description: String,
+    pub added_on: DateTime<Utc>,
+    /// Start of the text of the bookmarked page
+    pub snippet: Option<String>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct AddBookmarkRequest {
     pub page: i32,
     pub description: String,
+    /// Makes the bookmark a highlight of this area of the page, given as
+    /// `left bottom right top` in PDF points from the bottom left corner
+    #[serde(default)]
+    pub highlight_area: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    pub is_admin: bool,
+    pub added_on: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Credentials {
+    pub username: String,
+    #[schema(format = Password)]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateUserRequest {
+    pub username: String,
+    #[schema(format = Password)]
+    pub password: String,
+    #[serde(default)]
+    pub is_admin: bool,
+}
+
+/// A personal API token, as shown to its owner. The token itself is only
+/// returned once, when it is created.
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    #[sqlx(try_from = "ScopeList")]
+    pub scopes: Vec<String>,
+    pub added_on: DateTime<Utc>,
+    pub expires_on: Option<DateTime<Utc>>,
+    pub last_used_on: Option<DateTime<Utc>>,
+    pub revoked_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires_on: Option<DateTime<Utc>>,
+}
+
+/// Imports every PDF within a directory on the server
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImportRequest {
+    /// Absolute path of the directory, as seen by the server
+    pub path: String,
+    /// How many files are read and indexed at the same time. Defaults to the
+    /// number of CPUs.
+    pub jobs: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreatedToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub details: ApiToken,
+}
+
+/// A link which gives access to a single document without logging in
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct ShareLink {
+    pub id: Uuid,
+    pub document: Uuid,
+    pub page: Option<i32>,
+    pub has_password: bool,
+    pub added_on: DateTime<Utc>,
+    pub expires_on: Option<DateTime<Utc>>,
+    pub revoked_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Default)]
+pub struct CreateShareRequest {
+    pub document: Uuid,
+    pub page: Option<i32>,
+    #[schema(format = Password)]
+    pub password: Option<String>,
+    pub expires_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreatedShare {
+    pub token: String,
+    #[serde(flatten)]
+    pub details: ShareLink,
+}
+
+/// What someone opening a share link gets to know about the document
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SharedDocument {
+    pub name: String,
+    pub page_count: Option<i32>,
+    pub page: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct PageEvent {
+    pub id: Uuid,
+    pub document: Uuid,
+    pub page: i32,
+    pub occurred_on: DateTime<Utc>,
+    pub reader: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Debug)]
+pub struct DailyPages {
+    pub date: NaiveDate,
+    pub pages: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DocumentStatistics {
+    pub document: Uuid,
+    pub name: String,
+    pub current_page: i32,
+    pub page_count: Option<i32>,
+    pub reading_time_seconds: i64,
+    pub pages_read: i64,
+    pub pages_per_day: Vec<DailyPages>,
+    pub last_opened: Option<DateTime<Utc>>,
+    pub estimated_seconds_to_finish: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LibraryStatistics {
+    pub document_count: i64,
+    pub documents_started: i64,
+    pub documents_finished: i64,
+    pub reading_time_seconds: i64,
+    pub pages_per_day: Vec<DailyPages>,
+    pub last_opened: Option<DateTime<Utc>>,
+    pub estimated_seconds_to_finish: Option<i64>,
+    pub documents: Vec<DocumentStatistics>,
+}
+
+/// Storage used by the documents of a user, and the quotas it counts towards
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Usage {
+    pub document_count: i64,
+    pub byte_size: i64,
+    /// Most documents which may be uploaded, if limited
+    pub quota_documents: Option<u64>,
+    /// Most bytes which may be uploaded, if limited
+    pub quota_bytes: Option<u64>,
+    pub collections: Vec<CollectionUsage>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollectionUsage {
+    /// `null` for the documents which are not in a collection
+    pub collection: Option<String>,
+    pub document_count: i64,
+    pub byte_size: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+/// Outcome of checking one of the dependencies of the server
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    pub latency_ms: f64,
+    /// Why the component is down
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct HealthReport {
+    /// `up` only when every component is
+    pub status: HealthStatus,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+/// A file which was taken from the watched inbox directory
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct IngestionEvent {
+    pub id: Uuid,
+    pub file_name: String,
+    #[sqlx(try_from = "String")]
+    pub outcome: ImportOutcome,
+    /// The new document, or the existing one when the file was a duplicate
+    pub document: Option<Uuid>,
+    /// Why the file was skipped or failed
+    pub reason: Option<String>,
+    pub occurred_on: DateTime<Utc>,
 }
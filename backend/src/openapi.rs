@@ -0,0 +1,166 @@
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::{Components, OpenApi as OpenApiSpec};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::authentication::{TokenScope, SESSION_COOKIE_NAME};
+use crate::backup::RestoreSummary;
+use crate::error::ErrorCode;
+use crate::export::ExportFormat;
+use crate::import::{ImportOutcome, ImportReport, ImportedFile};
+use crate::indexer::SearchResult;
+use crate::models::{
+    AddBookmarkRequest, ApiToken, Bookmark, CollectionUsage, ComponentHealth, CreateShareRequest,
+    CreateTokenRequest, CreateUserRequest, CreatedShare, CreatedToken, Credentials, DailyPages,
+    Document, DocumentStatistics, ExportedBookmark, ExportedDocument, HealthReport, HealthStatus,
+    ImportRequest, IngestionEvent, LibraryStatistics, ShareLink, SharedDocument,
+    UpdateDocumentRequest, Usage, User,
+};
+use crate::routes::{
+    admin, bookmarks, documents, health, search, shares, stats, tokens, usage, users,
+};
+
+pub const OPENAPI_PATH: &str = "/api/openapi.json";
+
+/// Body of every error response, see [`crate::error::ApiError`]
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorResponse {
+    code: ErrorCode,
+    message: String,
+    /// Additional information which depends on the code, such as the invalid field
+    details: Option<serde_json::Value>,
+    /// Id of the request, to be quoted when reporting a problem
+    request_id: String,
+}
+
+/// Multipart body used to upload documents. Every file part is added as a separate
+/// document.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    /// Password of the protected files which follow it
+    password: Option<String>,
+    /// Whether to keep the password, encrypted, so that pages can be rendered later
+    keep_password: Option<bool>,
+    #[schema(value_type = Vec<String>, format = Binary)]
+    files: Vec<Vec<u8>>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "PDF reader"),
+    paths(
+        documents::get_all::list_documents,
+        documents::get_by_id::get_document,
+        documents::patch::update_document_status,
+        documents::post::upload_document,
+        bookmarks::add_bookmark,
+        bookmarks::get_bookmarks,
+        bookmarks::delete_bookmark,
+        bookmarks::export_document_bookmarks,
+        bookmarks::export_library_bookmarks,
+        search::search_document,
+        stats::get_document_statistics,
+        stats::get_library_statistics,
+        usage::get_usage,
+        users::login,
+        users::logout,
+        users::setup,
+        users::get_current_user,
+        users::create_user,
+        tokens::create_token,
+        tokens::get_tokens,
+        tokens::revoke_token,
+        shares::create_share,
+        shares::get_shares,
+        shares::revoke_share,
+        shares::get_shared_info,
+        shares::get_shared_document,
+        shares::get_shared_page,
+        crate::startup::health_check,
+        health::live,
+        health::ready,
+        admin::backup,
+        admin::restore,
+        admin::import,
+        admin::inbox_history,
+    ),
+    components(schemas(
+        Document,
+        UpdateDocumentRequest,
+        UploadForm,
+        Bookmark,
+        AddBookmarkRequest,
+        ExportedDocument,
+        ExportedBookmark,
+        ExportFormat,
+        SearchResult,
+        DocumentStatistics,
+        LibraryStatistics,
+        DailyPages,
+        Usage,
+        CollectionUsage,
+        User,
+        Credentials,
+        CreateUserRequest,
+        ApiToken,
+        CreateTokenRequest,
+        CreatedToken,
+        TokenScope,
+        ShareLink,
+        CreateShareRequest,
+        CreatedShare,
+        SharedDocument,
+        HealthReport,
+        ComponentHealth,
+        HealthStatus,
+        RestoreSummary,
+        ImportRequest,
+        ImportReport,
+        ImportedFile,
+        ImportOutcome,
+        IngestionEvent,
+        ErrorResponse,
+        ErrorCode,
+    )),
+    modifiers(&SecuritySchemes),
+    security(("session" = []), ("api_token" = [])),
+    tags(
+        (name = "documents", description = "Uploading, downloading and describing documents"),
+        (name = "bookmarks", description = "Bookmarks on pages of a document"),
+        (name = "search", description = "Full text search within a document"),
+        (name = "statistics", description = "Reading statistics"),
+        (name = "usage", description = "Storage used by each user, and their quotas"),
+        (name = "users", description = "Accounts and sessions"),
+        (name = "tokens", description = "Personal API tokens"),
+        (name = "shares", description = "Links which give access to a document without an account"),
+        (name = "health", description = "Monitoring"),
+        (name = "admin", description = "Backing up, restoring and importing the library, and the watched inbox"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Requests are authenticated either by the session cookie set when logging in,
+/// or by a personal API token sent as a bearer token.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut OpenApiSpec) {
+        let components = openapi.components.get_or_insert_with(Components::new);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE_NAME))),
+        );
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// Serves the specification, and the interactive docs on `/api/docs/`. Neither
+/// requires authentication, so that clients can be generated from them.
+pub fn setup_openapi_service() -> SwaggerUi {
+    SwaggerUi::new("/api/docs/{_:.*}").url(OPENAPI_PATH, ApiDoc::openapi())
+}
@@ -0,0 +1,203 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::body::{to_bytes, EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use futures::future::LocalBoxFuture;
+use serde_json::{Map, Value};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ErrorCode};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 200;
+
+/// Identifies a single request across logs, traces and the response sent to the client.
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// Uses the id sent by the client, or a proxy in front of us, as long as it
+    /// is reasonably short and safe to put in logs and headers.
+    fn from_request(req: &ServiceRequest) -> Self {
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LENGTH
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+            })
+            .map(|id| Self(id.to_owned()))
+            .unwrap_or_else(Self::generate)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(RequestId::generate)))
+    }
+}
+
+/// Middleware which gives every request an id. The id is attached to a span
+/// covering the whole request, echoed in the `X-Request-Id` response header, and
+/// added to the body of every error response.
+pub struct AssignRequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for AssignRequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = AssignRequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AssignRequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AssignRequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AssignRequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let request_id = RequestId::from_request(&req);
+        req.extensions_mut().insert(request_id.clone());
+
+        let span = tracing::info_span!(
+            "HTTP request",
+            request_id = %request_id,
+            method = %req.method(),
+            path = %req.path(),
+        );
+
+        Box::pin(async move {
+            match service.call(req).instrument(span).await {
+                Ok(response) => {
+                    let (request, response) = response.into_parts();
+                    let response = with_request_id(response, &request_id).await;
+                    Ok(ServiceResponse::new(request, response))
+                }
+                // Errors from middleware have not been turned into responses yet, and
+                // the request is gone, so the response is carried by the error
+                Err(e) => {
+                    let response = with_request_id(e.error_response(), &request_id).await;
+                    Err(InternalError::from_response(e, response.map_into_boxed_body()).into())
+                }
+            }
+        })
+    }
+}
+
+/// Sends the request id back in a header, and in the body of errors
+async fn with_request_id<B: MessageBody + 'static>(
+    response: HttpResponse<B>,
+    request_id: &RequestId,
+) -> HttpResponse<EitherBody<B>> {
+    let status = response.status();
+    let mut response = match status.is_client_error() || status.is_server_error() {
+        true => with_request_id_in_body(response, request_id).await,
+        false => response.map_into_left_body(),
+    };
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    response
+}
+
+/// Adds the request id to a JSON error body. Any other error body, such as the
+/// ones actix-web sends for unmatched routes, is replaced with the JSON an
+/// [`ApiError`] would have produced.
+async fn with_request_id_in_body<B: MessageBody + 'static>(
+    response: HttpResponse<B>,
+    request_id: &RequestId,
+) -> HttpResponse<EitherBody<B>> {
+    let (mut head, body) = response.into_parts();
+    let body = to_bytes(body).await.unwrap_or_default();
+
+    let is_json = head
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.as_bytes().starts_with(b"application/json"))
+        .unwrap_or(false);
+    let mut json = match serde_json::from_slice(&body) {
+        Ok(Value::Object(json)) if is_json => json,
+        _ => {
+            let message = match body.is_empty() {
+                true => head
+                    .status()
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_owned(),
+                false => String::from_utf8_lossy(&body).into_owned(),
+            };
+            let error = ApiError::new(ErrorCode::from_status(head.status()), message);
+            match serde_json::to_value(error) {
+                Ok(Value::Object(json)) => json,
+                _ => Map::new(),
+            }
+        }
+    };
+    json.insert(
+        "request_id".to_owned(),
+        Value::String(request_id.as_str().to_owned()),
+    );
+
+    head.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    head.headers_mut().remove(header::CONTENT_LENGTH);
+    let body = serde_json::to_vec(&json).unwrap_or_default();
+
+    head.set_body(body)
+        .map_into_boxed_body()
+        .map_into_right_body()
+}
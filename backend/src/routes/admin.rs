@@ -0,0 +1,224 @@
+use std::path::Path;
+
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use chrono::Utc;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::Pdfium;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use utoipa::IntoParams;
+
+use crate::authentication::AuthenticatedUser;
+use crate::backup::{create_backup, restore_backup, ScratchDirectory};
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::error::{internal_error, ApiError};
+use crate::import::{default_jobs, import_directory};
+use crate::indexer::Indexer;
+use crate::models::{ImportRequest, IngestionEvent};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BackupQuery {
+    /// Leave the index out of the archive, so that it is rebuilt from the documents
+    /// on restore. This makes the archive smaller, but the restore slower.
+    #[serde(default)]
+    without_index: bool,
+}
+
+/// Downloads a backup of the whole library: accounts, documents, bookmarks,
+/// reading history, tokens, share links and the index.
+#[utoipa::path(
+    get,
+    path = "/api/admin/backup",
+    tag = "admin",
+    params(BackupQuery),
+    responses(
+        (status = 200, description = "The backup as a gzipped tar archive", body = String, content_type = "application/gzip"),
+        (status = 403, description = "The user is not an administrator", body = ErrorResponse),
+    )
+)]
+async fn backup(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    indexer: web::Data<Indexer>,
+    user: AuthenticatedUser,
+    query: web::Query<BackupQuery>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    user.require_admin()?;
+    log::info!("{} is backing up the library", user.username);
+
+    let scratch = ScratchDirectory::create(&config).map_err(internal_error(
+        "Failed to create a directory for the backup",
+    ))?;
+    let archive = scratch.path().join("backup.tar.gz");
+    create_backup(&pool, &config, &indexer, &archive, !query.without_index).await?;
+
+    // The archive is removed along with the scratch directory once the file is
+    // open, and is only gone from disk once it has been sent
+    let file = NamedFile::open_async(&archive)
+        .await
+        .map_err(internal_error("Failed to open the backup"))?
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "pdfreader-backup-{}.tar.gz",
+                Utc::now().format("%Y-%m-%d")
+            ))],
+        });
+    Ok(file.into_response(&request))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RestoreQuery {
+    /// Replace the library even when it already has documents
+    #[serde(default)]
+    force: bool,
+}
+
+/// Replaces the whole library with a backup. The accounts are replaced as well, so
+/// everyone has to log in again, with the credentials they had in the backup.
+#[utoipa::path(
+    post,
+    path = "/api/admin/restore",
+    tag = "admin",
+    params(RestoreQuery),
+    request_body(content = String, description = "Archive created by a backup", content_type = "application/gzip"),
+    responses(
+        (status = 200, description = "The library was restored", body = RestoreSummary),
+        (status = 400, description = "The archive is invalid or corrupt", body = ErrorResponse),
+        (status = 403, description = "The user is not an administrator", body = ErrorResponse),
+        (status = 409, description = "The library has documents, and force was not set", body = ErrorResponse),
+    )
+)]
+async fn restore(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    indexer: web::Data<Indexer>,
+    pdfium: web::Data<&Lazy<Pdfium>>,
+    user: AuthenticatedUser,
+    query: web::Query<RestoreQuery>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    user.require_admin()?;
+    log::info!("{} is restoring the library from a backup", user.username);
+
+    let scratch = ScratchDirectory::create(&config).map_err(internal_error(
+        "Failed to create a directory for the backup",
+    ))?;
+    let archive = scratch.path().join("backup.tar.gz");
+    let mut file = tokio::fs::File::create(&archive)
+        .await
+        .map_err(internal_error("Failed to store the backup"))?;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ApiError::bad_request(e.to_string()))?;
+        file.write_all(&chunk)
+            .await
+            .map_err(internal_error("Failed to store the backup"))?;
+    }
+    file.flush()
+        .await
+        .map_err(internal_error("Failed to store the backup"))?;
+
+    let summary = restore_backup(&pool, &config, &indexer, &pdfium, &archive, query.force).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Imports every PDF within a directory on the server and its subdirectories into
+/// the library of the current user. Folders become collections, and files which
+/// are already in the library are skipped.
+#[utoipa::path(
+    post,
+    path = "/api/admin/import",
+    tag = "admin",
+    request_body = ImportRequest,
+    responses(
+        (status = 200, description = "What happened to each file", body = ImportReport),
+        (status = 400, description = "The path is not a directory", body = ErrorResponse),
+        (status = 403, description = "The user is not an administrator", body = ErrorResponse),
+        (status = 503, description = "The pdfium library could not be loaded", body = ErrorResponse),
+    )
+)]
+async fn import(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    indexer: web::Data<Indexer>,
+    pdfium: web::Data<&'static Lazy<Pdfium>>,
+    user: AuthenticatedUser,
+    request: web::Json<ImportRequest>,
+) -> Result<HttpResponse, ApiError> {
+    user.require_admin()?;
+    log::info!("{} is importing {}", user.username, request.path);
+
+    let report = import_directory(
+        &pool,
+        &config,
+        &indexer,
+        pdfium.get_ref(),
+        Path::new(&request.path),
+        user.id,
+        request.jobs.unwrap_or_else(default_jobs),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct InboxQuery {
+    /// How many files to list, at most 1000
+    #[serde(default = "default_inbox_limit")]
+    limit: i64,
+}
+
+fn default_inbox_limit() -> i64 {
+    100
+}
+
+/// Lists the files most recently taken from the inbox directory, newest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/inbox",
+    tag = "admin",
+    params(InboxQuery),
+    responses(
+        (status = 200, description = "What happened to each file", body = [IngestionEvent]),
+        (status = 400, description = "The limit is out of range", body = ErrorResponse),
+        (status = 403, description = "The user is not an administrator", body = ErrorResponse),
+    )
+)]
+async fn inbox_history(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    query: web::Query<InboxQuery>,
+) -> Result<HttpResponse, ApiError> {
+    user.require_admin()?;
+    if !(1..=1000).contains(&query.limit) {
+        return Err(ApiError::invalid_field(
+            "limit",
+            "Must be between 1 and 1000",
+        ));
+    }
+
+    let events = database::query_as::<IngestionEvent>(
+        "SELECT * FROM IngestionEvents ORDER BY occurred_on DESC LIMIT $1",
+    )
+    .bind(query.limit)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to retrieve the inbox history"))?;
+    Ok(HttpResponse::Ok().json(events))
+}
+
+pub fn setup_admin_service() -> Scope {
+    web::scope("/admin")
+        .route("/backup", web::get().to(backup))
+        .route("/restore", web::post().to(restore))
+        .route("/import", web::post().to(import))
+        .route("/inbox", web::get().to(inbox_history))
+}
@@ -1,109 +1,255 @@
-use actix_web::{error, web, Scope};
-use actix_web::{HttpResponse, Result as AWResult};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse, Scope};
 use serde::Deserialize;
-use sqlx::postgres::PgQueryResult;
-use sqlx::PgPool;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-use crate::models::{AddBookmarkRequest, Bookmark};
+use crate::authentication::AuthenticatedUser;
+use crate::database::{self, DbPool, SELECT_DOCUMENTS};
+use crate::error::{internal_error, ApiError};
+use crate::export::{collect_bookmarks, render, ExportFormat};
+use crate::indexer::Indexer;
+use crate::models::{AddBookmarkRequest, Bookmark, Document, ExportedDocument, HighlightArea};
 
+#[utoipa::path(
+    post,
+    path = "/api/documents/{document_id}/bookmarks",
+    tag = "bookmarks",
+    params(("document_id" = Uuid, Path, description = "Id of the document")),
+    request_body = AddBookmarkRequest,
+    responses(
+        (status = 201, description = "Bookmark added", body = Bookmark),
+    )
+)]
 async fn add_bookmark(
-    pool: web::Data<PgPool>,
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
     request: web::Json<AddBookmarkRequest>,
     document_id: web::Path<Uuid>,
-) -> AWResult<HttpResponse> {
+) -> Result<HttpResponse, ApiError> {
     log::info!(
         "Adding new bookmark to page {} for document {}",
         request.page,
         document_id
     );
+    let highlight_area = match &request.highlight_area {
+        Some(area) => Some(HighlightArea::parse(area).ok_or_else(|| {
+            ApiError::invalid_field(
+                "highlight_area",
+                "Highlight area must be four numbers: left, bottom, right and top",
+            )
+        })?),
+        None => None,
+    };
     let bookmark_id = Uuid::new_v4();
-    sqlx::query!(
-        "INSERT INTO Bookmarks (id, description, page, document)
-    VALUES ($1, $2, $3, $4)",
-        bookmark_id,
-        request.description,
-        request.page,
-        *document_id
+    database::query(
+        "INSERT INTO Bookmarks (id, description, page, document, owner, highlight_area)
+    VALUES ($1, $2, $3, $4, $5, $6)",
     )
+    .bind(bookmark_id)
+    .bind(&request.description)
+    .bind(request.page)
+    .bind(*document_id)
+    .bind(user.id)
+    .bind(highlight_area.map(|area| area.to_string()))
     .execute(pool.as_ref())
     .await
-    .map_err(|e| {
-        log::error!("Failed to add new bookmark.\n{}", e);
-        error::ErrorInternalServerError("Failed to add new bookmark")
-    })?;
+    .map_err(internal_error("Failed to add new bookmark"))?;
 
-    sqlx::query_as!(
-        Bookmark,
-        "SELECT * FROM Bookmarks WHERE id = $1",
-        bookmark_id
-    )
-    .fetch_one(pool.as_ref())
-    .await
-    .map_err(|e| {
-        log::error!("Failed to retrieve bookmark after inserting it.\n{}", e);
-        error::ErrorInternalServerError("Bookmark was inserted, but failed to retrieve it")
-    })
-    .map(|b| HttpResponse::Created().json(b))
+    database::query_as::<Bookmark>("SELECT * FROM Bookmarks WHERE id = $1")
+        .bind(bookmark_id)
+        .fetch_one(pool.as_ref())
+        .await
+        .map_err(internal_error(
+            "Bookmark was inserted, but failed to retrieve it",
+        ))
+        .map(|b| HttpResponse::Created().json(b))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/documents/{document_id}/bookmarks",
+    tag = "bookmarks",
+    params(("document_id" = Uuid, Path, description = "Id of the document")),
+    responses(
+        (status = 200, description = "Bookmarks of the current user", body = [Bookmark]),
+    )
+)]
 async fn get_bookmarks(
-    pool: web::Data<PgPool>,
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
     document_id: web::Path<Uuid>,
-) -> AWResult<HttpResponse> {
-    sqlx::query_as!(
-        Bookmark,
-        "SELECT * FROM Bookmarks WHERE document = $1",
-        *document_id
-    )
-    .fetch_all(pool.as_ref())
-    .await
-    .map_err(|e| {
-        log::error!(
-            "Failed to retrieve bookmarks for document {}.\n{}",
-            document_id,
-            e
-        );
-        error::ErrorInternalServerError("Failed to retrieve bookmarks")
-    })
-    .map(|b: Vec<Bookmark>| HttpResponse::Ok().json(b))
+) -> Result<HttpResponse, ApiError> {
+    database::query_as("SELECT * FROM Bookmarks WHERE document = $1 AND owner = $2")
+        .bind(*document_id)
+        .bind(user.id)
+        .fetch_all(pool.as_ref())
+        .await
+        .map_err(internal_error("Failed to retrieve bookmarks"))
+        .map(|b: Vec<Bookmark>| HttpResponse::Ok().json(b))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
 struct DeleteBookmarkArguments {
     bookmark_id: Uuid,
     document_id: Uuid,
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/documents/{document_id}/bookmarks/{bookmark_id}",
+    tag = "bookmarks",
+    params(DeleteBookmarkArguments),
+    responses(
+        (status = 204, description = "Bookmark deleted"),
+        (status = 404, description = "Bookmark not found", body = ErrorResponse),
+    )
+)]
 async fn delete_bookmark(
-    pool: web::Data<PgPool>,
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
     data: web::Path<DeleteBookmarkArguments>,
-) -> AWResult<HttpResponse> {
+) -> Result<HttpResponse, ApiError> {
     log::info!("Deleting bookmark {}", data.bookmark_id);
-    let result: PgQueryResult = sqlx::query!(
-        "DELETE FROM Bookmarks WHERE document = $1 AND id = $2",
-        data.document_id,
-        data.bookmark_id
-    )
-    .execute(pool.as_ref())
-    .await
-    .map_err(|e| {
-        log::error!("Failed to delete bookmark {}.\n{}", data.bookmark_id, e);
-        error::ErrorInternalServerError("Failed to delete bookmark")
-    })?;
+    let result =
+        database::query("DELETE FROM Bookmarks WHERE document = $1 AND id = $2 AND owner = $3")
+            .bind(data.document_id)
+            .bind(data.bookmark_id)
+            .bind(user.id)
+            .execute(pool.as_ref())
+            .await
+            .map_err(internal_error("Failed to delete bookmark"))?;
 
     match result.rows_affected() {
         0 => {
             log::info!("Attempted to delete non-existant bookmark");
-            Ok(HttpResponse::NotFound().finish())
+            Err(ApiError::not_found("Bookmark not found"))
         }
         _ => Ok(HttpResponse::NoContent().finish()),
     }
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// `markdown`, `json` or `csv`
+    #[serde(default)]
+    #[param(inline)]
+    format: ExportFormat,
+}
+
+/// Responds with the exported bookmarks as a file to download
+fn export_response(documents: &[ExportedDocument], format: ExportFormat) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "bookmarks.{}",
+                format.extension()
+            ))],
+        })
+        .body(render(documents, format))
+}
+
+/// Exports the bookmarks of the current user in a document, with the text of each
+/// bookmarked page
+#[utoipa::path(
+    get,
+    path = "/api/documents/{document_id}/bookmarks/export",
+    tag = "bookmarks",
+    params(("document_id" = Uuid, Path, description = "Id of the document"), ExportQuery),
+    responses(
+        (status = 200, description = "The bookmarks as Markdown, JSON or CSV", body = [ExportedDocument]),
+        (status = 400, description = "The format is not supported", body = ErrorResponse),
+        (status = 404, description = "Document not found", body = ErrorResponse),
+    )
+)]
+async fn export_document_bookmarks(
+    pool: web::Data<DbPool>,
+    indexer: web::Data<Indexer>,
+    user: AuthenticatedUser,
+    document_id: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let document: Document = database::query_as(&format!("{SELECT_DOCUMENTS} WHERE d.id = $2"))
+        .bind(user.id)
+        .bind(*document_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .map_err(internal_error("Failed to retrieve document"))?
+        .ok_or_else(|| ApiError::not_found("Document not found"))?;
+    let bookmarks: Vec<Bookmark> =
+        database::query_as("SELECT * FROM Bookmarks WHERE document = $1 AND owner = $2")
+            .bind(*document_id)
+            .bind(user.id)
+            .fetch_all(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to retrieve bookmarks"))?;
+
+    // The export names the document even when it has no bookmarks yet
+    let without_bookmarks = ExportedDocument {
+        id: document.id,
+        title: document
+            .title
+            .clone()
+            .unwrap_or_else(|| document.name.clone()),
+        name: document.name.clone(),
+        bookmarks: Vec::new(),
+    };
+    let mut exported = collect_bookmarks(&indexer, vec![document], bookmarks)
+        .map_err(internal_error("Failed to read the bookmarked pages"))?;
+    if exported.is_empty() {
+        exported.push(without_bookmarks);
+    }
+    Ok(export_response(&exported, query.format))
+}
+
+/// Exports the bookmarks of the current user in every document of the library,
+/// grouped by document
+#[utoipa::path(
+    get,
+    path = "/api/bookmarks/export",
+    tag = "bookmarks",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The bookmarks as Markdown, JSON or CSV", body = [ExportedDocument]),
+        (status = 400, description = "The format is not supported", body = ErrorResponse),
+    )
+)]
+async fn export_library_bookmarks(
+    pool: web::Data<DbPool>,
+    indexer: web::Data<Indexer>,
+    user: AuthenticatedUser,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let documents: Vec<Document> =
+        database::query_as(&format!("{SELECT_DOCUMENTS} ORDER BY d.added_on"))
+            .bind(user.id)
+            .fetch_all(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to retrieve documents"))?;
+    let bookmarks: Vec<Bookmark> = database::query_as("SELECT * FROM Bookmarks WHERE owner = $1")
+        .bind(user.id)
+        .fetch_all(pool.get_ref())
+        .await
+        .map_err(internal_error("Failed to retrieve bookmarks"))?;
+
+    let exported = collect_bookmarks(&indexer, documents, bookmarks)
+        .map_err(internal_error("Failed to read the bookmarked pages"))?;
+    Ok(export_response(&exported, query.format))
+}
+
 pub fn setup_bookmarks_service() -> Scope {
     web::scope("/documents/{document_id}/bookmarks")
+        // Before the bookmark routes, which would take `export` for an id
+        .route("/export", web::get().to(export_document_bookmarks))
         .route("/{bookmark_id}", web::delete().to(delete_bookmark))
         .route("", web::post().to(add_bookmark))
         .route("", web::get().to(get_bookmarks))
 }
+
+pub fn setup_bookmark_export_service() -> Scope {
+    web::scope("/bookmarks").route("/export", web::get().to(export_library_bookmarks))
+}
@@ -1,16 +1,29 @@
+use crate::authentication::AuthenticatedUser;
+use crate::database::{self, DbPool, SELECT_DOCUMENTS};
+use crate::error::{internal_error, ApiError};
 use crate::models::Document;
-use actix_web::Result as AWResult;
-use actix_web::{error, web, HttpResponse};
-use sqlx::PgPool;
+use crate::telemetry::query_span;
+use actix_web::{web, HttpResponse};
+use tracing::Instrument;
 
-pub async fn list_documents(pool: web::Data<PgPool>) -> AWResult<HttpResponse> {
-    let rows: Vec<Document> = sqlx::query_as!(Document, "SELECT * FROM Documents")
+#[utoipa::path(
+    get,
+    path = "/api/documents",
+    tag = "documents",
+    responses(
+        (status = 200, description = "Every document in the library", body = [Document]),
+    )
+)]
+pub async fn list_documents(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let rows: Vec<Document> = database::query_as(SELECT_DOCUMENTS)
+        .bind(user.id)
         .fetch_all(pool.get_ref())
+        .instrument(query_span(pool.backend(), SELECT_DOCUMENTS))
         .await
-        .map_err(|e| {
-            println!("{}", e);
-            error::ErrorInternalServerError("Failed to fetch documents")
-        })?;
+        .map_err(internal_error("Failed to fetch documents"))?;
 
     Ok(HttpResponse::Ok().json(rows))
 }
@@ -1,44 +1,146 @@
-use actix_files::NamedFile;
-use actix_web::Result as AWResult;
-use actix_web::{
-    error,
-    http::header::{ContentDisposition, DispositionParam, DispositionType},
-    web,
-};
-use sqlx::PgPool;
+use actix_web::http::header::DispositionType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use tracing::Instrument;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-use crate::{configuration::Settings, models::Document};
+use super::serve::{serve_document, serve_document_with_notes, Download};
+use crate::{
+    authentication::AuthenticatedUser,
+    configuration::Settings,
+    database::{self, DbPool, SELECT_DOCUMENTS},
+    error::{internal_error, ApiError},
+    models::{Bookmark, Document, Highlight, HighlightArea},
+    storage::DocumentStorage,
+    telemetry::query_span,
+};
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetDocumentQuery {
+    /// Ask for the document to be displayed by the browser, rather than downloaded
+    #[serde(default)]
+    inline: bool,
+    /// Add the bookmarks of the current user to the outline of the PDF, so that
+    /// desktop readers show them. Range and conditional requests are not supported
+    /// for this copy, and documents protected by a password are refused.
+    #[serde(default)]
+    with_bookmarks: bool,
+    /// Add the bookmarks of the current user which highlight an area as highlight
+    /// annotations, with the same limits as `with_bookmarks`. Highlights imported
+    /// from the PDF are left out, as the stored file already has them.
+    #[serde(default)]
+    with_highlights: bool,
+}
+
+/// Downloads the contents of a document. Range and conditional requests are supported.
+#[utoipa::path(
+    get,
+    path = "/api/documents/{id}",
+    tag = "documents",
+    params(("id" = Uuid, Path, description = "Id of the document"), GetDocumentQuery),
+    responses(
+        (status = 200, description = "The PDF file, with the bookmarks and highlights added when asked for", body = String, content_type = "application/pdf"),
+        (status = 206, description = "Part of the PDF file", body = String, content_type = "application/pdf"),
+        (status = 304, description = "The client already has the current version"),
+        (status = 404, description = "Document not found", body = ErrorResponse),
+        (status = 409, description = "Bookmarks or highlights were asked for, but the document is protected by a password", body = ErrorResponse),
+        (status = 416, description = "The requested range is outside of the document", body = ErrorResponse),
+    )
+)]
 pub async fn get_document(
-    pool: web::Data<PgPool>,
+    pool: web::Data<DbPool>,
     config: web::Data<Settings>,
+    storage: web::Data<dyn DocumentStorage>,
+    user: AuthenticatedUser,
     id: web::Path<Uuid>,
-) -> AWResult<NamedFile> {
-    println!("Looking up file {}", id);
-    let document: Document = sqlx::query_as("SELECT * FROM Documents WHERE id = $1")
+    query: web::Query<GetDocumentQuery>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    log::debug!("Looking up file {}", id);
+    let statement = format!("{SELECT_DOCUMENTS} WHERE d.id = $2");
+    let document: Document = database::query_as(&statement)
+        .bind(user.id)
         .bind(*id)
         .fetch_optional(pool.get_ref())
+        .instrument(query_span(pool.backend(), &statement))
         .await
-        .map_err(|e| {
-            println!("{}", e);
-            error::ErrorInternalServerError("Failed to make query")
-        })?
-        .ok_or_else(|| error::ErrorNotFound("Not found"))?;
-
-    let path = config
-        .documents_storage_path()
-        .join(id.to_string())
-        .with_extension("pdf");
-    println!("Reading file from {:?}", path);
-    let file = NamedFile::open(path)
-        .map_err(|_| error::ErrorInternalServerError("Unable to read file from disk"))?;
-
-    let cd = ContentDisposition {
-        parameters: vec![DispositionParam::Filename(document.name)],
-        disposition: DispositionType::Attachment,
+        .map_err(internal_error("Failed to look up document"))?
+        .ok_or_else(|| ApiError::not_found("Document not found"))?;
+
+    let disposition = match query.inline {
+        true => DispositionType::Inline,
+        false => DispositionType::Attachment,
     };
-    let file = file.set_content_disposition(cd);
+    let download = Download {
+        id: document.id,
+        name: document.name,
+        content_hash: document.content_hash,
+        added_on: document.added_on,
+        disposition,
+    };
+
+    if query.with_bookmarks || query.with_highlights {
+        let mut bookmarks: Vec<Bookmark> = Vec::new();
+        if query.with_bookmarks {
+            bookmarks = database::query_as(
+                "SELECT * FROM Bookmarks WHERE document = $1 AND owner = $2 ORDER BY page, added_on",
+            )
+            .bind(*id)
+            .bind(user.id)
+            .fetch_all(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to retrieve bookmarks"))?;
+        }
+        let mut highlights = Vec::new();
+        if query.with_highlights {
+            highlights = fetch_highlights(&pool, *id, user.id).await?;
+        }
+        return serve_document_with_notes(storage.get_ref(), download, bookmarks, highlights).await;
+    }
+
+    serve_document(
+        &request,
+        &pool,
+        storage.get_ref(),
+        &config.document_cache_control,
+        download,
+    )
+    .await
+}
+
+/// Highlights the owner added to the document, without those imported from it
+async fn fetch_highlights(
+    pool: &DbPool,
+    document: Uuid,
+    owner: Uuid,
+) -> Result<Vec<Highlight>, ApiError> {
+    let rows: Vec<(i32, String, String)> = database::query_as(
+        "SELECT page, description, highlight_area FROM Bookmarks
+        WHERE document = $1 AND owner = $2 AND highlight_area IS NOT NULL AND NOT imported
+        ORDER BY page, added_on",
+    )
+    .bind(document)
+    .bind(owner)
+    .fetch_all(pool)
+    .await
+    .map_err(internal_error("Failed to retrieve highlights"))?;
 
-    Ok(file)
+    Ok(rows
+        .into_iter()
+        .filter_map(
+            |(page, description, area)| match HighlightArea::parse(&area) {
+                Some(area) => Some(Highlight {
+                    page,
+                    description,
+                    area,
+                }),
+                None => {
+                    log::warn!("Skipping a highlight with a malformed area {area:?}");
+                    None
+                }
+            },
+        )
+        .collect())
 }
@@ -1,7 +1,8 @@
-mod get_all;
-mod get_by_id;
-mod patch;
-mod post;
+pub(crate) mod get_all;
+pub(crate) mod get_by_id;
+pub(crate) mod patch;
+pub(crate) mod post;
+mod serve;
 
 use actix_web::{web, Scope};
 
@@ -10,6 +11,8 @@ use self::{
     post::upload_document,
 };
 
+pub(crate) use serve::{serve_document, Download};
+
 pub fn setup_documents_service() -> Scope {
     web::scope("/documents")
         .route("{id}", web::get().to(get_document))
@@ -1,33 +1,192 @@
-use actix_web::{error, web, HttpResponse};
-use sqlx::{postgres::PgQueryResult, PgPool};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
 use uuid::Uuid;
 
+use crate::authentication::AuthenticatedUser;
+use crate::database::{self, DbPool};
+use crate::error::ApiError;
 use crate::models::UpdateDocumentRequest;
-use actix_web::Result as AWResult;
 
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_METADATA_LENGTH: usize = 1000;
+const MAX_NOTES_LENGTH: usize = 20_000;
+
+/// Changes the details of a document, and the reading progress of the current user
+#[utoipa::path(
+    patch,
+    path = "/api/documents/{id}",
+    tag = "documents",
+    params(("id" = Uuid, Path, description = "Id of the document")),
+    request_body = UpdateDocumentRequest,
+    responses(
+        (status = 204, description = "Document updated"),
+        (status = 400, description = "Invalid update", body = ErrorResponse),
+        (status = 403, description = "Only the owner can change the details of a document", body = ErrorResponse),
+        (status = 404, description = "Document not found", body = ErrorResponse),
+    )
+)]
 pub async fn update_document_status(
-    pool: web::Data<PgPool>,
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
     update_request: web::Json<UpdateDocumentRequest>,
     id: web::Path<Uuid>,
-) -> AWResult<HttpResponse> {
-    println!(
-        "Setting current page of {} to {}",
-        id, update_request.current_page
-    );
-    let result: PgQueryResult = sqlx::query("UPDATE Documents SET current_page = $1 WHERE id = $2")
-        .bind(update_request.current_page)
+) -> Result<HttpResponse, ApiError> {
+    let update_request = validate_update_request(update_request.into_inner())?;
+    log::info!("Updating document {}", id);
+
+    let mut tx = pool
+        .begin()
+        .await
+        .context("Failed to begin database transaction")?;
+
+    let (owner,): (Option<Uuid>,) = database::query_as("SELECT owner FROM Documents WHERE id = $1")
+        .bind(*id)
+        .fetch_optional(&mut tx)
+        .await
+        .context("Failed to look up document")?
+        .ok_or_else(|| ApiError::not_found("Document not found"))?;
+
+    let UpdateDocumentRequest {
+        current_page,
+        name,
+        title,
+        author,
+        notes,
+        collection,
+    } = update_request;
+
+    if name.is_some()
+        || title.is_some()
+        || author.is_some()
+        || notes.is_some()
+        || collection.is_some()
+    {
+        if owner.is_some() && owner != Some(user.id) && !user.is_admin {
+            return Err(ApiError::forbidden(
+                "Only the owner of a document can change its details",
+            ));
+        }
+
+        database::query(
+            "UPDATE Documents SET
+                name = COALESCE($1, name),
+                title = CASE WHEN CAST($2 AS TEXT) IS NULL THEN title ELSE NULLIF($2, '') END,
+                author = CASE WHEN CAST($3 AS TEXT) IS NULL THEN author ELSE NULLIF($3, '') END,
+                notes = CASE WHEN CAST($4 AS TEXT) IS NULL THEN notes ELSE NULLIF($4, '') END,
+                collection = CASE WHEN CAST($5 AS TEXT) IS NULL THEN collection ELSE NULLIF($5, '') END
+            WHERE id = $6",
+        )
+        .bind(name)
+        .bind(title)
+        .bind(author)
+        .bind(notes)
+        .bind(collection)
+        .bind(*id)
+        .execute(&mut tx)
+        .await
+        .context("Failed to update document")?;
+    }
+
+    if let Some(page) = current_page {
+        database::query(
+            "INSERT INTO ReadingProgress (reader, document, current_page) VALUES ($1, $2, $3)
+            ON CONFLICT (reader, document) DO UPDATE SET current_page = EXCLUDED.current_page",
+        )
+        .bind(user.id)
+        .bind(*id)
+        .bind(page)
+        .execute(&mut tx)
+        .await
+        .context("Failed to update reading progress")?;
+
+        database::query(
+            "INSERT INTO PageEvents (id, document, page, reader) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(Uuid::new_v4())
         .bind(*id)
-        .execute(pool.get_ref())
+        .bind(page)
+        .bind(user.id)
+        .execute(&mut tx)
         .await
-        .map_err(|e| {
-            println!("{}", e);
-            error::ErrorInternalServerError("Failed to make query")
-        })?;
-
-    match result.rows_affected() {
-        0 => Err(error::ErrorNotFound(
-            "Document could not be updated because it was not found",
-        )),
-        _ => Ok(HttpResponse::NoContent().finish()),
+        .context("Failed to record page event")?;
     }
+
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn validate_update_request(
+    mut request: UpdateDocumentRequest,
+) -> Result<UpdateDocumentRequest, ApiError> {
+    if request.current_page.is_none()
+        && request.name.is_none()
+        && request.title.is_none()
+        && request.author.is_none()
+        && request.notes.is_none()
+        && request.collection.is_none()
+    {
+        return Err(ApiError::validation(
+            "Request did not contain any fields to update",
+        ));
+    }
+
+    if let Some(page) = request.current_page {
+        if page < 1 {
+            return Err(ApiError::invalid_field(
+                "current_page",
+                "Current page must be at least 1",
+            ));
+        }
+    }
+
+    if let Some(name) = request.name.take() {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::invalid_field("name", "Name must not be empty"));
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(ApiError::invalid_field(
+                "name",
+                format!("Name must be at most {MAX_NAME_LENGTH} characters"),
+            ));
+        }
+        if name
+            .chars()
+            .any(|c| c.is_control() || c == '/' || c == '\\')
+        {
+            return Err(ApiError::invalid_field(
+                "name",
+                "Name must not contain control characters or path separators",
+            ));
+        }
+        request.name = Some(name.to_owned());
+    }
+
+    request.title = validate_metadata_field("Title", request.title, MAX_METADATA_LENGTH)?;
+    request.author = validate_metadata_field("Author", request.author, MAX_METADATA_LENGTH)?;
+    request.notes = validate_metadata_field("Notes", request.notes, MAX_NOTES_LENGTH)?;
+    request.collection =
+        validate_metadata_field("Collection", request.collection, MAX_NAME_LENGTH)?;
+
+    Ok(request)
+}
+
+fn validate_metadata_field(
+    field_name: &str,
+    value: Option<String>,
+    max_length: usize,
+) -> Result<Option<String>, ApiError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let value = value.trim();
+    if value.chars().count() > max_length {
+        return Err(ApiError::invalid_field(
+            &field_name.to_lowercase(),
+            format!("{field_name} must be at most {max_length} characters"),
+        ));
+    }
+
+    Ok(Some(value.to_owned()))
 }
@@ -1,76 +1,301 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use actix_multipart::Multipart;
 use actix_web::web;
 use actix_web::HttpResponse;
-use actix_web::ResponseError;
 use anyhow::Context;
 use futures::StreamExt;
 use futures::TryStreamExt;
 use once_cell::sync::Lazy;
-use pdfium_render::prelude::Pdfium;
-use sqlx::PgPool;
-use sqlx::{Postgres, Transaction};
+use pdfium_render::prelude::{
+    PdfBookmark, PdfDestination, PdfDocument, PdfPageAnnotationCommon, PdfPageAnnotationType,
+    Pdfium, PdfiumError, PdfiumInternalError,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use tokio::io::AsyncWriteExt;
+use tracing::Instrument;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
+use crate::authentication::AuthenticatedUser;
 use crate::configuration::Settings;
-use crate::error::error_chain_fmt;
+use crate::database::{self, DbPool, DbTransaction};
+use crate::encryption::{Encryptor, FileEncryption};
+use crate::error::{error_chain_fmt, internal_error, ApiError, ErrorCode};
 use crate::indexer::Indexer;
 use crate::indexer::IndexerError;
+use crate::metrics::METRICS;
+use crate::models::HighlightArea;
+use crate::shutdown::Shutdown;
+use crate::telemetry::query_span;
+use crate::usage::{check_quota, Quota, QuotaError, QuotaExceeded};
 
+/// A document which has been written to storage, but not yet added to the database
+struct SavedDocument {
+    filename: String,
+    path: PathBuf,
+    content_hash: String,
+    byte_size: u64,
+}
+
+#[tracing::instrument(skip_all, fields(document_id = %id))]
 async fn insert_document<'a>(
     id: Uuid,
-    filename: String,
-    transaction: &mut Transaction<'a, Postgres>,
+    document: &SavedDocument,
+    owner: Uuid,
+    encrypted_password: Option<&str>,
+    transaction: &mut DbTransaction<'a>,
 ) -> Result<(), AddDocumentError> {
-    println!("Saving file {} in database", filename);
-    sqlx::query!(
-        "INSERT INTO Documents (id, name) VALUES ($1, $2)",
-        id,
-        filename
+    log::debug!("Saving file {} in database", document.filename);
+    let query = database::query(
+        "INSERT INTO Documents (id, name, owner, content_hash, encrypted_password, byte_size)
+        VALUES ($1, $2, $3, $4, $5, $6)",
     )
-    .execute(transaction)
-    .await
-    .context("Failed to insert document")?;
+    .bind(id)
+    .bind(&document.filename)
+    .bind(owner)
+    .bind(&document.content_hash)
+    .bind(encrypted_password)
+    .bind(document.byte_size as i64);
+    let span = query_span(transaction.backend(), query.sql());
+    query
+        .execute(transaction)
+        .instrument(span)
+        .await
+        .context("Failed to insert document")?;
 
     Ok(())
 }
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadQuery {
+    /// Add the highlights, sticky notes and drawings already in the PDFs as bookmarks
+    #[serde(default)]
+    import_annotations: bool,
+    /// Add the entries of the outline of the PDFs as bookmarks
+    #[serde(default)]
+    import_outline: bool,
+}
+
+/// Adds the notes found in a document as bookmarks of the uploader
+pub(crate) async fn insert_imported_bookmarks<'a>(
+    id: Uuid,
+    annotations: &[FoundAnnotation],
+    owner: Uuid,
+    transaction: &mut DbTransaction<'a>,
+) -> Result<(), AddDocumentError> {
+    for annotation in annotations {
+        database::query(
+            "INSERT INTO Bookmarks (id, description, page, document, owner, imported, highlight_area)
+            VALUES ($1, $2, $3, $4, $5, TRUE, $6)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(&annotation.description)
+        .bind(annotation.page)
+        .bind(id)
+        .bind(owner)
+        .bind(annotation.area.map(|area| area.to_string()))
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to add imported bookmark")?;
+    }
+    if !annotations.is_empty() {
+        log::info!(
+            "Imported {} bookmarks from document {}",
+            annotations.len(),
+            id
+        );
+    }
+
+    Ok(())
+}
+
+/// Longest accepted document password, in bytes
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+/// Reads a text part of the multipart body, such as the password
+async fn read_text_field(
+    field: &mut actix_multipart::Field,
+    name: &str,
+) -> Result<String, ApiError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| ApiError::bad_request(e.to_string()))?;
+        value.extend_from_slice(&chunk);
+        if value.len() > MAX_PASSWORD_LENGTH {
+            return Err(ApiError::invalid_field(name, "Value is too long"));
+        }
+    }
+    String::from_utf8(value).map_err(|_| ApiError::invalid_field(name, "Value is not valid UTF-8"))
+}
+
+/// Adds every file in the multipart body to the library, and indexes its contents.
+/// Annotations and outline entries in the files can be added as bookmarks.
+///
+/// Files protected by a password are opened with the `password` part, which must
+/// come before every file, as files are stored while they are received. When `keep_password` is `true` the password is kept, encrypted
+/// with the server key, so that pages of the document can be rendered later.
+///
+/// The upload is refused as soon as it goes over the quotas of the user, if any.
+#[utoipa::path(
+    post,
+    path = "/api/documents",
+    tag = "documents",
+    params(UploadQuery),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "Documents added"),
+        (status = 400, description = "A file without a name was uploaded, or the password came after a file", body = ErrorResponse),
+        (status = 413, description = "The documents would go over the quotas of the user", body = ErrorResponse),
+        (status = 422, description = "A file is protected by a password, which was not given or is wrong", body = ErrorResponse),
+        (status = 503, description = "The server is shutting down", body = ErrorResponse),
+    )
+)]
+#[tracing::instrument(
+    skip_all,
+    fields(user = %user.id, document_count = tracing::field::Empty, byte_size = tracing::field::Empty)
+)]
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_document(
     indexer: web::Data<Indexer>,
-    pool: web::Data<PgPool>,
+    pool: web::Data<DbPool>,
     pdfium: web::Data<&Lazy<Pdfium>>,
     config: web::Data<Settings>,
+    shutdown: web::Data<Shutdown>,
+    encryption: web::Data<FileEncryption>,
+    user: AuthenticatedUser,
+    query: web::Query<UploadQuery>,
     mut payload: Multipart,
-) -> Result<HttpResponse, AddDocumentError> {
+) -> Result<HttpResponse, ApiError> {
+    let import = AnnotationImport {
+        annotations: query.import_annotations,
+        outline: query.import_outline,
+    };
+    let mut upload = shutdown.into_inner().begin_upload(user.id)?;
+    let mut quota = Quota::load(pool.get_ref(), config.get_ref(), user.id)
+        .await
+        .map_err(internal_error("Failed to compute storage usage"))?;
     log::info!("Handling incoming documents");
     let mut saved: Vec<Uuid> = Vec::new();
+    let mut read_documents: Vec<(Uuid, Vec<String>)> = Vec::new();
+    let mut byte_size = 0;
     let mut tx = pool
         .begin()
         .await
         .context("Failed to begin database transaction")?;
 
+    let mut password: Option<String> = None;
+    let mut keep_password = false;
+    let mut files_started = false;
     while let Ok(Some(mut field)) = payload.try_next().await {
+        match field.content_disposition().get_name() {
+            Some(name @ ("password" | "keep_password")) if files_started => {
+                delete_documents(&saved, config.get_ref());
+                return Err(ApiError::invalid_field(
+                    name,
+                    format!("The {name} part must come before the files it applies to"),
+                ));
+            }
+            Some("password") => {
+                password = Some(read_text_field(&mut field, "password").await?);
+                continue;
+            }
+            Some("keep_password") => {
+                keep_password = read_text_field(&mut field, "keep_password").await? == "true";
+                continue;
+            }
+            _ => files_started = true,
+        }
+        let encrypted_password = match (&password, keep_password) {
+            (Some(password), true) => {
+                let key = encryption.key().ok_or_else(|| {
+                    ApiError::invalid_field(
+                        "keep_password",
+                        "Passwords cannot be kept, as no server key is configured",
+                    )
+                })?;
+                Some(
+                    key.encrypt_string(password)
+                        .context("Failed to encrypt the password")?,
+                )
+            }
+            _ => None,
+        };
+        if let Err(e) = quota.add_document() {
+            log::info!("Refusing document over the quota. Unwinding transaction.");
+            delete_documents(&saved, config.get_ref());
+            return Err(AddDocumentError::from(e).into());
+        }
         let id = uuid::Uuid::new_v4();
 
         log::debug!("Writing document to disk");
-        let res = match save_document_to_disk(&id, &mut field, config.get_ref()).await {
+        let file_path = config
+            .documents_storage_path()
+            .join(id.to_string())
+            .with_extension("pdf");
+        upload.add_file(file_path.clone());
+        let saving = save_document_to_disk(&id, &file_path, &mut field, &encryption, &mut quota);
+        let res = match saving.await {
+            Err(e @ AddDocumentError::QuotaExceeded(_)) => {
+                log::info!("Refusing document over the quota. Unwinding transaction.");
+                delete_documents(&saved, config.get_ref());
+                return Err(e.into());
+            }
             Err(e) => {
                 log::error!("Failed to write document to disk. Unwinding transaction.");
                 delete_documents(&saved, config.get_ref());
-                return Err(e);
+                return Err(e.into());
             }
             Ok(f) => f,
         };
 
         saved.push(id);
-        if let Err(e) = insert_document(id, res.0, &mut tx).await {
+        byte_size += res.byte_size;
+        if let Err(e) =
+            insert_document(id, &res, user.id, encrypted_password.as_deref(), &mut tx).await
+        {
             log::error!("Failed insert document in database. Unwinding transaction.");
             delete_documents(&saved, config.get_ref());
-            return Err(e);
+            return Err(e.into());
+        }
+        if let Err(e) = check_quota(&mut tx, config.get_ref(), user.id).await {
+            log::info!("Refusing document over the quota. Unwinding transaction.");
+            delete_documents(&saved, config.get_ref());
+            return Err(AddDocumentError::from(e).into());
         }
 
-        index_pdf_file(pdfium.as_ref(), &indexer, &res.1, &id).await?;
+        let file = PdfFile {
+            path: &res.path,
+            encryption: &encryption,
+            password: password.as_deref(),
+        };
+        let read = read_pdf_file(pdfium.as_ref(), &file, &id, import)?;
+        let statement = "UPDATE Documents SET page_count = $1 WHERE id = $2";
+        let span = query_span(tx.backend(), statement);
+        database::query(statement)
+            .bind(read.pages.len() as i32)
+            .bind(id)
+            .execute(&mut tx)
+            .instrument(span)
+            .await
+            .context("Failed to store page count")?;
+        insert_imported_bookmarks(id, &read.annotations, user.id, &mut tx).await?;
+        read_documents.push((id, read.pages));
+    }
+
+    // The pages are only committed to the index after the documents are in the
+    // database, so that a failed upload leaves nothing behind for searches to find.
+    // Until then the writer rolls them back when it is dropped.
+    let mut writer = indexer.get_writer().await.map_err(AddDocumentError::from)?;
+    for (id, pages) in &read_documents {
+        for (page_nr, text) in pages.iter().enumerate() {
+            writer
+                .index_page(id, page_nr as u64 + 1, text)
+                .map_err(AddDocumentError::from)?;
+        }
     }
 
     let commit_result = tx.commit().await;
@@ -80,22 +305,43 @@ pub async fn upload_document(
         }
         commit_result.context("Failed to commit transaction")?;
     }
+    if let Err(e) = writer.commit() {
+        // The documents are stored and can be read, they are only missing from searches
+        log::error!("Failed to commit the index of uploaded documents: {:?}", e);
+    }
+
+    upload.finish();
 
+    let span = tracing::Span::current();
+    span.record("document_count", saved.len() as u64);
+    span.record("byte_size", byte_size);
     log::debug!("Document successfully added");
     Ok(HttpResponse::Created().finish())
 }
 
+/// Writes an uploaded file to storage, encrypted when encryption is enabled.
+/// Every chunk is counted towards the quota before it is written.
+#[tracing::instrument(skip(file_path, field, encryption, quota), fields(document_id = %id, byte_size = tracing::field::Empty))]
 async fn save_document_to_disk(
     id: &Uuid,
+    file_path: &Path,
     field: &mut actix_multipart::Field,
-    config: &Settings,
-) -> Result<(String, PathBuf), AddDocumentError> {
-    let base_path = config.documents_storage_path();
-    let file_path = base_path.join(id.to_string()).with_extension("pdf");
-
-    let mut fd = tokio::fs::File::create(&file_path)
+    encryption: &FileEncryption,
+    quota: &mut Quota,
+) -> Result<SavedDocument, AddDocumentError> {
+    let mut fd = tokio::fs::File::create(file_path)
         .await
         .context("Failed to create file")?;
+    let mut encryptor = match encryption.write_key() {
+        Some(key) => {
+            let (encryptor, header) = Encryptor::new(key).context("Failed to encrypt")?;
+            fd.write_all(&header)
+                .await
+                .context("Failed to write chunk to storage")?;
+            Some(encryptor)
+        }
+        None => None,
+    };
 
     let filename = field
         .content_disposition()
@@ -103,16 +349,43 @@ async fn save_document_to_disk(
         .ok_or(AddDocumentError::MissingFilename)?
         .to_owned();
 
-    println!("Writing file {} to disk", filename);
+    log::debug!("Writing file {} to disk", filename);
 
+    let mut hasher = Sha256::new();
+    let mut byte_size = 0;
     while let Some(chunk) = field.next().await {
         let chunk = chunk.context("Failed to read chunk")?;
-        fd.write_all(&chunk)
+        quota.add_bytes(chunk.len() as u64)?;
+        hasher.update(&chunk);
+        METRICS.upload_bytes.inc_by(chunk.len() as u64);
+        byte_size += chunk.len() as u64;
+        let written = match &mut encryptor {
+            Some(encryptor) => {
+                let sealed = encryptor.update(&chunk).context("Failed to encrypt")?;
+                fd.write_all(&sealed).await
+            }
+            None => fd.write_all(&chunk).await,
+        };
+        written.context("Failed to write chunk to storage")?;
+    }
+    if let Some(encryptor) = encryptor {
+        let sealed = encryptor.finish().context("Failed to encrypt")?;
+        fd.write_all(&sealed)
             .await
             .context("Failed to write chunk to storage")?;
     }
+    fd.flush()
+        .await
+        .context("Failed to write chunk to storage")?;
 
-    Ok((filename, file_path))
+    tracing::Span::current().record("byte_size", byte_size);
+
+    Ok(SavedDocument {
+        filename,
+        path: file_path.to_owned(),
+        content_hash: hex::encode(hasher.finalize()),
+        byte_size,
+    })
 }
 
 fn delete_documents(document_ids: &[Uuid], config: &Settings) {
@@ -123,7 +396,7 @@ fn delete_documents(document_ids: &[Uuid], config: &Settings) {
     let documents_path = config.documents_storage_path();
     let documents_on_disk = match std::fs::read_dir(documents_path) {
         Err(e) => {
-            println!("Failed to read documents directory {}", e);
+            log::error!("Failed to read documents directory {}", e);
             return;
         }
         Ok(r) => r,
@@ -143,31 +416,220 @@ fn delete_documents(document_ids: &[Uuid], config: &Settings) {
         .collect::<Vec<_>>();
 
     for failed in failed_deletes {
-        println!("Failed to delete: {}", failed);
+        log::error!("Failed to delete: {}", failed);
     }
 }
-pub async fn index_pdf_file(
+/// Which notes already in a PDF are added as bookmarks when it is indexed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnnotationImport {
+    /// Highlights, sticky notes and drawings
+    pub annotations: bool,
+    pub outline: bool,
+}
+
+/// A note found in a PDF, to be added as a bookmark
+pub struct FoundAnnotation {
+    pub page: i32,
+    pub description: String,
+    /// Area of the page covered by a highlight
+    pub area: Option<HighlightArea>,
+}
+
+/// The text and notes of a document, before its pages are added to the index
+pub struct ReadDocument {
+    pub pages: Vec<String>,
+    /// Notes found in the document, ordered by page
+    pub annotations: Vec<FoundAnnotation>,
+}
+
+/// A stored PDF, with what it takes to read it
+#[derive(Clone, Copy)]
+pub struct PdfFile<'a> {
+    pub path: &'a Path,
+    pub encryption: &'a FileEncryption,
+    /// Password of a protected document
+    pub password: Option<&'a str>,
+}
+
+/// Reads the text of every page and the notes of a document, without adding
+/// anything to the index
+#[tracing::instrument(
+    skip(pdfium, file),
+    fields(document_id = %doc_id, page_count = tracing::field::Empty)
+)]
+pub fn read_pdf_file(
     pdfium: &Pdfium,
-    indexer: &Indexer,
-    file: &PathBuf,
+    file: &PdfFile<'_>,
     doc_id: &Uuid,
-) -> Result<(), AddDocumentError> {
-    log::info!("Indexing new document {}", doc_id);
-    let pdf = pdfium
-        .load_pdf_from_file(file, None)
-        .context("Failed to load pdf file")?;
+    import: AnnotationImport,
+) -> Result<ReadDocument, AddDocumentError> {
+    log::info!("Reading new document {}", doc_id);
+    let started = Instant::now();
+    let pdf = open_pdf(pdfium, file)?;
+    let pages = extract_pages(&pdf)?;
+    let annotations = extract_annotations(&pdf, import);
 
-    let mut writer = indexer.get_writer().await?;
+    let page_count = pages.len();
+    tracing::Span::current().record("page_count", page_count);
+
+    let duration = started.elapsed().as_secs_f64();
+    METRICS.indexing_duration.observe(duration);
+    if duration > 0.0 {
+        METRICS
+            .indexing_pages_per_second
+            .observe(page_count as f64 / duration);
+    }
+
+    Ok(ReadDocument { pages, annotations })
+}
+
+/// Opens a document, telling a missing or wrong password apart from other failures
+pub fn open_pdf<'a>(
+    pdfium: &'a Pdfium,
+    file: &PdfFile<'a>,
+) -> Result<PdfDocument<'a>, AddDocumentError> {
+    let stored = file
+        .encryption
+        .open(file.path)
+        .context("Failed to open pdf file")?;
+    pdfium
+        .load_pdf_from_reader(stored, file.password)
+        .map_err(|e| match e {
+            PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+                AddDocumentError::IncorrectPassword
+            }
+            e => AddDocumentError::UnknownError(
+                anyhow::Error::new(e).context("Failed to load pdf file"),
+            ),
+        })
+}
+
+/// Reads the text of every page. This is the slow part of indexing, and does not
+/// need the index, so it can run for several documents at once.
+pub fn extract_pages(pdf: &PdfDocument<'_>) -> Result<Vec<String>, AddDocumentError> {
+    pdf.pages()
+        .iter()
+        .map(|p| Ok(p.text().context("Failed to read pdf file")?.all()))
+        .collect()
+}
+
+/// Reads the notes which other readers left in a document
+pub fn extract_annotations(
+    pdf: &PdfDocument<'_>,
+    import: AnnotationImport,
+) -> Vec<FoundAnnotation> {
+    let mut found = Vec::new();
+    if import.annotations {
+        for (index, page) in pdf.pages().iter().enumerate() {
+            for annotation in page.annotations().iter() {
+                let contents = non_empty(annotation.contents());
+                let description = match annotation.annotation_type() {
+                    PdfPageAnnotationType::Highlight => contents
+                        .or_else(|| non_empty(page.text().ok()?.for_annotation(&annotation).ok()))
+                        .unwrap_or_else(|| "Highlight".to_owned()),
+                    PdfPageAnnotationType::Text => contents.unwrap_or_else(|| "Note".to_owned()),
+                    PdfPageAnnotationType::Ink => contents.unwrap_or_else(|| "Drawing".to_owned()),
+                    _ => continue,
+                };
+                let area = match annotation.annotation_type() {
+                    PdfPageAnnotationType::Highlight => {
+                        annotation.bounds().ok().map(|bounds| HighlightArea {
+                            left: bounds.left().value,
+                            bottom: bounds.bottom().value,
+                            right: bounds.right().value,
+                            top: bounds.top().value,
+                        })
+                    }
+                    _ => None,
+                };
+                found.push(FoundAnnotation {
+                    page: index as i32 + 1,
+                    description,
+                    area,
+                });
+            }
+        }
+    }
+    if import.outline {
+        found.extend(read_outline(pdf));
+    }
+    found.sort_by_key(|a| a.page);
+
+    found
+}
+
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|t| t.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|t| !t.is_empty())
+}
+
+/// Most outline entries read from a document, as a malformed outline can link
+/// back to itself
+const MAX_OUTLINE_ENTRIES: usize = 10_000;
+
+/// Reads the title and page of every entry of the outline, in the order readers list
+/// them
+fn read_outline(pdf: &PdfDocument<'_>) -> Vec<FoundAnnotation> {
+    let mut entries = Vec::new();
+    let mut visited = 0;
+    let mut pending = vec![pdf.bookmarks().root()];
+    while let Some(bookmark) = pending.pop() {
+        let Some(bookmark) = bookmark else {
+            continue;
+        };
+        visited += 1;
+        if visited > MAX_OUTLINE_ENTRIES {
+            log::warn!("Outline has too many entries, reading only the first ones");
+            break;
+        }
+        // Children come before the next sibling
+        pending.push(bookmark.next_sibling());
+        pending.push(bookmark.first_child());
 
-    for (page_nr, p) in pdf.pages().iter().enumerate() {
-        let text = p.text().context("Failed to read pdf file")?.all();
-        writer.index_page(doc_id, page_nr as u64 + 1, &text)?;
+        let Some(description) = non_empty(bookmark.title()) else {
+            continue;
+        };
+        if let Some(index) = outline_page_index(&bookmark) {
+            entries.push(FoundAnnotation {
+                page: i32::from(index) + 1,
+                description,
+                area: None,
+            });
+        }
+    }
+
+    entries
+}
+
+/// The page an outline entry goes to, either directly or through a go to action
+fn outline_page_index(bookmark: &PdfBookmark<'_>) -> Option<u16> {
+    let page_index = |destination: PdfDestination<'_>| destination.page_index().ok();
+    match bookmark.destination() {
+        Some(destination) => page_index(destination),
+        None => bookmark
+            .action()?
+            .as_local_destination_action()?
+            .destination()
+            .ok()
+            .and_then(page_index),
+    }
+}
+
+/// Adds the text extracted from a document to the index, and returns the page count
+pub async fn index_pages(
+    indexer: &Indexer,
+    doc_id: &Uuid,
+    pages: &[String],
+) -> Result<i32, AddDocumentError> {
+    let mut writer = indexer.get_writer().await?;
+    for (page_nr, text) in pages.iter().enumerate() {
+        writer.index_page(doc_id, page_nr as u64 + 1, text)?;
     }
 
     writer.commit()?;
     log::info!("Index of document {} committed", doc_id);
 
-    Ok(())
+    Ok(pages.len() as i32)
 }
 
 #[derive(thiserror::Error)]
@@ -178,6 +640,10 @@ pub enum AddDocumentError {
     IndexingError(#[from] IndexerError),
     #[error("Uploaded file must have a name")]
     MissingFilename,
+    #[error("The document is protected by a password, which was not given or is wrong")]
+    IncorrectPassword,
+    #[error(transparent)]
+    QuotaExceeded(#[from] QuotaExceeded),
 }
 
 impl std::fmt::Debug for AddDocumentError {
@@ -186,11 +652,26 @@ impl std::fmt::Debug for AddDocumentError {
     }
 }
 
-impl ResponseError for AddDocumentError {
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        match self {
-            Self::MissingFilename => actix_web::http::StatusCode::BAD_REQUEST,
-            _ => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+impl From<QuotaError> for AddDocumentError {
+    fn from(e: QuotaError) -> Self {
+        match e {
+            QuotaError::Exceeded(e) => Self::QuotaExceeded(e),
+            QuotaError::UnknownError(e) => Self::UnknownError(e),
+        }
+    }
+}
+
+impl From<AddDocumentError> for ApiError {
+    fn from(e: AddDocumentError) -> Self {
+        match e {
+            AddDocumentError::MissingFilename => ApiError::validation(e.to_string()),
+            AddDocumentError::IncorrectPassword => {
+                ApiError::new(ErrorCode::PasswordRequired, e.to_string())
+            }
+            AddDocumentError::QuotaExceeded(_) => {
+                ApiError::new(ErrorCode::PayloadTooLarge, e.to_string())
+            }
+            e => ApiError::internal(e.to_string(), e),
         }
     }
 }
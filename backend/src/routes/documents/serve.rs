@@ -0,0 +1,225 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_files::HttpRange;
+use actix_web::body::SizedStream;
+use actix_web::http::header::{
+    self, Charset, ContentDisposition, DispositionParam, DispositionType, EntityTag, ExtendedValue,
+    HttpDate, IfModifiedSince, IfNoneMatch, IfRange,
+};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use uuid::Uuid;
+
+use crate::database::{self, DbPool};
+use crate::error::{internal_error, ApiError};
+use crate::export::{add_notes, OutlineError};
+use crate::models::{Bookmark, Highlight};
+use crate::storage::{content_hash, DocumentStorage};
+
+/// A stored document which is about to be sent to a client
+pub(crate) struct Download {
+    pub id: Uuid,
+    pub name: String,
+    pub content_hash: Option<String>,
+    pub added_on: DateTime<Utc>,
+    pub disposition: DispositionType,
+}
+
+/// Serves a document with support for conditional and range requests, so that
+/// readers such as pdf.js only need to fetch the parts of the file they display.
+pub(crate) async fn serve_document(
+    request: &HttpRequest,
+    pool: &DbPool,
+    storage: &dyn DocumentStorage,
+    cache_control: &str,
+    download: Download,
+) -> Result<HttpResponse, ApiError> {
+    let size = storage
+        .size(&download.id)
+        .await
+        .map_err(internal_error("Unable to read file from disk"))?;
+    let hash = match download.content_hash {
+        Some(hash) => hash,
+        None => store_content_hash(pool, storage, &download.id).await?,
+    };
+
+    let etag = EntityTag::new_strong(hash);
+    let last_modified = HttpDate::from(SystemTime::from(download.added_on));
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(header::ETag(etag.clone()))
+        .insert_header(header::LastModified(last_modified))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .insert_header((header::ACCEPT_RANGES, "bytes"));
+
+    if is_not_modified(request, &etag, last_modified) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    response
+        .content_type("application/pdf")
+        .insert_header(ContentDisposition {
+            disposition: download.disposition,
+            parameters: filename_parameters(download.name),
+        });
+
+    let (offset, length) = match requested_range(request, &etag, last_modified) {
+        None => (0, size),
+        Some(range) => match HttpRange::parse(&range, size) {
+            Ok(ranges) => {
+                // Only the first range is served, which is what every reader asks for anyway
+                let range = &ranges[0];
+                response.status(StatusCode::PARTIAL_CONTENT).insert_header((
+                    header::CONTENT_RANGE,
+                    format!(
+                        "bytes {}-{}/{}",
+                        range.start,
+                        range.start + range.length - 1,
+                        size
+                    ),
+                ));
+                (range.start, range.length)
+            }
+            Err(_) => {
+                return Ok(response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{size}")))
+                    .finish());
+            }
+        },
+    };
+
+    let body = storage
+        .read(&download.id, offset, length)
+        .await
+        .map_err(internal_error("Unable to read file from disk"))?;
+
+    Ok(response.body(SizedStream::new(length, body)))
+}
+
+/// Serves a copy of a document with the bookmarks added to its outline and the
+/// highlights added as annotations. The copy is made for each request, so neither
+/// caching nor ranges are offered.
+pub(crate) async fn serve_document_with_notes(
+    storage: &dyn DocumentStorage,
+    download: Download,
+    bookmarks: Vec<Bookmark>,
+    highlights: Vec<Highlight>,
+) -> Result<HttpResponse, ApiError> {
+    let size = storage
+        .size(&download.id)
+        .await
+        .map_err(internal_error("Unable to read file from disk"))?;
+    let pdf = storage
+        .read(&download.id, 0, size)
+        .await
+        .map_err(internal_error("Unable to read file from disk"))?
+        .try_fold(
+            Vec::with_capacity(size as usize),
+            |mut pdf, chunk| async move {
+                pdf.extend_from_slice(&chunk);
+                Ok(pdf)
+            },
+        )
+        .await
+        .map_err(internal_error("Unable to read file from disk"))?;
+
+    let pdf = web::block(move || add_notes(&pdf, &bookmarks, &highlights))
+        .await
+        .map_err(internal_error("Failed to add the notes to the document"))?
+        .map_err(|e| match e {
+            OutlineError::Protected => ApiError::conflict(e.to_string()),
+            e => ApiError::internal("Failed to add the notes to the document", e),
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((header::CACHE_CONTROL, "private, no-store"))
+        .insert_header(ContentDisposition {
+            disposition: download.disposition,
+            parameters: filename_parameters(download.name),
+        })
+        .body(pdf))
+}
+
+/// Documents stored before content hashes were recorded get theirs the first time
+/// they are downloaded.
+async fn store_content_hash(
+    pool: &DbPool,
+    storage: &dyn DocumentStorage,
+    id: &Uuid,
+) -> Result<String, ApiError> {
+    let hash = content_hash(storage, id)
+        .await
+        .map_err(internal_error("Unable to read file from disk"))?;
+
+    database::query("UPDATE Documents SET content_hash = $1 WHERE id = $2")
+        .bind(&hash)
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(internal_error("Failed to store content hash"))?;
+
+    Ok(hash)
+}
+
+fn is_not_modified(request: &HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    match request.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(etag)),
+        // If-Modified-Since is only considered when there is no If-None-Match
+        None => match request.get_header::<IfModifiedSince>() {
+            Some(IfModifiedSince(since)) => seconds(last_modified) <= seconds(since),
+            None => false,
+        },
+    }
+}
+
+/// The value of the `Range` header, unless an `If-Range` condition says the
+/// client has an outdated copy and should get the whole document instead.
+fn requested_range(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: HttpDate,
+) -> Option<String> {
+    let range = request.headers().get(header::RANGE)?.to_str().ok()?;
+
+    let is_current = match request.get_header::<IfRange>() {
+        None => true,
+        Some(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+        Some(IfRange::Date(date)) => seconds(last_modified) <= seconds(date),
+    };
+
+    is_current.then(|| range.to_owned())
+}
+
+fn seconds(date: HttpDate) -> u64 {
+    SystemTime::from(date)
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Display names may contain any unicode, so non-ascii names are sent with an
+/// additional `filename*` parameter alongside an ascii fallback.
+fn filename_parameters(name: String) -> Vec<DispositionParam> {
+    if name.is_ascii() {
+        return vec![DispositionParam::Filename(name)];
+    }
+
+    let fallback = name
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    vec![
+        DispositionParam::Filename(fallback),
+        DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_owned()),
+            language_tag: None,
+            value: name.into_bytes(),
+        }),
+    ]
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use actix_web::{web, HttpResponse, Scope};
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::Pdfium;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::indexer::Indexer;
+use crate::models::{ComponentHealth, HealthReport, HealthStatus};
+use crate::startup::bind_pdfium;
+
+/// Checks which take longer than this are reported as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the process is running. It does not check any dependency, so that
+/// an orchestrator does not restart the server because the database is down.
+#[utoipa::path(
+    get,
+    path = "/api/health/live",
+    tag = "health",
+    responses((status = 200, description = "The server is running", body = HealthReport)),
+    security(())
+)]
+async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(HealthReport {
+        status: HealthStatus::Up,
+        components: BTreeMap::new(),
+    })
+}
+
+/// Whether the server is able to handle requests, checking every dependency
+#[utoipa::path(
+    get,
+    path = "/api/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every component is up", body = HealthReport),
+        (status = 503, description = "At least one component is down", body = HealthReport),
+    ),
+    security(())
+)]
+async fn ready(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    indexer: web::Data<Indexer>,
+    pdfium: web::Data<&Lazy<Pdfium>>,
+) -> HttpResponse {
+    let storage_path = config.documents_storage_path();
+    let contents_path = config.documents_contents_path();
+    let (database, migrations, storage, contents, index, binding) = futures::join!(
+        check(check_database(&pool)),
+        check(check_migrations(&pool)),
+        check(check_writable(&storage_path)),
+        check(check_writable(&contents_path)),
+        check(async { indexer.check().context("Failed to open the index") }),
+        check(async { check_pdfium(&pdfium) }),
+    );
+    let components = BTreeMap::from([
+        ("database".to_owned(), database),
+        ("migrations".to_owned(), migrations),
+        ("documents_storage".to_owned(), storage),
+        ("documents_contents".to_owned(), contents),
+        ("index".to_owned(), index),
+        ("pdfium".to_owned(), binding),
+    ]);
+
+    let healthy = components.values().all(|c| c.status == HealthStatus::Up);
+    for (name, component) in components
+        .iter()
+        .filter(|(_, c)| c.status == HealthStatus::Down)
+    {
+        log::warn!(
+            "Health check of {} failed: {}",
+            name,
+            component.message.as_deref().unwrap_or_default()
+        );
+    }
+    let report = HealthReport {
+        status: if healthy {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        },
+        components,
+    };
+    match healthy {
+        true => HttpResponse::Ok().json(report),
+        false => HttpResponse::ServiceUnavailable().json(report),
+    }
+}
+
+async fn check(component: impl Future<Output = anyhow::Result<()>>) -> ComponentHealth {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, component).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!(
+            "No answer within {} seconds",
+            CHECK_TIMEOUT.as_secs()
+        )),
+    };
+    ComponentHealth {
+        status: match result {
+            Ok(()) => HealthStatus::Up,
+            Err(_) => HealthStatus::Down,
+        },
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+        message: result.err().map(|e| format!("{e:#}")),
+    }
+}
+
+async fn check_database(pool: &DbPool) -> anyhow::Result<()> {
+    database::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("Failed to query the database")?;
+    Ok(())
+}
+
+async fn check_migrations(pool: &DbPool) -> anyhow::Result<()> {
+    let applied: Vec<i64> =
+        database::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .context("Failed to read the applied migrations")?;
+    let missing = pool
+        .backend()
+        .migrator()
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version.to_string())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        anyhow::bail!("Migrations {} are not applied", missing.join(", "));
+    }
+    Ok(())
+}
+
+async fn check_writable(directory: &Path) -> anyhow::Result<()> {
+    let probe = directory.join(format!(".health-check-{}", Uuid::new_v4()));
+    tokio::fs::write(&probe, b"")
+        .await
+        .with_context(|| format!("{} is not writable", directory.display()))?;
+    tokio::fs::remove_file(&probe)
+        .await
+        .with_context(|| format!("Failed to remove {}", probe.display()))?;
+    Ok(())
+}
+
+fn check_pdfium(pdfium: &Lazy<Pdfium>) -> anyhow::Result<()> {
+    bind_pdfium(pdfium)?;
+    Ok(())
+}
+
+pub fn setup_health_service() -> Scope {
+    web::scope("/health")
+        .route("/live", web::get().to(live))
+        .route("/ready", web::get().to(ready))
+}
@@ -1,5 +1,12 @@
+pub mod admin;
 pub mod bookmarks;
 pub mod documents;
+pub mod health;
 pub mod search;
+pub mod shares;
+pub mod stats;
+pub mod tokens;
+pub mod usage;
+pub mod users;
 
 pub use documents::*;
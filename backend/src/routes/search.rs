@@ -1,23 +1,45 @@
-use actix_web::{web, HttpResponse, ResponseError, Scope};
+use std::time::Instant;
+
+use actix_web::{web, HttpResponse, Scope};
 use serde::Deserialize;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
 use crate::{
-    error::error_chain_fmt,
-    indexer::{Indexer, IndexerError},
+    error::{internal_error, ApiError},
+    indexer::Indexer,
+    metrics::METRICS,
 };
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SearchQuery {
+    /// Words to look for
     q: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/documents/{document_id}/search",
+    tag = "search",
+    params(("document_id" = Uuid, Path, description = "Id of the document"), SearchQuery),
+    responses(
+        (status = 200, description = "Matching pages, best match first", body = [SearchResult]),
+    )
+)]
 pub async fn search_document(
     id: web::Path<Uuid>,
     indexer: web::Data<Indexer>,
     query: web::Query<SearchQuery>,
-) -> Result<HttpResponse, SearchError> {
-    let res = indexer.search_document(&id, &query.q)?;
+) -> Result<HttpResponse, ApiError> {
+    let started = Instant::now();
+    let res = indexer
+        .search_document(&id, &query.q)
+        .map_err(internal_error("Failed to search document"))?;
+    METRICS
+        .search_duration
+        .observe(started.elapsed().as_secs_f64());
+    METRICS.search_results.observe(res.len() as f64);
 
     Ok(HttpResponse::Ok().json(res))
 }
@@ -25,21 +47,3 @@ pub async fn search_document(
 pub fn setup_search_service() -> Scope {
     web::scope("/documents/{document_id}/search").route("", web::get().to(search_document))
 }
-
-#[derive(thiserror::Error)]
-pub enum SearchError {
-    #[error(transparent)]
-    SearcherError(#[from] IndexerError),
-}
-
-impl std::fmt::Debug for SearchError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-
-impl ResponseError for SearchError {
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
-    }
-}
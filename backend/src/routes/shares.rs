@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::http::header::DispositionType;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::authentication::{
+    generate_token, hash_password, hash_token, verify_password, AuthenticatedUser,
+};
+use crate::configuration::Settings;
+use crate::database::{self, DbPool};
+use crate::encryption::FileEncryption;
+use crate::error::{internal_error, ApiError};
+use crate::models::{CreateShareRequest, CreatedShare, ShareLink, SharedDocument};
+use crate::routes::documents::post::{open_pdf, PdfFile};
+use crate::routes::documents::{serve_document, Download};
+use crate::secrets::SecretKey;
+use crate::storage::DocumentStorage;
+
+/// Header used to send the password of a protected share link. A header is used
+/// rather than a query parameter so that the password does not end up in access logs.
+pub const SHARE_PASSWORD_HEADER: &str = "X-Share-Password";
+
+const MAX_PASSWORD_LENGTH: usize = 1024;
+/// How long a correct share password is remembered. A viewer makes many range
+/// requests and renders many pages, which would otherwise each cost a password hash.
+const VERIFIED_PASSWORD_LIFETIME: Duration = Duration::from_secs(10 * 60);
+const MAX_VERIFIED_PASSWORDS: usize = 10_000;
+
+/// When each recently given correct password stops being trusted, by a digest of
+/// the hash it was checked against and the password itself. A wrong password is
+/// never remembered, so guessing still costs a password hash per attempt.
+static VERIFIED_PASSWORDS: Lazy<Mutex<HashMap<String, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+const DEFAULT_IMAGE_WIDTH: u16 = 1200;
+const MAX_IMAGE_WIDTH: u16 = 2400;
+/// Keeps very tall pages from being rendered into huge images
+const MAX_IMAGE_HEIGHT: u16 = 4800;
+const SHARE_COLUMNS: &str = "id, document, page, password_hash IS NOT NULL AS has_password,
+    added_on, expires_on, revoked_on";
+
+#[derive(sqlx::FromRow)]
+struct Share {
+    document: Uuid,
+    content_hash: Option<String>,
+    added_on: DateTime<Utc>,
+    password_hash: Option<String>,
+    name: String,
+    page_count: Option<i32>,
+    page: Option<i32>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PageImageQuery {
+    /// Width of the image in pixels, at most 2400
+    width: Option<u16>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shares",
+    tag = "shares",
+    request_body = CreateShareRequest,
+    responses(
+        (status = 201, description = "Share link created. This is the only time the token is returned", body = CreatedShare),
+        (status = 400, description = "Invalid share link details", body = ErrorResponse),
+        (status = 404, description = "Document not found", body = ErrorResponse),
+    )
+)]
+async fn create_share(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    request: web::Json<CreateShareRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let CreateShareRequest {
+        document,
+        page,
+        password,
+        expires_on,
+    } = request.into_inner();
+
+    if matches!(expires_on, Some(expires_on) if expires_on <= Utc::now()) {
+        return Err(ApiError::invalid_field(
+            "expires_on",
+            "Expiry date must be in the future",
+        ));
+    }
+    if let Some(password) = &password {
+        if password.is_empty() || password.len() > MAX_PASSWORD_LENGTH {
+            return Err(ApiError::invalid_field(
+                "password",
+                format!("Password must be between 1 and {MAX_PASSWORD_LENGTH} characters"),
+            ));
+        }
+    }
+
+    let page_count: Option<(Option<i32>,)> =
+        database::query_as("SELECT page_count FROM Documents WHERE id = $1")
+            .bind(document)
+            .fetch_optional(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to look up document"))?;
+    let Some((page_count,)) = page_count else {
+        return Err(ApiError::not_found("Document not found"));
+    };
+    if let Some(page) = page {
+        if page < 1 || matches!(page_count, Some(count) if page > count) {
+            return Err(ApiError::invalid_field(
+                "page",
+                "Page is outside of the document",
+            ));
+        }
+    }
+
+    let password_hash = match password {
+        Some(password) => Some(
+            web::block(move || hash_password(&password))
+                .await
+                .map_err(internal_error("Failed to hash password"))?
+                .map_err(internal_error("Failed to hash password"))?,
+        ),
+        None => None,
+    };
+
+    let token = generate_token();
+    log::info!(
+        "Sharing document {} on behalf of {}",
+        document,
+        user.username
+    );
+    let id = Uuid::new_v4();
+    database::query(
+        "INSERT INTO ShareLinks (id, document, owner, token_hash, password_hash, page, expires_on)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(id)
+    .bind(document)
+    .bind(user.id)
+    .bind(hash_token(&token))
+    .bind(password_hash)
+    .bind(page)
+    .bind(expires_on)
+    .execute(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to create share link"))?;
+    // Read back rather than RETURNING, as SQLite 3.38 computes has_password wrongly there
+    let details: ShareLink = database::query_as(&format!(
+        "SELECT {SHARE_COLUMNS} FROM ShareLinks WHERE id = $1"
+    ))
+    .bind(id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to read created share link"))?;
+
+    Ok(HttpResponse::Created().json(CreatedShare { token, details }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shares",
+    tag = "shares",
+    responses(
+        (status = 200, description = "Share links created by the current user", body = [ShareLink]),
+    )
+)]
+async fn get_shares(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    database::query_as::<ShareLink>(&format!(
+        "SELECT {SHARE_COLUMNS} FROM ShareLinks WHERE owner = $1 ORDER BY added_on"
+    ))
+    .bind(user.id)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to retrieve share links"))
+    .map(|shares| HttpResponse::Ok().json(shares))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/shares/{share_id}",
+    tag = "shares",
+    params(("share_id" = Uuid, Path, description = "Id of the share link")),
+    responses(
+        (status = 204, description = "Share link revoked"),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+    )
+)]
+async fn revoke_share(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    share_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let result = database::query(
+        "UPDATE ShareLinks SET revoked_on = COALESCE(revoked_on, $3)
+        WHERE id = $1 AND owner = $2",
+    )
+    .bind(*share_id)
+    .bind(user.id)
+    .bind(Utc::now())
+    .execute(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to revoke share link"))?;
+
+    match result.rows_affected() {
+        0 => Err(ApiError::not_found("Share link not found")),
+        _ => {
+            log::info!("Revoked share link {}", share_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+    }
+}
+
+/// Looks up an active share link, and checks the password if the link has one.
+/// Unknown, revoked and expired links are indistinguishable to the caller.
+async fn open_share(pool: &DbPool, token: &str, request: &HttpRequest) -> Result<Share, ApiError> {
+    let share: Share = database::query_as(
+        "SELECT s.document, s.password_hash, s.page, d.name, d.page_count, d.content_hash, d.added_on
+        FROM ShareLinks s
+        JOIN Documents d ON d.id = s.document
+        WHERE s.token_hash = $1
+            AND s.revoked_on IS NULL
+            AND (s.expires_on IS NULL OR s.expires_on > $2)",
+    )
+    .bind(hash_token(token))
+    .bind(Utc::now())
+    .fetch_optional(pool)
+    .await
+    .map_err(internal_error("Failed to look up share link"))?
+    .ok_or_else(|| ApiError::not_found("Share link not found"))?;
+
+    let Some(expected_hash) = share.password_hash.clone() else {
+        return Ok(share);
+    };
+    let Some(candidate) = request
+        .headers()
+        .get(SHARE_PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+    else {
+        return Err(ApiError::unauthorized(
+            "A password is required to open this link",
+        ));
+    };
+
+    let verified_key = hash_token(&format!("{expected_hash}\n{candidate}"));
+    if was_verified(&verified_key) {
+        return Ok(share);
+    }
+
+    let is_valid = web::block(move || verify_password(Some(&expected_hash), &candidate))
+        .await
+        .map_err(internal_error("Failed to verify password"))?
+        .map_err(internal_error("Failed to verify password"))?;
+    match is_valid {
+        true => {
+            remember_verified(verified_key);
+            Ok(share)
+        }
+        false => Err(ApiError::unauthorized("Invalid password")),
+    }
+}
+
+fn was_verified(key: &str) -> bool {
+    let verified = VERIFIED_PASSWORDS.lock().unwrap();
+    matches!(verified.get(key), Some(until) if *until > Instant::now())
+}
+
+fn remember_verified(key: String) {
+    let now = Instant::now();
+    let mut verified = VERIFIED_PASSWORDS.lock().unwrap();
+    verified.retain(|_, until| *until > now);
+    if verified.len() < MAX_VERIFIED_PASSWORDS {
+        verified.insert(key, now + VERIFIED_PASSWORD_LIFETIME);
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shared/{token}/info",
+    tag = "shares",
+    params(
+        ("token" = String, Path, description = "Token of the share link"),
+        ("X-Share-Password" = Option<String>, Header, description = "Password of a protected link"),
+    ),
+    responses(
+        (status = 200, description = "The shared document", body = SharedDocument),
+        (status = 401, description = "The link is protected by a password", body = ErrorResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+    ),
+    security(())
+)]
+async fn get_shared_info(
+    pool: web::Data<DbPool>,
+    token: web::Path<String>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let share = open_share(&pool, &token, &request).await?;
+
+    Ok(HttpResponse::Ok().json(SharedDocument {
+        name: share.name,
+        page_count: share.page_count,
+        page: share.page,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shared/{token}",
+    tag = "shares",
+    params(
+        ("token" = String, Path, description = "Token of the share link"),
+        ("X-Share-Password" = Option<String>, Header, description = "Password of a protected link"),
+    ),
+    responses(
+        (status = 200, description = "The PDF file", body = String, content_type = "application/pdf"),
+        (status = 206, description = "Part of the PDF file", body = String, content_type = "application/pdf"),
+        (status = 401, description = "The link is protected by a password", body = ErrorResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+    ),
+    security(())
+)]
+async fn get_shared_document(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    storage: web::Data<dyn DocumentStorage>,
+    token: web::Path<String>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let share = open_share(&pool, &token, &request).await?;
+
+    // Shared documents are meant to be read in the browser, rather than downloaded
+    let download = Download {
+        id: share.document,
+        name: share.name,
+        content_hash: share.content_hash,
+        added_on: share.added_on,
+        disposition: DispositionType::Inline,
+    };
+
+    serve_document(
+        &request,
+        &pool,
+        storage.get_ref(),
+        &config.document_cache_control,
+        download,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shared/{token}/pages/{page}",
+    tag = "shares",
+    params(
+        ("token" = String, Path, description = "Token of the share link"),
+        ("page" = u16, Path, description = "Number of the page, starting at 1"),
+        ("X-Share-Password" = Option<String>, Header, description = "Password of a protected link"),
+        PageImageQuery,
+    ),
+    responses(
+        (status = 200, description = "The page rendered as an image", body = String, content_type = "image/png"),
+        (status = 401, description = "The link is protected by a password", body = ErrorResponse),
+        (status = 404, description = "Share link or page not found", body = ErrorResponse),
+        (status = 422, description = "The document is protected by a password, which was not kept", body = ErrorResponse),
+    ),
+    security(())
+)]
+async fn get_shared_page(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    pdfium: web::Data<&'static Lazy<Pdfium>>,
+    encryption: web::Data<FileEncryption>,
+    path: web::Path<(String, u16)>,
+    query: web::Query<PageImageQuery>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let (token, page) = path.into_inner();
+    let share = open_share(&pool, &token, &request).await?;
+
+    let file = config
+        .documents_storage_path()
+        .join(share.document.to_string())
+        .with_extension("pdf");
+    let password = document_password(&pool, encryption.key(), &share.document).await?;
+    let width = query
+        .width
+        .unwrap_or(DEFAULT_IMAGE_WIDTH)
+        .clamp(1, MAX_IMAGE_WIDTH);
+
+    let pdfium = *pdfium.get_ref();
+    let png = web::block(move || {
+        let file = PdfFile {
+            path: &file,
+            encryption: &encryption,
+            password: password.as_deref(),
+        };
+        render_page(pdfium, &file, page, width)
+    })
+    .await
+    .map_err(internal_error("Failed to render page"))??;
+
+    Ok(HttpResponse::Ok().content_type("image/png").body(png))
+}
+
+/// Renders a page as a PNG image `width` pixels wide, at most [`MAX_IMAGE_HEIGHT`]
+/// high. Loading and rendering are slow, so this runs on the blocking thread pool.
+fn render_page(
+    pdfium: &Pdfium,
+    file: &PdfFile<'_>,
+    page: u16,
+    width: u16,
+) -> Result<Vec<u8>, ApiError> {
+    let pdf = open_pdf(pdfium, file)?;
+    let pdf_page = page
+        .checked_sub(1)
+        .and_then(|index| pdf.pages().get(index).ok())
+        .ok_or_else(|| ApiError::not_found("Page not found"))?;
+
+    let image = pdf_page
+        .render_with_config(
+            &PdfRenderConfig::new()
+                .set_target_width(width.into())
+                .set_maximum_height(MAX_IMAGE_HEIGHT.into()),
+        )
+        .map_err(internal_error("Failed to render page"))?
+        .as_image();
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(internal_error("Failed to encode page image"))?;
+    Ok(png)
+}
+
+/// The password a protected document was uploaded with, when it was kept
+async fn document_password(
+    pool: &DbPool,
+    key: Option<&SecretKey>,
+    document: &Uuid,
+) -> Result<Option<String>, ApiError> {
+    let encrypted: Option<String> =
+        database::query_scalar("SELECT encrypted_password FROM Documents WHERE id = $1")
+            .bind(document)
+            .fetch_optional(pool)
+            .await
+            .map_err(internal_error("Failed to fetch document"))?
+            .flatten();
+    match (encrypted, key) {
+        (Some(encrypted), Some(key)) => key
+            .decrypt_string(&encrypted)
+            .map(Some)
+            .map_err(internal_error("Failed to decrypt the document password")),
+        // Opening the document fails with a clear error if it needs the password
+        _ => Ok(None),
+    }
+}
+
+pub fn setup_shares_service() -> Scope {
+    web::scope("/shares")
+        .route("", web::get().to(get_shares))
+        .route("", web::post().to(create_share))
+        .route("/{share_id}", web::delete().to(revoke_share))
+}
+
+/// Routes used by whoever a document was shared with. These must be reachable
+/// without being logged in, since access is granted by the token in the path.
+pub fn setup_shared_service() -> Scope {
+    web::scope("/shared/{token}")
+        .route("", web::get().to(get_shared_document))
+        .route("/info", web::get().to(get_shared_info))
+        .route("/pages/{page}", web::get().to(get_shared_page))
+}
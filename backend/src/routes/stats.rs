@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, Scope};
+use uuid::Uuid;
+
+use crate::authentication::AuthenticatedUser;
+use crate::database::{self, DbPool, SELECT_DOCUMENTS};
+use crate::error::{internal_error, ApiError};
+use crate::models::{Document, PageEvent};
+use crate::statistics::{document_statistics, library_statistics};
+
+#[utoipa::path(
+    get,
+    path = "/api/documents/{document_id}/stats",
+    tag = "statistics",
+    params(("document_id" = Uuid, Path, description = "Id of the document")),
+    responses(
+        (status = 200, description = "Reading statistics of the document", body = DocumentStatistics),
+        (status = 404, description = "Document not found", body = ErrorResponse),
+    )
+)]
+async fn get_document_statistics(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    document_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let document: Document = database::query_as(&format!("{SELECT_DOCUMENTS} WHERE d.id = $2"))
+        .bind(user.id)
+        .bind(*document_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .map_err(internal_error("Failed to retrieve document"))?
+        .ok_or_else(|| ApiError::not_found("Document not found"))?;
+
+    let events: Vec<PageEvent> = database::query_as(
+        "SELECT * FROM PageEvents WHERE document = $1 AND reader = $2 ORDER BY occurred_on",
+    )
+    .bind(*document_id)
+    .bind(user.id)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to retrieve reading history"))?;
+
+    Ok(HttpResponse::Ok().json(document_statistics(&document, &events)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    tag = "statistics",
+    responses(
+        (status = 200, description = "Reading statistics of the whole library", body = LibraryStatistics),
+    )
+)]
+async fn get_library_statistics(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let documents: Vec<Document> =
+        database::query_as(&format!("{SELECT_DOCUMENTS} ORDER BY d.added_on"))
+            .bind(user.id)
+            .fetch_all(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to retrieve documents"))?;
+
+    let events: Vec<PageEvent> =
+        database::query_as("SELECT * FROM PageEvents WHERE reader = $1 ORDER BY occurred_on")
+            .bind(user.id)
+            .fetch_all(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to retrieve reading history"))?;
+
+    let mut events_by_document: HashMap<Uuid, Vec<PageEvent>> = HashMap::new();
+    for event in events {
+        events_by_document
+            .entry(event.document)
+            .or_default()
+            .push(event);
+    }
+
+    let statistics = documents
+        .iter()
+        .map(|d| {
+            let events = events_by_document.remove(&d.id).unwrap_or_default();
+            document_statistics(d, &events)
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(library_statistics(statistics)))
+}
+
+pub fn setup_document_stats_service() -> Scope {
+    web::scope("/documents/{document_id}/stats").route("", web::get().to(get_document_statistics))
+}
+
+pub fn setup_library_stats_service() -> Scope {
+    web::scope("/stats").route("", web::get().to(get_library_statistics))
+}
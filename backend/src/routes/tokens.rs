@@ -0,0 +1,151 @@
+use actix_web::{web, HttpResponse, Scope};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::authentication::{generate_token, hash_token, AuthenticatedUser, API_TOKEN_PREFIX};
+use crate::database::{self, DbPool, ScopeList};
+use crate::error::{internal_error, ApiError};
+use crate::models::{ApiToken, CreateTokenRequest, CreatedToken};
+
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+const TOKEN_COLUMNS: &str = "id, name, scopes, added_on, expires_on, last_used_on, revoked_on";
+
+#[utoipa::path(
+    post,
+    path = "/api/tokens",
+    tag = "tokens",
+    request_body = CreateTokenRequest,
+    responses(
+        (status = 201, description = "Token created. This is the only time the token is returned", body = CreatedToken),
+        (status = 400, description = "Invalid token details", body = ErrorResponse),
+        (status = 403, description = "The request was made with an API token, or is not allowed to grant one of the scopes", body = ErrorResponse),
+    )
+)]
+async fn create_token(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    request: web::Json<CreateTokenRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let CreateTokenRequest {
+        name,
+        mut scopes,
+        expires_on,
+    } = request.into_inner();
+
+    // A token made by another token would outlive its expiry and revocation
+    user.require_session()?;
+
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        return Err(ApiError::invalid_field(
+            "name",
+            format!("Token name must be between 1 and {MAX_TOKEN_NAME_LENGTH} characters"),
+        ));
+    }
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(ApiError::invalid_field(
+            "scopes",
+            "A token must have at least one scope",
+        ));
+    }
+    // A token can never be allowed more than the request which created it
+    if let Some(scope) = scopes.iter().find(|s| !user.has_scope(**s)) {
+        return Err(ApiError::forbidden(format!(
+            "Not allowed to create a token with the {} scope",
+            scope.as_str()
+        )));
+    }
+    if matches!(expires_on, Some(expires_on) if expires_on <= Utc::now()) {
+        return Err(ApiError::invalid_field(
+            "expires_on",
+            "Expiry date must be in the future",
+        ));
+    }
+
+    let token = format!("{API_TOKEN_PREFIX}{}", generate_token());
+    let scopes = ScopeList(scopes.iter().map(|s| s.as_str().to_owned()).collect());
+
+    log::info!("Creating API token {} for {}", name, user.username);
+    let details: ApiToken = database::query_as(&format!(
+        "INSERT INTO ApiTokens (id, owner, name, token_hash, scopes, expires_on)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING {TOKEN_COLUMNS}"
+    ))
+    .bind(Uuid::new_v4())
+    .bind(user.id)
+    .bind(name)
+    .bind(hash_token(&token))
+    .bind(scopes)
+    .bind(expires_on)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to create API token"))?;
+
+    Ok(HttpResponse::Created().json(CreatedToken { token, details }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tokens",
+    tag = "tokens",
+    responses(
+        (status = 200, description = "API tokens of the current user", body = [ApiToken]),
+    )
+)]
+async fn get_tokens(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    database::query_as::<ApiToken>(&format!(
+        "SELECT {TOKEN_COLUMNS} FROM ApiTokens WHERE owner = $1 ORDER BY added_on"
+    ))
+    .bind(user.id)
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to retrieve API tokens"))
+    .map(|tokens| HttpResponse::Ok().json(tokens))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/tokens/{token_id}",
+    tag = "tokens",
+    params(("token_id" = Uuid, Path, description = "Id of the token")),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 404, description = "Token not found", body = ErrorResponse),
+    )
+)]
+async fn revoke_token(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    token_id: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let result = database::query(
+        "UPDATE ApiTokens SET revoked_on = COALESCE(revoked_on, $3)
+        WHERE id = $1 AND owner = $2",
+    )
+    .bind(*token_id)
+    .bind(user.id)
+    .bind(Utc::now())
+    .execute(pool.get_ref())
+    .await
+    .map_err(internal_error("Failed to revoke API token"))?;
+
+    match result.rows_affected() {
+        0 => Err(ApiError::not_found("API token not found")),
+        _ => {
+            log::info!("Revoked API token {}", token_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+    }
+}
+
+pub fn setup_tokens_service() -> Scope {
+    web::scope("/tokens")
+        .route("", web::get().to(get_tokens))
+        .route("", web::post().to(create_token))
+        .route("/{token_id}", web::delete().to(revoke_token))
+}
@@ -0,0 +1,31 @@
+use actix_web::{web, HttpResponse, Scope};
+
+use crate::authentication::AuthenticatedUser;
+use crate::configuration::Settings;
+use crate::database::DbPool;
+use crate::error::{internal_error, ApiError};
+use crate::usage::user_usage;
+
+#[utoipa::path(
+    get,
+    path = "/api/usage",
+    tag = "usage",
+    responses(
+        (status = 200, description = "Storage used by the documents of the current user, and the quotas", body = Usage),
+    )
+)]
+async fn get_usage(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let usage = user_usage(pool.get_ref(), config.get_ref(), user.id)
+        .await
+        .map_err(internal_error("Failed to compute storage usage"))?;
+
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+pub fn setup_usage_service() -> Scope {
+    web::scope("/usage").route("", web::get().to(get_usage))
+}
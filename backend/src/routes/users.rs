@@ -0,0 +1,311 @@
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::authentication::{
+    generate_token, hash_password, hash_token, verify_password, AuthenticatedUser,
+    SESSION_COOKIE_NAME,
+};
+use crate::configuration::Settings;
+use crate::database::{self, is_unique_violation, DbPool};
+use crate::error::{internal_error, ApiError};
+use crate::models::{CreateUserRequest, Credentials, User};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 1024;
+const MAX_USERNAME_LENGTH: usize = 64;
+
+fn validate_credentials(username: &str, password: &str) -> Result<(), ApiError> {
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(ApiError::invalid_field(
+            "username",
+            format!("Username must be between 1 and {MAX_USERNAME_LENGTH} characters"),
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+    {
+        return Err(ApiError::invalid_field(
+            "username",
+            "Username may only contain letters, digits, '.', '_' and '-'",
+        ));
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH || password.len() > MAX_PASSWORD_LENGTH {
+        return Err(ApiError::invalid_field(
+            "password",
+            format!(
+            "Password must be between {MIN_PASSWORD_LENGTH} and {MAX_PASSWORD_LENGTH} characters"
+        ),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn hash_password_blocking(password: String) -> Result<String, ApiError> {
+    web::block(move || hash_password(&password))
+        .await
+        .map_err(internal_error("Failed to hash password"))?
+        .map_err(internal_error("Failed to hash password"))
+}
+
+async fn start_session(
+    pool: &DbPool,
+    config: &Settings,
+    user_id: Uuid,
+) -> Result<HttpResponse, ApiError> {
+    let token = generate_token();
+    let expires_on = Utc::now() + chrono::Duration::hours(config.session_lifetime_hours);
+
+    database::query("DELETE FROM Sessions WHERE expires_on < $1")
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .map_err(internal_error("Failed to remove expired sessions"))?;
+
+    database::query("INSERT INTO Sessions (id, user_id, expires_on) VALUES ($1, $2, $3)")
+        .bind(hash_token(&token))
+        .bind(user_id)
+        .bind(expires_on)
+        .execute(pool)
+        .await
+        .map_err(internal_error("Failed to create session"))?;
+
+    let cookie = Cookie::build(SESSION_COOKIE_NAME, token)
+        .path("/")
+        .http_only(true)
+        .secure(config.secure_session_cookie())
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::hours(config.session_lifetime_hours))
+        .finish();
+
+    Ok(HttpResponse::NoContent().cookie(cookie).finish())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "users",
+    request_body = Credentials,
+    responses(
+        (status = 204, description = "Logged in. The session cookie is set"),
+        (status = 401, description = "Invalid username or password", body = ErrorResponse),
+    ),
+    security(())
+)]
+async fn login(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    credentials: web::Json<Credentials>,
+) -> Result<HttpResponse, ApiError> {
+    let Credentials { username, password } = credentials.into_inner();
+    let user: Option<(Uuid, String)> =
+        database::query_as("SELECT id, password_hash FROM Users WHERE username = $1")
+            .bind(&username)
+            .fetch_optional(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to look up user"))?;
+
+    let (user_id, expected_hash) = match user {
+        Some((id, hash)) => (Some(id), Some(hash)),
+        None => (None, None),
+    };
+    let is_valid = web::block(move || verify_password(expected_hash.as_deref(), &password))
+        .await
+        .map_err(internal_error("Failed to verify password"))?
+        .map_err(internal_error("Failed to verify password"))?;
+
+    match user_id {
+        Some(user_id) if is_valid => {
+            log::info!("User {} logged in", username);
+            start_session(&pool, &config, user_id).await
+        }
+        _ => {
+            log::info!("Rejected login attempt for {}", username);
+            Err(ApiError::unauthorized("Invalid username or password"))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "users",
+    responses(
+        (status = 204, description = "Logged out. The session cookie is removed"),
+    ),
+    security(())
+)]
+async fn logout(pool: web::Data<DbPool>, request: HttpRequest) -> Result<HttpResponse, ApiError> {
+    if let Some(cookie) = request.cookie(SESSION_COOKIE_NAME) {
+        database::query("DELETE FROM Sessions WHERE id = $1")
+            .bind(hash_token(cookie.value()))
+            .execute(pool.get_ref())
+            .await
+            .map_err(internal_error("Failed to remove session"))?;
+    }
+
+    let mut removal = Cookie::build(SESSION_COOKIE_NAME, "").path("/").finish();
+    removal.make_removal();
+
+    Ok(HttpResponse::NoContent().cookie(removal).finish())
+}
+
+/// Creates the first account of a new installation. The account is an administrator,
+/// and takes ownership of anything which was created before accounts existed.
+#[utoipa::path(
+    post,
+    path = "/api/auth/setup",
+    tag = "users",
+    request_body = Credentials,
+    responses(
+        (status = 204, description = "Administrator created and logged in"),
+        (status = 400, description = "Invalid credentials", body = ErrorResponse),
+        (status = 409, description = "Setup has already been completed", body = ErrorResponse),
+    ),
+    security(())
+)]
+async fn setup(
+    pool: web::Data<DbPool>,
+    config: web::Data<Settings>,
+    credentials: web::Json<Credentials>,
+) -> Result<HttpResponse, ApiError> {
+    let Credentials { username, password } = credentials.into_inner();
+    validate_credentials(&username, &password)?;
+    let password_hash = hash_password_blocking(password).await?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(internal_error("Failed to begin database transaction"))?;
+
+    database::query(tx.backend().lock_users())
+        .execute(&mut tx)
+        .await
+        .map_err(internal_error("Failed to lock users table"))?;
+
+    let (user_count,): (i64,) = database::query_as("SELECT COUNT(*) FROM Users")
+        .fetch_one(&mut tx)
+        .await
+        .map_err(internal_error("Failed to count users"))?;
+    if user_count > 0 {
+        return Err(ApiError::conflict("Setup has already been completed"));
+    }
+
+    let user_id = Uuid::new_v4();
+    database::query(
+        "INSERT INTO Users (id, username, password_hash, is_admin) VALUES ($1, $2, $3, TRUE)",
+    )
+    .bind(user_id)
+    .bind(&username)
+    .bind(password_hash)
+    .execute(&mut tx)
+    .await
+    .map_err(internal_error("Failed to create user"))?;
+
+    let claims = [
+        "UPDATE Documents SET owner = $1 WHERE owner IS NULL",
+        "UPDATE Bookmarks SET owner = $1 WHERE owner IS NULL",
+        "UPDATE PageEvents SET reader = $1 WHERE reader IS NULL",
+        "INSERT INTO ReadingProgress (reader, document, current_page)
+            SELECT $1, document, current_page FROM UnclaimedReadingProgress",
+    ];
+    for claim in claims {
+        database::query(claim)
+            .bind(user_id)
+            .execute(&mut tx)
+            .await
+            .map_err(internal_error("Failed to claim existing data"))?;
+    }
+    database::query("DELETE FROM UnclaimedReadingProgress")
+        .execute(&mut tx)
+        .await
+        .map_err(internal_error("Failed to claim existing data"))?;
+
+    tx.commit()
+        .await
+        .map_err(internal_error("Failed to commit transaction"))?;
+
+    log::info!("Created initial administrator {}", username);
+    start_session(&pool, &config, user_id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me",
+    tag = "users",
+    responses(
+        (status = 200, description = "The current user", body = User),
+    )
+)]
+async fn get_current_user(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    database::query_as::<User>("SELECT id, username, is_admin, added_on FROM Users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(pool.get_ref())
+        .await
+        .map_err(internal_error("Failed to retrieve user"))
+        .map(|u| HttpResponse::Ok().json(u))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users",
+    tag = "users",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 201, description = "User created", body = User),
+        (status = 400, description = "Invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Only administrators can create users", body = ErrorResponse),
+        (status = 409, description = "Username is already taken", body = ErrorResponse),
+    )
+)]
+async fn create_user(
+    pool: web::Data<DbPool>,
+    user: AuthenticatedUser,
+    request: web::Json<CreateUserRequest>,
+) -> Result<HttpResponse, ApiError> {
+    user.require_admin()?;
+    let CreateUserRequest {
+        username,
+        password,
+        is_admin,
+    } = request.into_inner();
+    validate_credentials(&username, &password)?;
+    let password_hash = hash_password_blocking(password).await?;
+
+    log::info!("Creating user {}", username);
+    database::query_as::<User>(
+        "INSERT INTO Users (id, username, password_hash, is_admin) VALUES ($1, $2, $3, $4)
+        RETURNING id, username, is_admin, added_on",
+    )
+    .bind(Uuid::new_v4())
+    .bind(&username)
+    .bind(password_hash)
+    .bind(is_admin)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(|e| match e {
+        e if is_unique_violation(&e) => ApiError::conflict("Username is already taken"),
+        e => internal_error("Failed to create user")(e),
+    })
+    .map(|u| HttpResponse::Created().json(u))
+}
+
+/// Routes which must be reachable without being logged in.
+pub fn setup_auth_service() -> Scope {
+    web::scope("/auth")
+        .route("/login", web::post().to(login))
+        .route("/logout", web::post().to(logout))
+        .route("/setup", web::post().to(setup))
+}
+
+pub fn setup_users_service() -> Scope {
+    web::scope("/users")
+        .route("/me", web::get().to(get_current_user))
+        .route("", web::post().to(create_user))
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+
+use crate::configuration::Settings;
+use crate::error::error_chain_fmt;
+
+/// Length of the server key in bytes. It is configured as twice as many hex digits.
+pub const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// The server key, which encrypts secrets the server has to keep, such as the
+/// passwords of protected documents. Losing it makes those secrets unreadable.
+#[derive(Clone)]
+pub struct SecretKey {
+    cipher: Aes256Gcm,
+}
+
+impl SecretKey {
+    /// Parses a key written as hex digits, such as one made by `openssl rand -hex 32`
+    pub fn from_hex(key: &str) -> Result<Self, SecretError> {
+        let bytes = hex::decode(key.trim()).map_err(|_| SecretError::InvalidKey)?;
+        if bytes.len() != KEY_LENGTH {
+            return Err(SecretError::InvalidKey);
+        }
+        Ok(Self {
+            cipher: Aes256Gcm::new_from_slice(&bytes).map_err(|_| SecretError::InvalidKey)?,
+        })
+    }
+
+    /// The key set by `secret_key` or read from `secret_key_file`, if either is set
+    pub fn load(config: &Settings) -> Result<Option<Self>, SecretError> {
+        match (&config.secret_key, &config.secret_key_file) {
+            (Some(_), Some(_)) => Err(SecretError::AmbiguousKey),
+            (Some(key), None) => Self::from_hex(key).map(Some),
+            (None, Some(file)) => {
+                let key = std::fs::read_to_string(file)
+                    .map_err(|e| SecretError::KeyFile(file.clone(), e))?;
+                Self::from_hex(&key).map(Some)
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Encrypts `plaintext` with a fresh nonce, which is put in front of the result
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, SecretError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| SecretError::Encryption)?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, SecretError> {
+        if sealed.len() < NONCE_LENGTH {
+            return Err(SecretError::Decryption);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| SecretError::Decryption)
+    }
+
+    /// Encrypts a string for storing it in a text column
+    pub fn encrypt_string(&self, plaintext: &str) -> Result<String, SecretError> {
+        self.encrypt(plaintext.as_bytes()).map(hex::encode)
+    }
+
+    pub fn decrypt_string(&self, sealed: &str) -> Result<String, SecretError> {
+        let sealed = hex::decode(sealed).map_err(|_| SecretError::Decryption)?;
+        String::from_utf8(self.decrypt(&sealed)?).map_err(|_| SecretError::Decryption)
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SecretError {
+    #[error("The key must be {} hex digits", KEY_LENGTH * 2)]
+    InvalidKey,
+    #[error("Only one of secret_key and secret_key_file may be set")]
+    AmbiguousKey,
+    #[error("Failed to read the key from {0}")]
+    KeyFile(PathBuf, #[source] std::io::Error),
+    #[error("No server key is configured")]
+    MissingKey,
+    #[error("Failed to encrypt")]
+    Encryption,
+    #[error("Failed to decrypt, the data is corrupt or was encrypted with another key")]
+    Decryption,
+}
+
+impl std::fmt::Debug for SecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
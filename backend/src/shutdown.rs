@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ErrorCode};
+
+/// Coordinates stopping the server. Once a shutdown is requested, new uploads are
+/// refused while the ones in flight are given time to finish. Uploads which do not
+/// finish in time are aborted, and reported when the server has stopped.
+#[derive(Default)]
+pub struct Shutdown {
+    requested: AtomicBool,
+    on_request: Notify,
+    uploads_in_flight: AtomicUsize,
+    on_upload_end: Notify,
+    aborted: Mutex<Vec<AbortedUpload>>,
+}
+
+/// An upload which was interrupted by the shutdown
+#[derive(Clone, Debug)]
+pub struct AbortedUpload {
+    pub user: Uuid,
+    /// Files which had been written, and were deleted again
+    pub documents: usize,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the shutdown. Calling this more than once has no further effect.
+    pub fn request(&self) {
+        if !self.requested.swap(true, Ordering::SeqCst) {
+            log::info!("Shutdown requested, no longer accepting uploads");
+            self.on_request.notify_waiters();
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Waits until a shutdown is requested, either through [`Shutdown::request`]
+    /// or by SIGTERM or SIGINT.
+    pub async fn requested(&self) -> std::io::Result<()> {
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let on_request = self.on_request.notified();
+        if !self.is_requested() {
+            tokio::select! {
+                _ = terminate.recv() => log::info!("Received SIGTERM"),
+                _ = interrupt.recv() => log::info!("Received SIGINT"),
+                _ = on_request => {},
+            }
+        }
+        self.request();
+        Ok(())
+    }
+
+    /// Registers an upload, unless the server is shutting down. The upload counts
+    /// as in flight until the returned guard is dropped.
+    pub fn begin_upload(self: &Arc<Self>, user: Uuid) -> Result<InFlightUpload, ApiError> {
+        self.uploads_in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.is_requested() {
+            self.end_upload();
+            return Err(ApiError::new(
+                ErrorCode::ServiceUnavailable,
+                "The server is shutting down, please retry the upload later",
+            ));
+        }
+        Ok(InFlightUpload {
+            shutdown: self.clone(),
+            user,
+            files: Vec::new(),
+            finished: false,
+        })
+    }
+
+    fn end_upload(&self) {
+        self.uploads_in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.on_upload_end.notify_waiters();
+    }
+
+    pub fn uploads_in_flight(&self) -> usize {
+        self.uploads_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the uploads in flight to end, for at most `timeout`. Returns
+    /// whether they all did.
+    pub async fn wait_for_uploads(&self, timeout: Duration) -> bool {
+        let drained = async {
+            loop {
+                let upload_ended = self.on_upload_end.notified();
+                if self.uploads_in_flight() == 0 {
+                    return;
+                }
+                upload_ended.await;
+            }
+        };
+        tokio::time::timeout(timeout, drained).await.is_ok()
+    }
+
+    pub fn aborted_uploads(&self) -> Vec<AbortedUpload> {
+        self.aborted.lock().unwrap().clone()
+    }
+}
+
+/// Guard for an upload in progress. Unless [`InFlightUpload::finish`] is called,
+/// the files added to it are deleted when it is dropped, which happens when the
+/// upload fails or the request is cancelled.
+pub struct InFlightUpload {
+    shutdown: Arc<Shutdown>,
+    user: Uuid,
+    files: Vec<PathBuf>,
+    finished: bool,
+}
+
+impl InFlightUpload {
+    /// Adds a file to be deleted if the upload does not finish. It does not need to exist yet.
+    pub fn add_file(&mut self, path: PathBuf) {
+        self.files.push(path);
+    }
+
+    /// Marks the upload as complete, keeping its files
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for InFlightUpload {
+    fn drop(&mut self) {
+        if !self.finished {
+            for file in &self.files {
+                match std::fs::remove_file(file) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        log::error!("Failed to delete {}: {}", file.display(), e)
+                    }
+                    _ => {}
+                }
+            }
+            if self.shutdown.is_requested() {
+                self.shutdown.aborted.lock().unwrap().push(AbortedUpload {
+                    user: self.user,
+                    documents: self.files.len(),
+                });
+            }
+        }
+        self.shutdown.end_upload();
+    }
+}
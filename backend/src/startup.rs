@@ -1,26 +1,127 @@
-use std::net::TcpListener;
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::panic::AssertUnwindSafe;
 
-use crate::configuration::Settings;
-use crate::database;
+use crate::authentication::RequireAuthentication;
+use crate::configuration::{ListenAddress, Settings};
+use crate::database::{self, DbPool};
+use crate::encryption::FileEncryption;
+use crate::error::{ApiError, ErrorCode};
+use crate::inbox::Inbox;
 use crate::indexer::Indexer;
-use crate::routes::{bookmarks, documents, search};
+use crate::metrics::{self, RecordMetrics};
+use crate::openapi;
+use crate::request_id::AssignRequestId;
+use crate::routes::{
+    admin, bookmarks, documents, health, search, shares, stats, tokens, usage, users,
+};
+use crate::shutdown::Shutdown;
+use crate::storage::{DocumentStorage, FileSystemStorage};
+use crate::tls::{RedirectToHttps, ReloadableCertificate};
+use crate::usage::record_missing_sizes;
 use actix_web::middleware::Logger;
 use actix_web::{dev::Server, get, web, App, HttpResponse, HttpServer, Responder};
 use once_cell::sync::Lazy;
 use pdfium_render::prelude::Pdfium;
-use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct Application {
+    /// Port of the main listener, or 0 when listening on a unix socket
     pub port: u16,
+    /// Port of the listener redirecting plain HTTP to HTTPS, if there is one
+    pub redirect_port: Option<u16>,
     pub server: Server,
+    /// Stops the server gracefully when requested, as SIGTERM and SIGINT do
+    pub shutdown: Arc<Shutdown>,
+    indexer: web::Data<Indexer>,
+    db_pool: DbPool,
+    /// Watches the inbox directory, when one is configured
+    inbox: Option<Inbox>,
 }
 
+/// How long uploads cancelled at the end of the shutdown timeout get to clean up
+const UPLOAD_CLEANUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Socket the application is served on
+pub enum Listener {
+    Http(TcpListener),
+    Https(TcpListener, Arc<ReloadableCertificate>),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Binds the address from the settings, serving HTTPS when a certificate is configured
+    pub fn bind(configuration: &Settings) -> Result<Self, std::io::Error> {
+        let address = configuration
+            .listen_address()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        match address {
+            ListenAddress::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+                match configuration.tls_files() {
+                    Some((certificate, private_key)) => {
+                        let certificate = ReloadableCertificate::load(certificate, private_key)
+                            .map_err(|e| {
+                                std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+                            })?;
+                        Ok(Self::Https(listener, Arc::new(certificate)))
+                    }
+                    None => Ok(Self::Http(listener)),
+                }
+            }
+            ListenAddress::Unix(path) => {
+                // The socket of a previous run is not removed when the server stops
+                if std::fs::metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+                    std::fs::remove_file(&path)?;
+                }
+                Ok(Self::Unix(UnixListener::bind(path)?))
+            }
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        match self {
+            Self::Http(listener) | Self::Https(listener, _) => {
+                listener.local_addr().unwrap().port()
+            }
+            Self::Unix(_) => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(listener) => write!(f, "http://{}", listener.local_addr().unwrap()),
+            Self::Https(listener, _) => write!(f, "https://{}", listener.local_addr().unwrap()),
+            Self::Unix(listener) => match listener.local_addr().unwrap().as_pathname() {
+                Some(path) => write!(f, "unix:{}", path.display()),
+                None => write!(f, "unix socket"),
+            },
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/health_check",
+    tag = "health",
+    responses((status = 200, description = "The server is up")),
+    security(())
+)]
 #[get("health_check")]
 async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
 
-static PDFIUM: Lazy<Pdfium> = Lazy::new(|| {
+/// The default access log format, with the request id added so that access log
+/// lines can be matched with the rest of the logs for the same request.
+const LOG_FORMAT: &str =
+    r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{x-request-id}o"#;
+
+pub static PDFIUM: Lazy<Pdfium> = Lazy::new(|| {
     log::info!("Binding pdfium");
     let pdfium = Pdfium::new(
         Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
@@ -31,58 +132,232 @@ static PDFIUM: Lazy<Pdfium> = Lazy::new(|| {
     pdfium
 });
 
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to bind the pdfium library")]
+pub struct PdfiumUnavailable;
+
+impl From<PdfiumUnavailable> for ApiError {
+    fn from(e: PdfiumUnavailable) -> Self {
+        ApiError::new(ErrorCode::ServiceUnavailable, e.to_string())
+    }
+}
+
+/// Pdfium is bound the first time it is used. Binding panics when the library
+/// cannot be loaded, and every later use panics as well, so the panic is turned
+/// into an error for callers which can report it.
+pub fn bind_pdfium(pdfium: &Lazy<Pdfium>) -> Result<&Pdfium, PdfiumUnavailable> {
+    if let Some(pdfium) = Lazy::get(pdfium) {
+        return Ok(pdfium);
+    }
+    std::panic::catch_unwind(AssertUnwindSafe(|| Lazy::force(pdfium)))
+        .map_err(|_| PdfiumUnavailable)
+}
+
+/// Serves the application on `listener`. Requests received on `redirect_listener`
+/// are redirected to the HTTPS listener.
 pub fn run(
-    listener: TcpListener,
-    db_pool: PgPool,
+    listener: Listener,
+    redirect_listener: Option<TcpListener>,
+    db_pool: DbPool,
+    indexer: web::Data<Indexer>,
+    shutdown: Arc<Shutdown>,
     configuration: Settings,
 ) -> Result<Server, std::io::Error> {
     let db_pool = web::Data::new(db_pool);
-    let indexer = web::Data::new(
-        Indexer::new(configuration.documents_contents_path()).expect("Failed to set up indexer"),
-    );
+    let shutdown = web::Data::from(shutdown);
+    let shutdown_timeout = configuration.shutdown_timeout_seconds;
+    let encryption = FileEncryption::load(&configuration)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let storage: Arc<dyn DocumentStorage> = Arc::new(FileSystemStorage::new(
+        configuration.documents_storage_path(),
+        encryption.clone(),
+    ));
+    let storage = web::Data::from(storage);
+    let encryption = web::Data::new(encryption);
     let config = web::Data::new(configuration);
     let pdfium = web::Data::new(&PDFIUM);
-    log::info!("Starting listen on {}", listener.local_addr().unwrap());
+    let https_port = match (&listener, &redirect_listener) {
+        (Listener::Https(..), Some(_)) => Some(listener.port()),
+        _ => None,
+    };
+    log::info!("Starting listen on {}", listener);
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
+            .wrap(RedirectToHttps::new(https_port))
+            .wrap(AssignRequestId)
+            .wrap(RecordMetrics)
+            .wrap(Logger::new(LOG_FORMAT))
+            .service(metrics::setup_metrics_service())
+            .service(openapi::setup_openapi_service())
             .service(
                 web::scope("/api")
-                    .service(search::setup_search_service())
-                    .service(bookmarks::setup_bookmarks_service())
-                    .service(documents::setup_documents_service())
-                    .service(health_check),
+                    .service(users::setup_auth_service())
+                    .service(shares::setup_shared_service())
+                    .service(health_check)
+                    .service(health::setup_health_service())
+                    .service(
+                        web::scope("")
+                            .wrap(RequireAuthentication)
+                            .service(users::setup_users_service())
+                            .service(tokens::setup_tokens_service())
+                            .service(shares::setup_shares_service())
+                            .service(search::setup_search_service())
+                            .service(bookmarks::setup_bookmarks_service())
+                            .service(bookmarks::setup_bookmark_export_service())
+                            .service(stats::setup_document_stats_service())
+                            .service(stats::setup_library_stats_service())
+                            .service(usage::setup_usage_service())
+                            .service(admin::setup_admin_service())
+                            .service(documents::setup_documents_service()),
+                    ),
             )
             .app_data(db_pool.clone())
             .app_data(config.clone())
+            .app_data(storage.clone())
             .app_data(pdfium.clone())
+            .app_data(encryption.clone())
             .app_data(indexer.clone())
+            .app_data(shutdown.clone())
     })
-    .listen(listener)?
-    .run();
+    .shutdown_timeout(shutdown_timeout)
+    .disable_signals();
+    let server = match listener {
+        Listener::Http(listener) => server.listen(listener)?,
+        Listener::Https(listener, certificate) => {
+            certificate.clone().reload_on_hangup()?;
+            server.listen_rustls(listener, certificate.server_config())?
+        }
+        Listener::Unix(listener) => server.listen_uds(listener)?,
+    };
+    let server = match redirect_listener {
+        Some(listener) => {
+            log::info!(
+                "Redirecting http://{} to HTTPS",
+                listener.local_addr().unwrap()
+            );
+            server.listen(listener)?
+        }
+        None => server,
+    };
 
-    Ok(server)
+    Ok(server.run())
 }
+
 impl Application {
     pub async fn build(
         configuration: Settings,
-        db_pool: Option<PgPool>,
+        db_pool: Option<DbPool>,
     ) -> Result<Self, std::io::Error> {
         Application::ensure_storage_path(&configuration).await;
+        if configuration.secure_session_cookie() && configuration.tls_files().is_none() {
+            log::warn!(
+                "Session cookies are only sent over HTTPS, but the server does not serve HTTPS. \
+                 Logging in only works behind a proxy which does."
+            );
+        }
         let connection_pool =
             db_pool.unwrap_or_else(|| database::get_connection_pool(&configuration));
         database::initialize_database(&connection_pool).await;
 
-        let adress_binding = format!("0.0.0.0:{}", configuration.port);
-        let listener = TcpListener::bind(adress_binding)?;
-        let port = listener.local_addr().unwrap().port();
-        let server = run(listener, connection_pool, configuration)?;
+        let listener = Listener::bind(&configuration)?;
+        let redirect_listener = match (&listener, configuration.http_redirect_port) {
+            (Listener::Https(https, _), Some(port)) => {
+                let address = SocketAddr::new(https.local_addr()?.ip(), port);
+                Some(TcpListener::bind(address)?)
+            }
+            _ => None,
+        };
+        let port = listener.port();
+        let redirect_port = redirect_listener
+            .as_ref()
+            .map(|listener| listener.local_addr().unwrap().port());
+        let encryption = FileEncryption::load(&configuration)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let storage =
+            FileSystemStorage::new(configuration.documents_storage_path(), encryption.clone());
+        if let Err(e) = record_missing_sizes(&connection_pool, &storage).await {
+            log::error!("Failed to record the size of older documents: {:?}", e);
+        }
+        let indexer = web::Data::new(
+            Indexer::with_encryption(configuration.documents_contents_path(), encryption)
+                .expect("Failed to set up indexer"),
+        );
+        let shutdown = Arc::new(Shutdown::new());
+        let inbox = Inbox::start(
+            &configuration,
+            connection_pool.clone(),
+            indexer.clone(),
+            &PDFIUM,
+            shutdown.clone(),
+        )?;
+        let server = run(
+            listener,
+            redirect_listener,
+            connection_pool.clone(),
+            indexer.clone(),
+            shutdown.clone(),
+            configuration,
+        )?;
 
-        Ok(Self { server, port })
+        Ok(Self {
+            server,
+            port,
+            redirect_port,
+            shutdown,
+            indexer,
+            db_pool: connection_pool,
+            inbox,
+        })
     }
 
+    /// Serves requests until a shutdown is requested. Requests in flight are then
+    /// given `shutdown_timeout_seconds` to finish and the inbox finishes the file it
+    /// is adding, after which the index and the database connections are closed.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let handle = self.server.handle();
+        let shutdown = self.shutdown.clone();
+        let stop = tokio::spawn(async move {
+            match shutdown.requested().await {
+                Ok(()) => handle.stop(true).await,
+                Err(e) => log::error!("Failed to listen for shutdown signals: {}", e),
+            }
+        });
+        let result = self.server.await;
+        stop.abort();
+
+        // The server can also stop without a shutdown having been requested
+        self.shutdown.request();
+        if let Some(inbox) = self.inbox {
+            inbox.stopped().await;
+        }
+        // Uploads cancelled at the timeout are dropped on the worker threads
+        if !self.shutdown.wait_for_uploads(UPLOAD_CLEANUP_TIMEOUT).await {
+            log::warn!(
+                "{} uploads were still running when the server stopped",
+                self.shutdown.uploads_in_flight()
+            );
+        }
+        if let Err(e) = self.indexer.close().await {
+            log::error!("Failed to close the index: {:?}", e);
+        }
+        self.db_pool.close().await;
+
+        let aborted = self.shutdown.aborted_uploads();
+        if aborted.is_empty() {
+            log::info!("Shutdown complete, no uploads were aborted");
+        } else {
+            let descriptions = aborted
+                .iter()
+                .map(|u| format!("{} document(s) from user {}", u.documents, u.user))
+                .collect::<Vec<_>>();
+            log::warn!(
+                "Shutdown complete, {} uploads were aborted: {}",
+                aborted.len(),
+                descriptions.join(", ")
+            );
+        }
+
+        result
     }
 
     pub async fn ensure_storage_path(configuration: &Settings) {
@@ -92,7 +367,7 @@ impl Application {
         ];
         for path in required_paths.into_iter() {
             if !std::path::Path::exists(&path) {
-                println!("Path {:?} did not exist. Creating it now", &path);
+                log::info!("Path {:?} did not exist. Creating it now", &path);
                 tokio::fs::create_dir_all(path).await.unwrap();
             }
         }
@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::NaiveDate;
+
+use crate::models::{DailyPages, Document, DocumentStatistics, LibraryStatistics, PageEvent};
+
+/// Gaps between two page changes longer than this are treated as the reader
+/// having put the document down, and are not counted as reading time.
+pub const MAX_READING_GAP_SECONDS: i64 = 10 * 60;
+
+/// Computes reading statistics for a single document. The events are expected
+/// to be ordered by the time they occurred.
+pub fn document_statistics(document: &Document, events: &[PageEvent]) -> DocumentStatistics {
+    let reading_time_seconds = events
+        .windows(2)
+        .map(|pair| (pair[1].occurred_on - pair[0].occurred_on).num_seconds())
+        .filter(|gap| *gap <= MAX_READING_GAP_SECONDS)
+        .sum::<i64>();
+
+    let pages_read = events.iter().map(|e| e.page).collect::<HashSet<_>>().len() as i64;
+
+    let mut pages_by_day: BTreeMap<NaiveDate, HashSet<i32>> = BTreeMap::new();
+    for event in events {
+        pages_by_day
+            .entry(event.occurred_on.date_naive())
+            .or_default()
+            .insert(event.page);
+    }
+    let pages_per_day = pages_by_day
+        .into_iter()
+        .map(|(date, pages)| DailyPages {
+            date,
+            pages: pages.len() as i64,
+        })
+        .collect();
+
+    let estimated_seconds_to_finish = match document.page_count {
+        Some(page_count) if reading_time_seconds > 0 && pages_read > 0 => {
+            let seconds_per_page = reading_time_seconds as f64 / pages_read as f64;
+            let remaining_pages = (page_count - document.current_page).max(0);
+            Some((seconds_per_page * remaining_pages as f64).round() as i64)
+        }
+        _ => None,
+    };
+
+    DocumentStatistics {
+        document: document.id,
+        name: document.name.clone(),
+        current_page: document.current_page,
+        page_count: document.page_count,
+        reading_time_seconds,
+        pages_read,
+        pages_per_day,
+        last_opened: events.iter().map(|e| e.occurred_on).max(),
+        estimated_seconds_to_finish,
+    }
+}
+
+/// Combines the statistics of every document in the library.
+pub fn library_statistics(documents: Vec<DocumentStatistics>) -> LibraryStatistics {
+    let mut pages_by_day: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    for daily in documents.iter().flat_map(|d| d.pages_per_day.iter()) {
+        *pages_by_day.entry(daily.date).or_default() += daily.pages;
+    }
+
+    let estimates = documents
+        .iter()
+        .filter_map(|d| d.estimated_seconds_to_finish)
+        .collect::<Vec<_>>();
+
+    LibraryStatistics {
+        document_count: documents.len() as i64,
+        documents_started: documents.iter().filter(|d| d.last_opened.is_some()).count() as i64,
+        documents_finished: documents
+            .iter()
+            .filter(|d| matches!(d.page_count, Some(count) if d.current_page >= count))
+            .count() as i64,
+        reading_time_seconds: documents.iter().map(|d| d.reading_time_seconds).sum(),
+        pages_per_day: pages_by_day
+            .into_iter()
+            .map(|(date, pages)| DailyPages { date, pages })
+            .collect(),
+        last_opened: documents.iter().filter_map(|d| d.last_opened).max(),
+        estimated_seconds_to_finish: match estimates.is_empty() {
+            true => None,
+            false => Some(estimates.iter().sum()),
+        },
+        documents,
+    }
+}
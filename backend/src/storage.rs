@@ -0,0 +1,118 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use actix_web::web::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::encryption::FileEncryption;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Where the contents of documents are kept. Handlers only read documents through
+/// this trait, so that they do not depend on documents being plain files on disk.
+pub trait DocumentStorage: Send + Sync {
+    /// Size of the stored document in bytes
+    fn size<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, io::Result<u64>>;
+
+    /// Streams `length` bytes of the document, starting at `offset`
+    fn read<'a>(
+        &'a self,
+        id: &'a Uuid,
+        offset: u64,
+        length: u64,
+    ) -> BoxFuture<'a, io::Result<ByteStream>>;
+}
+
+/// Stores every document as `<id>.pdf` in a single directory, encrypted when
+/// encryption is enabled
+pub struct FileSystemStorage {
+    root: PathBuf,
+    encryption: FileEncryption,
+}
+
+impl FileSystemStorage {
+    pub fn new(root: PathBuf, encryption: FileEncryption) -> Self {
+        Self { root, encryption }
+    }
+
+    pub fn path(&self, id: &Uuid) -> PathBuf {
+        self.root.join(id.to_string()).with_extension("pdf")
+    }
+}
+
+/// Runs file IO, which includes decryption, on the blocking thread pool
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+impl DocumentStorage for FileSystemStorage {
+    fn size<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, io::Result<u64>> {
+        let (encryption, path) = (self.encryption.clone(), self.path(id));
+        blocking(move || encryption.open(&path)?.size()).boxed()
+    }
+
+    fn read<'a>(
+        &'a self,
+        id: &'a Uuid,
+        offset: u64,
+        length: u64,
+    ) -> BoxFuture<'a, io::Result<ByteStream>> {
+        let (encryption, path) = (self.encryption.clone(), self.path(id));
+        async move {
+            let file = blocking(move || {
+                let mut file = encryption.open(&path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(file)
+            })
+            .await?;
+
+            let chunks = stream::try_unfold((file, length), |(file, remaining)| async move {
+                if remaining == 0 {
+                    return Ok(None);
+                }
+                let (file, buffer) = blocking(move || {
+                    let mut file = file;
+                    let mut buffer = vec![0; remaining.min(CHUNK_SIZE) as usize];
+                    let read = file.read(&mut buffer)?;
+                    buffer.truncate(read);
+                    Ok((file, buffer))
+                })
+                .await?;
+                if buffer.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Document is shorter than expected",
+                    ));
+                }
+                let read = buffer.len() as u64;
+                Ok(Some((Bytes::from(buffer), (file, remaining - read))))
+            });
+
+            Ok(chunks.boxed())
+        }
+        .boxed()
+    }
+}
+
+/// Hex encoded SHA-256 of the whole document
+pub async fn content_hash(storage: &dyn DocumentStorage, id: &Uuid) -> io::Result<String> {
+    let size = storage.size(id).await?;
+    let mut chunks = storage.read(id, 0, size).await?;
+
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = chunks.try_next().await? {
+        hasher.update(&chunk);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
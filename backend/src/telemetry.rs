@@ -1,24 +1,66 @@
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
 use tracing::{subscriber::set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
 
+use crate::database::Backend;
+
+/// Builds the subscriber for the application. Spans are always logged as Bunyan
+/// formatted JSON, and are additionally exported over OTLP/HTTP when an endpoint
+/// such as `http://localhost:4318/v1/traces` is given.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    otlp_endpoint: Option<String>,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let otlp_layer = otlp_endpoint.map(|endpoint| {
+        let tracer = otlp_tracer(name.clone(), endpoint).expect("Failed to set up OTLP exporter");
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    });
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
 
     Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(otlp_layer)
+}
+
+fn otlp_tracer(
+    name: String,
+    endpoint: String,
+) -> Result<trace::Tracer, opentelemetry::trace::TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", name)])),
+        )
+        // Exports from a thread of its own, since actix workers run single threaded runtimes
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)
+}
+
+/// Span for a single database query, named after the OpenTelemetry database conventions
+pub fn query_span(backend: Backend, statement: &str) -> tracing::Span {
+    tracing::info_span!(
+        "db.query",
+        otel.kind = "client",
+        db.system = backend.system(),
+        db.statement = statement
+    )
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
@@ -26,3 +68,8 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+/// Exports any spans which have not been sent yet. Should be called before exiting.
+pub fn shutdown_subscriber() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
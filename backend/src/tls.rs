@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::future::{ready, Ready};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use futures::future::LocalBoxFuture;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::error::error_chain_fmt;
+
+/// Certificate chain and private key read from PEM files. They can be reloaded
+/// while the server is running, so that a renewed certificate is picked up
+/// without dropping connections.
+pub struct ReloadableCertificate {
+    certificate_path: PathBuf,
+    private_key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+    pub fn load(certificate_path: &Path, private_key_path: &Path) -> Result<Self, TlsError> {
+        let current = load_certified_key(certificate_path, private_key_path)?;
+        Ok(Self {
+            certificate_path: certificate_path.to_owned(),
+            private_key_path: private_key_path.to_owned(),
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Reads the files again. The certificate in use is kept when they are
+    /// invalid, so that a botched renewal does not take the server down.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let reloaded = load_certified_key(&self.certificate_path, &self.private_key_path)?;
+        *self.current.write().unwrap() = Arc::new(reloaded);
+        Ok(())
+    }
+
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn server_config(self: Arc<Self>) -> ServerConfig {
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(self)
+    }
+
+    /// Reloads the certificate every time the process receives SIGHUP
+    pub fn reload_on_hangup(self: Arc<Self>) -> std::io::Result<()> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                match self.reload() {
+                    Ok(()) => log::info!(
+                        "Reloaded TLS certificate from {}",
+                        self.certificate_path.display()
+                    ),
+                    Err(e) => log::error!(
+                        "Failed to reload TLS certificate, keeping the current one: {:?}",
+                        e
+                    ),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+fn load_certified_key(
+    certificate_path: &Path,
+    private_key_path: &Path,
+) -> Result<CertifiedKey, TlsError> {
+    let chain = load_certificate_chain(certificate_path)?;
+    let private_key = load_private_key(private_key_path)?;
+    let signing_key = sign::any_supported_type(&private_key)
+        .map_err(|_| TlsError::UnsupportedPrivateKey(private_key_path.to_owned()))?;
+    Ok(CertifiedKey::new(chain, signing_key))
+}
+
+/// Reads every certificate in a PEM file, starting with the server's own
+pub fn load_certificate_chain(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Read(path.to_owned(), e))?;
+    let chain = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| TlsError::Read(path.to_owned(), e))?;
+    if chain.is_empty() {
+        return Err(TlsError::MissingCertificate(path.to_owned()));
+    }
+    Ok(chain.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first RSA, EC or PKCS#8 private key in a PEM file
+pub fn load_private_key(path: &Path) -> Result<PrivateKey, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Read(path.to_owned(), e))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| TlsError::Read(path.to_owned(), e))?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::MissingPrivateKey(path.to_owned()))
+}
+
+#[derive(thiserror::Error)]
+pub enum TlsError {
+    #[error("Failed to read {}: {1}", .0.display())]
+    Read(PathBuf, std::io::Error),
+    #[error("{} does not contain a PEM encoded certificate", .0.display())]
+    MissingCertificate(PathBuf),
+    #[error("{} does not contain a PEM encoded private key", .0.display())]
+    MissingPrivateKey(PathBuf),
+    #[error("{} contains a private key of an unsupported type", .0.display())]
+    UnsupportedPrivateKey(PathBuf),
+}
+
+impl std::fmt::Debug for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Middleware which answers requests received over plain HTTP with a permanent
+/// redirect to the same URL on the HTTPS port. Requests received over TLS, and
+/// every request when there is no HTTPS port, are passed through.
+pub struct RedirectToHttps {
+    https_port: Option<u16>,
+}
+
+impl RedirectToHttps {
+    pub fn new(https_port: Option<u16>) -> Self {
+        Self { https_port }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RedirectToHttps
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RedirectToHttpsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RedirectToHttpsMiddleware {
+            service,
+            https_port: self.https_port,
+        }))
+    }
+}
+
+pub struct RedirectToHttpsMiddleware<S> {
+    service: S,
+    https_port: Option<u16>,
+}
+
+impl<S, B> Service<ServiceRequest> for RedirectToHttpsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        match self.https_port {
+            Some(https_port) if !req.app_config().secure() => {
+                let location = https_location(&req, https_port);
+                let response = HttpResponse::PermanentRedirect()
+                    .insert_header((header::LOCATION, location))
+                    .finish();
+                Box::pin(ready(Ok(req.into_response(response).map_into_right_body())))
+            }
+            _ => {
+                let response = self.service.call(req);
+                Box::pin(async move { Ok(response.await?.map_into_left_body()) })
+            }
+        }
+    }
+}
+
+fn https_location(req: &ServiceRequest, https_port: u16) -> String {
+    let connection_info = req.connection_info();
+    let host = connection_info.host();
+    // Drop the port of the plain HTTP listener, but not the colons of an IPv6 address
+    let host = match host.rfind(':') {
+        Some(colon) if !host.ends_with(']') => &host[..colon],
+        _ => host,
+    };
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    match https_port {
+        443 => format!("https://{host}{path}"),
+        port => format!("https://{host}:{port}{path}"),
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::Context;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+use crate::database::{self, DbPool, DbTransaction};
+use crate::error::error_chain_fmt;
+use crate::models::{CollectionUsage, Usage};
+use crate::storage::DocumentStorage;
+
+/// Records the size of the documents which were added before sizes were kept, by
+/// reading it from storage. Documents missing from storage are left out. This runs
+/// when the server starts, so it only has work to do after an upgrade.
+pub async fn record_missing_sizes(
+    pool: &DbPool,
+    storage: &dyn DocumentStorage,
+) -> anyhow::Result<()> {
+    let missing: Vec<(Uuid,)> =
+        database::query_as("SELECT id FROM Documents WHERE byte_size IS NULL")
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve documents without a size")?;
+    if !missing.is_empty() {
+        log::info!("Recording the size of {} documents", missing.len());
+    }
+
+    for (id,) in missing {
+        let byte_size = match storage.size(&id).await {
+            Ok(byte_size) => byte_size,
+            Err(e) => {
+                log::warn!("Failed to read the size of document {}: {}", id, e);
+                continue;
+            }
+        };
+        database::query("UPDATE Documents SET byte_size = $1 WHERE id = $2")
+            .bind(byte_size as i64)
+            .bind(id)
+            .execute(pool)
+            .await
+            .context("Failed to store document size")?;
+    }
+    Ok(())
+}
+
+/// Counts the documents of `owner` and the bytes they take, in total and for
+/// each collection
+pub async fn user_usage(pool: &DbPool, config: &Settings, owner: Uuid) -> anyhow::Result<Usage> {
+    // SUM of a BIGINT is a NUMERIC on Postgres
+    let rows: Vec<(Option<String>, i64, i64)> = database::query_as(
+        "SELECT collection, COUNT(*), CAST(COALESCE(SUM(byte_size), 0) AS BIGINT)
+        FROM Documents WHERE owner = $1 GROUP BY collection",
+    )
+    .bind(owner)
+    .fetch_all(pool)
+    .await
+    .context("Failed to count documents")?;
+
+    let mut collections = rows
+        .into_iter()
+        .map(|(collection, document_count, byte_size)| CollectionUsage {
+            collection,
+            document_count,
+            byte_size,
+        })
+        .collect::<Vec<_>>();
+    // Postgres and SQLite disagree on where NULL is sorted
+    collections.sort_by(|a, b| a.collection.cmp(&b.collection));
+
+    Ok(Usage {
+        document_count: collections.iter().map(|c| c.document_count).sum(),
+        byte_size: collections.iter().map(|c| c.byte_size).sum(),
+        quota_documents: config.quota_documents,
+        quota_bytes: config.quota_bytes,
+        collections,
+    })
+}
+
+/// What a user may still upload, as counted when the upload started. The upload is
+/// stopped as soon as it goes over either quota, rather than once it has been
+/// received. Uploads running at the same time are only caught by [`check_quota`].
+pub struct Quota {
+    document_count: u64,
+    byte_size: u64,
+    max_documents: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// The quotas of `owner`, counting what is already stored only when a quota is configured
+    pub async fn load(pool: &DbPool, config: &Settings, owner: Uuid) -> anyhow::Result<Self> {
+        let (document_count, byte_size) = match (config.quota_documents, config.quota_bytes) {
+            (None, None) => (0, 0),
+            _ => {
+                let usage = user_usage(pool, config, owner).await?;
+                (usage.document_count as u64, usage.byte_size as u64)
+            }
+        };
+        Ok(Self {
+            document_count,
+            byte_size,
+            max_documents: config.quota_documents,
+            max_bytes: config.quota_bytes,
+        })
+    }
+
+    /// Counts one more document, unless the user has as many as they may have
+    pub fn add_document(&mut self) -> Result<(), QuotaExceeded> {
+        match self.max_documents {
+            Some(max) if self.document_count >= max => Err(QuotaExceeded::Documents(max)),
+            _ => {
+                self.document_count += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts `bytes` more, unless this goes over the quota
+    pub fn add_bytes(&mut self, bytes: u64) -> Result<(), QuotaExceeded> {
+        self.byte_size += bytes;
+        match self.max_bytes {
+            Some(max) if self.byte_size > max => Err(QuotaExceeded::Bytes(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that the documents of `owner`, with the ones added in `transaction`, fit
+/// in the quotas. The user is locked until the transaction ends, so that uploads
+/// running at the same time are counted one after the other.
+pub async fn check_quota(
+    transaction: &mut DbTransaction<'_>,
+    config: &Settings,
+    owner: Uuid,
+) -> Result<(), QuotaError> {
+    if config.quota_documents.is_none() && config.quota_bytes.is_none() {
+        return Ok(());
+    }
+    // Locks the row on Postgres, and the database on SQLite
+    database::query("UPDATE Users SET id = id WHERE id = $1")
+        .bind(owner)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to lock the user")?;
+    let (document_count, byte_size): (i64, i64) = database::query_as(
+        "SELECT COUNT(*), CAST(COALESCE(SUM(byte_size), 0) AS BIGINT)
+        FROM Documents WHERE owner = $1",
+    )
+    .bind(owner)
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to count documents")?;
+
+    match (config.quota_documents, config.quota_bytes) {
+        (Some(max), _) if document_count as u64 > max => Err(QuotaExceeded::Documents(max).into()),
+        (_, Some(max)) if byte_size as u64 > max => Err(QuotaExceeded::Bytes(max).into()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum QuotaError {
+    #[error(transparent)]
+    Exceeded(#[from] QuotaExceeded),
+    #[error(transparent)]
+    UnknownError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for QuotaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum QuotaExceeded {
+    #[error("Storing more than {0} documents is not allowed")]
+    Documents(u64),
+    #[error("Storing more than {0} bytes of documents is not allowed")]
+    Bytes(u64),
+}
+
+impl std::fmt::Debug for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
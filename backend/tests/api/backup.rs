@@ -0,0 +1,338 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use pdf_reader::backup::RestoreSummary;
+use pdf_reader::encryption::is_encrypted;
+use pdf_reader::indexer::SearchResult;
+use pdf_reader::models::{Bookmark, Document};
+
+use crate::api::helpers::{spawn_app, TestApp, TEST_PASSWORD, TEST_USERNAME};
+
+async fn download_backup(app: &TestApp, query: &str) -> Vec<u8> {
+    let response = app
+        .client
+        .get(format!("{}/api/admin/backup{}", &app.address, query))
+        .send()
+        .await
+        .expect("Failed to send backup request");
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("content-disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("pdfreader-backup-"));
+    response.bytes().await.unwrap().to_vec()
+}
+
+async fn upload_backup(app: &TestApp, archive: Vec<u8>, query: &str) -> reqwest::Response {
+    app.client
+        .post(format!("{}/api/admin/restore{}", &app.address, query))
+        .body(archive)
+        .send()
+        .await
+        .expect("Failed to send restore request")
+}
+
+/// Uploads a document with a bookmark, so that there is something to back up
+async fn populate_library(app: &TestApp) -> Document {
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    assert_eq!(
+        app.post_document(pdf).await.status(),
+        reqwest::StatusCode::CREATED
+    );
+    let document = app.fetch_documents().await.remove(0);
+    assert!(app
+        .post_bookmark(document.id, 1, "Start here")
+        .await
+        .status()
+        .is_success());
+    document
+}
+
+/// Checks that the restored library has the document, bookmark and index of
+/// [`populate_library`]. The accounts were replaced, so this logs in again.
+async fn assert_library_restored(app: &TestApp, document: &Document) {
+    let client = TestApp::new_client();
+    let response = app.login(&client, TEST_USERNAME, TEST_PASSWORD).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let documents = client
+        .get(format!("{}/api/documents", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Document>>()
+        .await
+        .unwrap();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].id, document.id);
+    assert_eq!(documents[0].name, document.name);
+
+    let bookmarks = client
+        .get(format!(
+            "{}/api/documents/{}/bookmarks",
+            &app.address, document.id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Bookmark>>()
+        .await
+        .unwrap();
+    assert_eq!(bookmarks.len(), 1);
+    assert_eq!(bookmarks[0].description, "Start here");
+
+    let response = client
+        .get(format!("{}/api/documents/{}", &app.address, document.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        response.bytes().await.unwrap().as_ref(),
+        include_bytes!("../../tests/test_files/pdf-sample.pdf")
+    );
+
+    let results = client
+        .get(format!(
+            "{}/api/documents/{}/search?q=test",
+            &app.address, document.id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<SearchResult>>()
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].page, 1);
+}
+
+#[actix_rt::test]
+async fn backup_can_be_restored_into_an_empty_library() {
+    let app = spawn_app().await;
+    let document = populate_library(&app).await;
+    let archive = download_backup(&app, "").await;
+
+    let other = spawn_app().await;
+    let response = upload_backup(&other, archive, "").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let summary = response.json::<RestoreSummary>().await.unwrap();
+    assert_eq!(summary.documents, 1);
+    assert!(!summary.rebuilt_index);
+
+    assert_library_restored(&other, &document).await;
+}
+
+#[actix_rt::test]
+async fn index_is_rebuilt_when_left_out_of_the_backup() {
+    let app = spawn_app().await;
+    let document = populate_library(&app).await;
+    let archive = download_backup(&app, "?without_index=true").await;
+
+    let other = spawn_app().await;
+    let response = upload_backup(&other, archive, "").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(
+        response
+            .json::<RestoreSummary>()
+            .await
+            .unwrap()
+            .rebuilt_index
+    );
+
+    assert_library_restored(&other, &document).await;
+}
+
+#[actix_rt::test]
+async fn restore_requires_force_to_replace_a_library_with_documents() {
+    let app = spawn_app().await;
+    let document = populate_library(&app).await;
+    let archive = download_backup(&app, "").await;
+
+    let response = upload_backup(&app, archive.clone(), "").await;
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+
+    let response = upload_backup(&app, archive, "?force=true").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_library_restored(&app, &document).await;
+}
+
+#[actix_rt::test]
+async fn restore_rejects_an_archive_with_a_modified_file() {
+    let app = spawn_app().await;
+    populate_library(&app).await;
+    let archive = download_backup(&app, "").await;
+
+    // Repack the archive with one byte of the document changed
+    let mut original = tar::Archive::new(GzDecoder::new(archive.as_slice()));
+    let mut tampered = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for entry in original.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut header = entry.header().clone();
+        let path = entry.path().unwrap().into_owned();
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(&mut entry, &mut contents).unwrap();
+        if path.starts_with("documents") {
+            contents[100] ^= 0xff;
+        }
+        tampered
+            .append_data(&mut header, path, contents.as_slice())
+            .unwrap();
+    }
+    let tampered = tampered.into_inner().unwrap().finish().unwrap();
+
+    let other = spawn_app().await;
+    let response = upload_backup(&other, tampered, "").await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(other.fetch_documents().await.is_empty());
+}
+
+#[actix_rt::test]
+async fn failed_restore_leaves_the_library_as_it_was() {
+    // The index is rebuilt from the documents of a backup made without it, which
+    // fails on a document that is not a PDF
+    let app = spawn_app().await;
+    let unreadable = app.insert_document("unreadable.pdf").await;
+    std::fs::write(
+        app.config
+            .documents_storage_path()
+            .join(format!("{unreadable}.pdf")),
+        b"not a pdf",
+    )
+    .unwrap();
+    let archive = download_backup(&app, "?without_index=true").await;
+
+    let other = spawn_app().await;
+    let kept = other.insert_document("kept.pdf").await;
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    std::fs::write(
+        other
+            .config
+            .documents_storage_path()
+            .join(format!("{kept}.pdf")),
+        pdf,
+    )
+    .unwrap();
+    assert!(other
+        .post_bookmark(kept, 1, "Start here")
+        .await
+        .status()
+        .is_success());
+
+    let response = upload_backup(&other, archive, "?force=true").await;
+    assert!(response.status().is_server_error());
+
+    let documents = other.fetch_documents().await;
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].id, kept);
+    let bookmarks = other
+        .client
+        .get(format!(
+            "{}/api/documents/{}/bookmarks",
+            &other.address, kept
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Bookmark>>()
+        .await
+        .unwrap();
+    assert_eq!(bookmarks.len(), 1);
+    let response = other
+        .client
+        .get(format!("{}/api/documents/{}", &other.address, kept))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.bytes().await.unwrap().as_ref(), pdf);
+    let response = other
+        .client
+        .get(format!(
+            "{}/api/documents/{}/search?q=test",
+            &other.address, kept
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn page_text_is_encrypted_in_backups_of_encrypted_storage() {
+    let app = spawn_app().await;
+    let server = app
+        .build_server(|configuration| configuration.encrypt_storage = true)
+        .await;
+    let address = format!("http://localhost:{}", server.port);
+    tokio::spawn(server.run_until_stopped());
+
+    let archive = app
+        .client
+        .get(format!("{address}/api/admin/backup"))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap()
+        .to_vec();
+    let mut entries = tar::Archive::new(GzDecoder::new(archive.as_slice()));
+    let mut index = entries
+        .entries()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.path().unwrap().ends_with("index/pages.jsonl"))
+        .expect("The index is missing from the archive");
+    let mut contents = Vec::new();
+    std::io::Read::read_to_end(&mut index, &mut contents).unwrap();
+    assert!(is_encrypted(&contents));
+
+    let response = app
+        .client
+        .post(format!("{address}/api/admin/restore?force=true"))
+        .body(archive)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(
+        !response
+            .json::<RestoreSummary>()
+            .await
+            .unwrap()
+            .rebuilt_index
+    );
+}
+
+#[actix_rt::test]
+async fn restore_rejects_something_which_is_not_a_backup() {
+    let app = spawn_app().await;
+
+    let response = upload_backup(&app, b"not an archive".to_vec(), "").await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn backup_and_restore_require_an_administrator() {
+    let app = spawn_app().await;
+    let (_, client) = app.create_user("visitor", false).await;
+
+    let response = client
+        .get(format!("{}/api/admin/backup", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let response = client
+        .post(format!("{}/api/admin/restore", &app.address))
+        .body(Vec::new())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+}
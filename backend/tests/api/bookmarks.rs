@@ -1,22 +1,15 @@
 use fake::Fake;
-use pdf_reader::models::Bookmark;
+use pdf_reader::database;
+use pdf_reader::models::{Bookmark, ExportedDocument};
 use uuid::Uuid;
 
-use crate::api::helpers::spawn_app;
+use crate::api::helpers::{spawn_app, TestApp};
 
 #[actix_rt::test]
 async fn add_bookmark() {
     let app = spawn_app().await;
 
-    let document_id = Uuid::new_v4();
-    sqlx::query!(
-        "INSERT INTO Documents (id, name) VALUES ($1, $2)",
-        document_id,
-        (3..60).fake::<String>()
-    )
-    .execute(&app.db_pool)
-    .await
-    .expect("Failed to insert preseeded document");
+    let document_id = app.insert_document(&(3..60).fake::<String>()).await;
 
     let response = app
         .post_bookmark(document_id, 10, "An interesting page")
@@ -28,7 +21,7 @@ async fn add_bookmark() {
         .await
         .expect("Failed to deserialize response");
 
-    let bookmark = sqlx::query_as!(Bookmark, "SELECT * FROM Bookmarks")
+    let bookmark: Bookmark = database::query_as("SELECT * FROM Bookmarks")
         .fetch_one(&app.db_pool)
         .await
         .unwrap();
@@ -42,21 +35,14 @@ async fn add_bookmark() {
     assert_eq!(bookmark.deleted_on, None);
     assert_eq!(bookmark.description, response_body.description);
     assert_eq!(bookmark.description, "An interesting page");
+    assert_eq!(bookmark.owner, Some(app.user_id));
 }
 
 #[actix_rt::test]
 async fn get_bookmarks() {
     let app = spawn_app().await;
 
-    let document_id = Uuid::new_v4();
-    sqlx::query!(
-        "INSERT INTO Documents (id, name) VALUES ($1, $2)",
-        document_id,
-        (3..60).fake::<String>()
-    )
-    .execute(&app.db_pool)
-    .await
-    .expect("Failed to insert preseeded document");
+    let document_id = app.insert_document(&(3..60).fake::<String>()).await;
 
     let response = app
         .post_bookmark(document_id, 10, "An interesting page")
@@ -71,7 +57,12 @@ async fn get_bookmarks() {
     assert_eq!(response.status(), reqwest::StatusCode::CREATED);
 
     let url = format!("{}/api/documents/{}/bookmarks", &app.address, document_id);
-    let response = reqwest::get(url).await.expect("Failed to fetch bookmarks");
+    let response = app
+        .client
+        .get(url)
+        .send()
+        .await
+        .expect("Failed to fetch bookmarks");
     assert_eq!(response.status(), reqwest::StatusCode::OK);
 
     let bookmarks = response
@@ -93,7 +84,8 @@ async fn delete_bookmark_which_does_not_exist() {
         Uuid::new_v4()
     );
 
-    let response = reqwest::Client::new()
+    let response = app
+        .client
         .delete(url)
         .send()
         .await
@@ -106,15 +98,7 @@ async fn delete_bookmark_which_does_not_exist() {
 async fn delete_bookmark() {
     let app = spawn_app().await;
 
-    let document_id = Uuid::new_v4();
-    sqlx::query!(
-        "INSERT INTO Documents (id, name) VALUES ($1, $2)",
-        document_id,
-        (3..60).fake::<String>()
-    )
-    .execute(&app.db_pool)
-    .await
-    .expect("Failed to insert preseeded document");
+    let document_id = app.insert_document(&(3..60).fake::<String>()).await;
 
     let response = app
         .post_bookmark(document_id, 10, "An interesting page")
@@ -130,7 +114,8 @@ async fn delete_bookmark() {
         "{}/api/documents/{}/bookmarks/{}",
         app.address, document_id, bookmark.id
     );
-    let response = reqwest::Client::new()
+    let response = app
+        .client
         .delete(url)
         .send()
         .await
@@ -138,3 +123,317 @@ async fn delete_bookmark() {
 
     assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
 }
+
+#[actix_rt::test]
+async fn bookmarks_are_private_to_each_user() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+
+    let response = app
+        .post_bookmark(document_id, 10, "An interesting page")
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let bookmark = response.json::<Bookmark>().await.unwrap();
+
+    let (_, other_client) = app.create_user("another.reader", false).await;
+    let url = format!("{}/api/documents/{}/bookmarks", &app.address, document_id);
+    let bookmarks = other_client
+        .get(&url)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Bookmark>>()
+        .await
+        .unwrap();
+    assert!(bookmarks.is_empty());
+
+    let response = other_client
+        .delete(format!("{}/{}", url, bookmark.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+/// Uploads the sample document, whose first page is indexed, and bookmarks it
+async fn upload_bookmarked_document(app: &TestApp) -> Uuid {
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    assert_eq!(
+        app.post_document(pdf).await.status(),
+        reqwest::StatusCode::CREATED
+    );
+    let document_id = app.fetch_documents().await.remove(0).id;
+    let response = app
+        .post_bookmark(document_id, 1, "First, the \"introduction\"")
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    document_id
+}
+
+async fn export(app: &TestApp, client: &reqwest::Client, path: &str) -> reqwest::Response {
+    client
+        .get(format!("{}/api/{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to send export request")
+}
+
+#[actix_rt::test]
+async fn bookmarks_are_exported_with_the_text_of_their_page() {
+    let app = spawn_app().await;
+    let document_id = upload_bookmarked_document(&app).await;
+
+    let response = export(
+        &app,
+        &app.client,
+        &format!("documents/{document_id}/bookmarks/export?format=json"),
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("content-disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("bookmarks.json"));
+    let exported = response.json::<Vec<ExportedDocument>>().await.unwrap();
+
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0].id, document_id);
+    assert_eq!(exported[0].title, "file.pdf");
+    assert_eq!(exported[0].bookmarks.len(), 1);
+    let bookmark = &exported[0].bookmarks[0];
+    assert_eq!(bookmark.page, 1);
+    assert_eq!(bookmark.description, "First, the \"introduction\"");
+    let snippet = bookmark.snippet.as_deref().unwrap();
+    assert!(!snippet.is_empty());
+    assert!(!snippet.contains('\n'));
+}
+
+#[actix_rt::test]
+async fn bookmarks_are_exported_as_markdown_and_csv() {
+    let app = spawn_app().await;
+    let document_id = upload_bookmarked_document(&app).await;
+    let path = format!("documents/{document_id}/bookmarks/export");
+
+    let response = export(&app, &app.client, &path).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/markdown"));
+    let markdown = response.text().await.unwrap();
+    assert!(markdown.starts_with("# Bookmarks\n"));
+    assert!(markdown.contains("\n## file.pdf\n"));
+    assert!(markdown.contains("\n### Page 1: First, the \"introduction\"\n"));
+    assert!(markdown.contains("\n> "));
+
+    let response = export(&app, &app.client, &format!("{path}?format=csv")).await;
+    assert!(response
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let csv = response.text().await.unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0],
+        "document_id,title,page,description,added_on,snippet"
+    );
+    assert!(lines[1].starts_with(&format!(
+        "{document_id},file.pdf,1,\"First, the \"\"introduction\"\"\","
+    )));
+}
+
+#[actix_rt::test]
+async fn csv_export_does_not_start_fields_with_a_formula() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("=1+1.pdf").await;
+    for description in ["=HYPERLINK(\"http://evil\")", "+1", "-1", "@SUM(A1)", "1-2"] {
+        let response = app.post_bookmark(document_id, 1, description).await;
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    }
+
+    let response = export(
+        &app,
+        &app.client,
+        &format!("documents/{document_id}/bookmarks/export?format=csv"),
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let csv = response.text().await.unwrap();
+    let descriptions = csv
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields = line
+                .strip_prefix(&format!("{document_id},'=1+1.pdf,1,"))
+                .unwrap();
+            fields[..fields.rfind(',').unwrap()]
+                .rsplit_once(',')
+                .unwrap()
+                .0
+                .to_owned()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        descriptions,
+        [
+            "\"'=HYPERLINK(\"\"http://evil\"\")\"",
+            "'+1",
+            "'-1",
+            "'@SUM(A1)",
+            "1-2"
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn csv_export_does_not_start_fields_with_a_tab_or_carriage_return() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("\t=1+1.pdf").await;
+    let response = app.post_bookmark(document_id, 1, "\r=1+1").await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let response = export(
+        &app,
+        &app.client,
+        &format!("documents/{document_id}/bookmarks/export?format=csv"),
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let csv = response.text().await.unwrap();
+
+    assert!(csv.contains(&format!("{document_id},'\t=1+1.pdf,1,\"'\r=1+1\",")));
+}
+
+#[actix_rt::test]
+async fn library_export_has_the_bookmarks_of_the_current_user() {
+    let app = spawn_app().await;
+    let document_id = upload_bookmarked_document(&app).await;
+    let unmarked = app.insert_document("unmarked.pdf").await;
+    let (_, other_client) = app.create_user("another.reader", false).await;
+
+    let exported = export(&app, &app.client, "bookmarks/export?format=json")
+        .await
+        .json::<Vec<ExportedDocument>>()
+        .await
+        .unwrap();
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0].id, document_id);
+    assert!(exported.iter().all(|d| d.id != unmarked));
+
+    let exported = export(&app, &other_client, "bookmarks/export?format=json")
+        .await
+        .json::<Vec<ExportedDocument>>()
+        .await
+        .unwrap();
+    assert!(exported.is_empty());
+}
+
+#[actix_rt::test]
+async fn export_rejects_unknown_formats_and_documents() {
+    let app = spawn_app().await;
+    let document_id = upload_bookmarked_document(&app).await;
+
+    let response = export(
+        &app,
+        &app.client,
+        &format!("documents/{document_id}/bookmarks/export?format=docx"),
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = export(
+        &app,
+        &app.client,
+        &format!("documents/{}/bookmarks/export", Uuid::new_v4()),
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+/// Uploads a PDF with a sticky note on the first page, a drawing on the second and
+/// an outline entry for each page, and returns its bookmarks ordered by page
+async fn upload_annotated_document(app: &TestApp, query: &str) -> Vec<(i32, String, bool)> {
+    let pdf = include_bytes!("../../tests/test_files/annotated.pdf");
+    let part = reqwest::multipart::Part::bytes(pdf.to_vec()).file_name("annotated.pdf");
+    let response = app
+        .client
+        .post(format!("{}/api/documents{}", &app.address, query))
+        .multipart(reqwest::multipart::Form::new().part("file", part))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let document_id = app.fetch_documents().await.remove(0).id;
+    let mut bookmarks = app
+        .client
+        .get(format!(
+            "{}/api/documents/{}/bookmarks",
+            &app.address, document_id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Bookmark>>()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|b| (b.page, b.description, b.imported))
+        .collect::<Vec<_>>();
+    bookmarks.sort();
+    bookmarks
+}
+
+#[actix_rt::test]
+async fn annotations_and_outline_are_imported_when_asked_for() {
+    let app = spawn_app().await;
+
+    let bookmarks =
+        upload_annotated_document(&app, "?import_annotations=true&import_outline=true").await;
+    assert_eq!(
+        bookmarks,
+        vec![
+            (1, "Chapter One".to_owned(), true),
+            (1, "Read this first".to_owned(), true),
+            (2, "Chapter Two".to_owned(), true),
+            (2, "Drawing".to_owned(), true),
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn only_annotations_are_imported_without_the_outline() {
+    let app = spawn_app().await;
+
+    let bookmarks = upload_annotated_document(&app, "?import_annotations=true").await;
+    assert_eq!(
+        bookmarks,
+        vec![
+            (1, "Read this first".to_owned(), true),
+            (2, "Drawing".to_owned(), true),
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn nothing_is_imported_by_default() {
+    let app = spawn_app().await;
+
+    let bookmarks = upload_annotated_document(&app, "").await;
+    assert!(bookmarks.is_empty());
+
+    let document_id = app.fetch_documents().await.remove(0).id;
+    let response = app.post_bookmark(document_id, 1, "Mine").await;
+    assert!(!response.json::<Bookmark>().await.unwrap().imported);
+}
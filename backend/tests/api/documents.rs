@@ -1,8 +1,13 @@
-use pdf_reader::models::Document;
+use pdf_reader::database;
+use pdf_reader::indexer::SearchResult;
+use pdf_reader::models::{
+    AddBookmarkRequest, Bookmark, CreateShareRequest, CreatedShare, Document, UpdateDocumentRequest,
+};
+use sha2::{Digest, Sha256};
 use std::{collections::HashMap, io::Write};
 use uuid::Uuid;
 
-use crate::api::helpers::spawn_app;
+use crate::api::helpers::{spawn_app, TestApp};
 
 #[actix_rt::test]
 async fn upload_document() {
@@ -13,10 +18,12 @@ async fn upload_document() {
 
     assert_eq!(response.status(), reqwest::StatusCode::CREATED);
 
-    let document = sqlx::query_as!(Document, "SELECT * FROM Documents")
-        .fetch_one(&app.db_pool)
-        .await
-        .unwrap();
+    let document = app.fetch_documents().await.remove(0);
+    assert_eq!(document.owner, Some(app.user_id));
+    assert_eq!(
+        document.content_hash,
+        Some(hex::encode(Sha256::digest(pdf)))
+    );
 
     let documents_location = &app.config.storage_location.join("documents");
     let storage_contents =
@@ -25,7 +32,7 @@ async fn upload_document() {
         .map(Result::ok)
         .map(Option::unwrap)
         .collect();
-    let file = file.get(0).unwrap();
+    let file = file.first().unwrap();
     let expected_file_name = format!("{}.pdf", document.id);
     assert_eq!(file.file_name().to_string_lossy(), expected_file_name);
 
@@ -36,15 +43,9 @@ async fn upload_document() {
 #[actix_rt::test]
 async fn update_document() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    let client = &app.client;
 
-    let document_id = Uuid::new_v4();
-    sqlx::query("INSERT INTO Documents (id, name) VALUES ($1, $2)")
-        .bind(document_id)
-        .bind("adocument")
-        .execute(&app.db_pool)
-        .await
-        .unwrap();
+    let document_id = app.insert_document("adocument").await;
 
     let url = format!("{}/api/documents/{}", &app.address, document_id);
     let mut params = HashMap::new();
@@ -58,10 +59,7 @@ async fn update_document() {
 
     assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
 
-    let document = sqlx::query_as!(Document, "SELECT * FROM Documents")
-        .fetch_one(&app.db_pool)
-        .await
-        .unwrap();
+    let document = app.fetch_documents().await.remove(0);
 
     assert_eq!(document.current_page, 10);
 }
@@ -69,15 +67,9 @@ async fn update_document() {
 #[actix_rt::test]
 async fn get_document() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    let client = &app.client;
 
-    let document_id = Uuid::new_v4();
-    sqlx::query("INSERT INTO Documents (id, name) VALUES ($1, $2)")
-        .bind(document_id)
-        .bind("adocument")
-        .execute(&app.db_pool)
-        .await
-        .unwrap();
+    let document_id = app.insert_document("adocument").await;
 
     let documents_location = &app.config.storage_location.join("documents");
     let document_path = documents_location.join(format!("{}.pdf", document_id));
@@ -109,3 +101,650 @@ async fn get_document() {
 
     assert_eq!(response.text().await.unwrap(), "pdfcontents");
 }
+
+fn write_document(app: &TestApp, document_id: Uuid, contents: &[u8]) {
+    let documents_location = &app.config.storage_location.join("documents");
+    let document_path = documents_location.join(format!("{}.pdf", document_id));
+
+    let mut file = std::fs::File::create(document_path).unwrap();
+    file.write_all(contents).unwrap();
+    file.flush().unwrap();
+}
+
+#[actix_rt::test]
+async fn get_document_range() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    write_document(&app, document_id, b"0123456789");
+    let url = format!("{}/api/documents/{}", &app.address, document_id);
+
+    let response = app
+        .client
+        .get(&url)
+        .header("Range", "bytes=2-5")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers().get("Content-Range").unwrap(),
+        "bytes 2-5/10"
+    );
+    assert_eq!(response.headers().get("Accept-Ranges").unwrap(), "bytes");
+    assert_eq!(response.text().await.unwrap(), "2345");
+
+    let response = app
+        .client
+        .get(&url)
+        .header("Range", "bytes=-3")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.text().await.unwrap(), "789");
+
+    let response = app
+        .client
+        .get(&url)
+        .header("Range", "bytes=20-30")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.status(),
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+    );
+    assert_eq!(
+        response.headers().get("Content-Range").unwrap(),
+        "bytes */10"
+    );
+
+    // A stale If-Range means the whole document is sent instead
+    let response = app
+        .client
+        .get(&url)
+        .header("Range", "bytes=2-5")
+        .header("If-Range", "\"outdated\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "0123456789");
+}
+
+#[actix_rt::test]
+async fn get_document_caching_headers() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    write_document(&app, document_id, b"pdfcontents");
+    let url = format!("{}/api/documents/{}", &app.address, document_id);
+
+    let response = app.client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let etag = response.headers().get("ETag").unwrap().clone();
+    assert_eq!(
+        etag,
+        "\"cbe73e6f6ef57f9ed8b6c5a8f5f67ddc3a20021983205fd36a3609ccec8f2e12\""
+    );
+    assert!(response.headers().contains_key("Last-Modified"));
+    assert_eq!(
+        response.headers().get("Cache-Control").unwrap(),
+        app.config.document_cache_control.as_str()
+    );
+
+    let response = app
+        .client
+        .get(&url)
+        .header("If-None-Match", etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+    assert!(response.text().await.unwrap().is_empty());
+
+    let response = app
+        .client
+        .get(&url)
+        .header("If-None-Match", "\"something else\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn get_document_inline() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    write_document(&app, document_id, b"pdfcontents");
+
+    let url = format!("{}/api/documents/{}?inline=true", &app.address, document_id);
+    let response = app.client.get(url).send().await.unwrap();
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        response.headers().get("Content-Disposition").unwrap(),
+        "inline; filename=\"adocument\""
+    );
+}
+
+#[actix_rt::test]
+async fn update_document_metadata() {
+    let app = spawn_app().await;
+
+    let document_id = app.insert_document("1234.5678v2.pdf").await;
+
+    let documents_location = &app.config.storage_location.join("documents");
+    std::fs::write(
+        documents_location.join(format!("{}.pdf", document_id)),
+        b"pdfcontents",
+    )
+    .unwrap();
+
+    let request = UpdateDocumentRequest {
+        name: Some("  Attention Is All You Need.pdf ".to_owned()),
+        title: Some("Attention Is All You Need".to_owned()),
+        author: Some("Vaswani et al.".to_owned()),
+        notes: Some("Assigned reading for week 3".to_owned()),
+        ..Default::default()
+    };
+    let response = app.patch_document(document_id, &request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let document = app.fetch_documents().await.remove(0);
+
+    assert_eq!(document.name, "Attention Is All You Need.pdf");
+    assert_eq!(document.title.as_deref(), Some("Attention Is All You Need"));
+    assert_eq!(document.author.as_deref(), Some("Vaswani et al."));
+    assert_eq!(
+        document.notes.as_deref(),
+        Some("Assigned reading for week 3")
+    );
+    assert_eq!(document.current_page, 1);
+
+    let url = format!("{}/api/documents/{}", &app.address, document_id);
+    let response = app.client.get(url).send().await.unwrap();
+    assert_eq!(
+        response.headers().get("Content-Disposition").unwrap(),
+        "attachment; filename=\"Attention Is All You Need.pdf\""
+    );
+
+    let request = UpdateDocumentRequest {
+        notes: Some("".to_owned()),
+        ..Default::default()
+    };
+    let response = app.patch_document(document_id, &request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let document = app.fetch_documents().await.remove(0);
+    assert_eq!(document.notes, None);
+    assert_eq!(document.title.as_deref(), Some("Attention Is All You Need"));
+}
+
+#[actix_rt::test]
+async fn update_document_which_does_not_exist() {
+    let app = spawn_app().await;
+
+    let request = UpdateDocumentRequest {
+        name: Some("A new name".to_owned()),
+        ..Default::default()
+    };
+    let response = app.patch_document(Uuid::new_v4(), &request).await;
+
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn update_document_with_invalid_name() {
+    let app = spawn_app().await;
+
+    let document_id = app.insert_document("adocument").await;
+
+    for name in ["", "   ", "../etc/passwd", "a\nb"] {
+        let request = UpdateDocumentRequest {
+            name: Some(name.to_owned()),
+            ..Default::default()
+        };
+        let response = app.patch_document(document_id, &request).await;
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "Name {:?} was not rejected",
+            name
+        );
+    }
+
+    let response = app
+        .patch_document(document_id, &UpdateDocumentRequest::default())
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn reading_progress_is_tracked_per_user() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    let (other_user, other_client) = app.create_user("another.reader", false).await;
+
+    let request = UpdateDocumentRequest {
+        current_page: Some(5),
+        ..Default::default()
+    };
+    let response = app.patch_document(document_id, &request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let documents = other_client
+        .get(format!("{}/api/documents", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Document>>()
+        .await
+        .unwrap();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].current_page, 1);
+    assert_eq!(documents[0].owner, Some(app.user_id));
+
+    let response = other_client
+        .patch(format!("{}/api/documents/{}", &app.address, document_id))
+        .json(&UpdateDocumentRequest {
+            current_page: Some(7),
+            ..Default::default()
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let (page,): (i32,) = database::query_as(
+        "SELECT current_page FROM ReadingProgress WHERE reader = $1 AND document = $2",
+    )
+    .bind(other_user)
+    .bind(document_id)
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(page, 7);
+    assert_eq!(app.fetch_documents().await[0].current_page, 5);
+}
+
+#[actix_rt::test]
+async fn only_owner_can_change_document_details() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    let (_, other_client) = app.create_user("another.reader", false).await;
+
+    let response = other_client
+        .patch(format!("{}/api/documents/{}", &app.address, document_id))
+        .json(&UpdateDocumentRequest {
+            name: Some("Renamed".to_owned()),
+            ..Default::default()
+        })
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(app.fetch_documents().await[0].name, "adocument");
+}
+
+#[actix_rt::test]
+async fn get_document_with_bookmarks_in_its_outline() {
+    let app = spawn_app().await;
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    assert_eq!(
+        app.post_document(pdf).await.status(),
+        reqwest::StatusCode::CREATED
+    );
+    let document_id = app.fetch_documents().await.remove(0).id;
+    assert!(app
+        .post_bookmark(document_id, 1, "Start here")
+        .await
+        .status()
+        .is_success());
+    // Pages the document does not have are left out of the outline
+    assert!(app
+        .post_bookmark(document_id, 40, "Nowhere")
+        .await
+        .status()
+        .is_success());
+
+    let url = format!(
+        "{}/api/documents/{}?with_bookmarks=true",
+        &app.address, document_id
+    );
+    let response = app.client.get(url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/pdf"
+    );
+    assert_eq!(
+        response.headers().get("Content-Disposition").unwrap(),
+        "attachment; filename=\"file.pdf\""
+    );
+    let bytes = response.bytes().await.unwrap().to_vec();
+
+    let document = pdf_reader::startup::PDFIUM
+        .load_pdf_from_byte_vec(bytes, None)
+        .expect("The copy is not a valid PDF");
+    let titles = document
+        .bookmarks()
+        .iter()
+        .filter_map(|b| b.title())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, vec!["p. 1: Start here"]);
+
+    // Without the option the stored file is served unchanged
+    let url = format!("{}/api/documents/{}", &app.address, document_id);
+    let response = app.client.get(url).send().await.unwrap();
+    assert_eq!(response.bytes().await.unwrap().as_ref(), pdf);
+}
+
+#[actix_rt::test]
+async fn bookmarks_are_not_added_to_protected_documents() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("protected.pdf").await;
+    std::fs::write(
+        app.config
+            .documents_storage_path()
+            .join(format!("{document_id}.pdf")),
+        PROTECTED_PDF,
+    )
+    .unwrap();
+    assert!(app
+        .post_bookmark(document_id, 1, "Start here")
+        .await
+        .status()
+        .is_success());
+
+    let url = format!(
+        "{}/api/documents/{}?with_bookmarks=true",
+        &app.address, document_id
+    );
+    let response = app.client.get(url).send().await.unwrap();
+
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["code"], "conflict");
+}
+
+#[actix_rt::test]
+async fn get_document_with_highlights_as_annotations() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("highlighted.pdf").await;
+    std::fs::write(
+        app.config
+            .documents_storage_path()
+            .join(format!("{document_id}.pdf")),
+        include_bytes!("../../tests/test_files/pdf-sample.pdf"),
+    )
+    .unwrap();
+    let url = format!("{}/api/documents/{}/bookmarks", &app.address, document_id);
+    let response = app
+        .client
+        .post(&url)
+        .json(&AddBookmarkRequest {
+            page: 1,
+            description: "Größte Zahl".to_owned(),
+            highlight_area: Some("72 700 300 714.5".to_owned()),
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let bookmark = response.json::<Bookmark>().await.unwrap();
+    assert_eq!(bookmark.highlight_area.as_deref(), Some("72 700 300 714.5"));
+    // Bookmarks without an area are not highlights
+    assert!(app
+        .post_bookmark(document_id, 1, "Start here")
+        .await
+        .status()
+        .is_success());
+
+    let url = format!(
+        "{}/api/documents/{}?with_highlights=true",
+        &app.address, document_id
+    );
+    let response = app.client.get(url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let bytes = response.bytes().await.unwrap();
+
+    let document = lopdf::Document::load_mem(&bytes).expect("The copy is not a valid PDF");
+    let page = document.get_pages()[&1];
+    let annotations = document.get_page_annotations(page);
+    assert_eq!(annotations.len(), 1);
+    let highlight = annotations[0];
+    assert_eq!(
+        highlight.get(b"Subtype").unwrap().as_name_str().unwrap(),
+        "Highlight"
+    );
+    let rect = highlight
+        .get(b"Rect")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|side| side.as_float().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(rect, vec![72.0, 700.0, 300.0, 714.5]);
+    let contents = highlight.get(b"Contents").unwrap().as_str().unwrap();
+    assert_eq!(
+        contents[2..]
+            .chunks(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>(),
+        "Größte Zahl".encode_utf16().collect::<Vec<_>>()
+    );
+}
+
+#[actix_rt::test]
+async fn highlights_need_a_valid_area() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("highlighted.pdf").await;
+    let url = format!("{}/api/documents/{}/bookmarks", &app.address, document_id);
+
+    for area in ["72 700 300", "300 700 72 714", "72 700 300 NaN"] {
+        let response = app
+            .client
+            .post(&url)
+            .json(&AddBookmarkRequest {
+                page: 1,
+                description: "Somewhere".to_owned(),
+                highlight_area: Some(area.to_owned()),
+            })
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::BAD_REQUEST,
+            "{area}"
+        );
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["details"]["field"], "highlight_area");
+    }
+}
+
+const PROTECTED_PDF: &[u8] = include_bytes!("../../tests/test_files/protected.pdf");
+
+async fn post_protected_document(
+    address: &str,
+    client: &reqwest::Client,
+    password: Option<&str>,
+    keep_password: bool,
+) -> reqwest::Response {
+    let mut form = reqwest::multipart::Form::new();
+    if let Some(password) = password {
+        form = form.text("password", password.to_owned());
+    }
+    if keep_password {
+        form = form.text("keep_password", "true");
+    }
+    let file = reqwest::multipart::Part::bytes(PROTECTED_PDF).file_name("protected.pdf");
+    client
+        .post(format!("{address}/api/documents"))
+        .multipart(form.part("file", file))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn fetch_shared_page(app: &TestApp, document: Uuid) -> reqwest::Response {
+    let share = app
+        .client
+        .post(format!("{}/api/shares", &app.address))
+        .json(&CreateShareRequest {
+            document,
+            ..Default::default()
+        })
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json::<CreatedShare>()
+        .await
+        .unwrap();
+    reqwest::get(format!(
+        "{}/api/shared/{}/pages/1",
+        &app.address, share.token
+    ))
+    .await
+    .expect("Failed to send request")
+}
+
+#[actix_rt::test]
+async fn upload_protected_document_without_the_right_password() {
+    let app = spawn_app().await;
+
+    for password in [None, Some("wrong")] {
+        let response = post_protected_document(&app.address, &app.client, password, false).await;
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["code"], "password_required");
+    }
+    assert!(app.fetch_documents().await.is_empty());
+}
+
+#[actix_rt::test]
+async fn upload_with_the_password_after_a_file_is_refused() {
+    let app = spawn_app().await;
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    let form = reqwest::multipart::Form::new()
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(pdf.to_vec()).file_name("file.pdf"),
+        )
+        .text("password", "secret")
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(PROTECTED_PDF).file_name("protected.pdf"),
+        );
+
+    let response = app
+        .client
+        .post(format!("{}/api/documents", app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["details"]["field"], "password");
+    assert!(app.fetch_documents().await.is_empty());
+}
+
+#[actix_rt::test]
+async fn failed_upload_leaves_nothing_in_the_index() {
+    let app = spawn_app().await;
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    let form = reqwest::multipart::Form::new()
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(pdf.to_vec()).file_name("file.pdf"),
+        )
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(PROTECTED_PDF).file_name("protected.pdf"),
+        );
+
+    let response = app
+        .client
+        .post(format!("{}/api/documents", app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(app.fetch_documents().await.is_empty());
+    let index = tantivy::Index::open_in_dir(app.config.documents_contents_path())
+        .expect("Failed to open index");
+    let searcher = index.reader().expect("Failed to read index").searcher();
+    assert_eq!(searcher.num_docs(), 0);
+}
+
+#[actix_rt::test]
+async fn upload_protected_document_with_its_password() {
+    let app = spawn_app().await;
+
+    let response = post_protected_document(&app.address, &app.client, Some("secret"), false).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let document = app.fetch_documents().await.remove(0);
+    let results = app
+        .client
+        .get(format!(
+            "{}/api/documents/{}/search?q=protected",
+            app.address, document.id
+        ))
+        .send()
+        .await
+        .expect("Failed to send search request")
+        .json::<Vec<SearchResult>>()
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+
+    // The password was not kept, so the pages cannot be rendered
+    let response = fetch_shared_page(&app, document.id).await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_rt::test]
+async fn kept_password_is_stored_encrypted_and_used_to_render_pages() {
+    let app = spawn_app().await;
+
+    let response = post_protected_document(&app.address, &app.client, Some("secret"), true).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let document = app.fetch_documents().await.remove(0);
+    let (stored,): (Option<String>,) =
+        database::query_as("SELECT encrypted_password FROM Documents WHERE id = $1")
+            .bind(document.id)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    let stored = stored.expect("The password was not kept");
+    assert!(!stored.contains(&hex::encode("secret")));
+
+    let response = fetch_shared_page(&app, document.id).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], "image/png");
+}
+
+#[actix_rt::test]
+async fn password_cannot_be_kept_without_a_server_key() {
+    let app = spawn_app().await;
+    let server = app
+        .build_server(|configuration| configuration.secret_key = None)
+        .await;
+    let address = format!("http://localhost:{}", server.port);
+    tokio::spawn(server.run_until_stopped());
+
+    let response = post_protected_document(&address, &app.client, Some("secret"), true).await;
+
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(app.fetch_documents().await.is_empty());
+}
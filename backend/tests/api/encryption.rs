@@ -0,0 +1,142 @@
+use pdf_reader::configuration::Settings;
+use pdf_reader::database;
+use pdf_reader::encryption::{rotate_key, FileEncryption};
+use pdf_reader::indexer::SearchResult;
+use pdf_reader::secrets::SecretKey;
+use pdf_reader::storage::{content_hash, FileSystemStorage};
+use sha2::{Digest, Sha256};
+
+use crate::api::helpers::{spawn_app, TestApp, TEST_SECRET_KEY};
+
+const PDF: &[u8] = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+
+/// Starts a server which encrypts what it stores, and returns its address and settings
+async fn start_encrypted_server(app: &TestApp) -> (String, Settings) {
+    let mut settings = None;
+    let server = app
+        .build_server(|configuration| {
+            configuration.encrypt_storage = true;
+            settings = Some(configuration.clone());
+        })
+        .await;
+    let address = format!("http://localhost:{}", server.port);
+    tokio::spawn(server.run_until_stopped());
+    (address, settings.unwrap())
+}
+
+async fn upload(app: &TestApp, address: &str) -> uuid::Uuid {
+    let file = reqwest::multipart::Part::bytes(PDF).file_name("file.pdf");
+    let response = app
+        .client
+        .post(format!("{address}/api/documents"))
+        .multipart(reqwest::multipart::Form::new().part("file", file))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    app.fetch_documents().await.remove(0).id
+}
+
+#[actix_rt::test]
+async fn stored_documents_are_encrypted_and_served_decrypted() {
+    let app = spawn_app().await;
+    let (address, settings) = start_encrypted_server(&app).await;
+    let document = upload(&app, &address).await;
+
+    let stored = std::fs::read(
+        settings
+            .documents_storage_path()
+            .join(format!("{document}.pdf")),
+    )
+    .unwrap();
+    assert!(!stored.starts_with(b"%PDF"));
+    let meta = std::fs::read(settings.documents_contents_path().join("meta.json")).unwrap();
+    assert!(!meta.starts_with(b"{"));
+
+    let url = format!("{address}/api/documents/{document}");
+    let response = app.client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+    assert!(etag.contains(&hex::encode(Sha256::digest(PDF))));
+    assert_eq!(response.bytes().await.unwrap(), PDF);
+
+    let response = app
+        .client
+        .get(&url)
+        .header("Range", "bytes=100-199")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()["Content-Range"],
+        format!("bytes 100-199/{}", PDF.len())
+    );
+    assert_eq!(response.bytes().await.unwrap(), &PDF[100..200]);
+
+    let results = app
+        .client
+        .get(format!("{url}/search?q=test"))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<SearchResult>>()
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[actix_rt::test]
+async fn rotating_the_key_moves_files_and_passwords_to_the_new_key() {
+    let app = spawn_app().await;
+    let (address, mut settings) = start_encrypted_server(&app).await;
+    let document = upload(&app, &address).await;
+    let old_key = SecretKey::from_hex(TEST_SECRET_KEY).unwrap();
+    database::query("UPDATE Documents SET encrypted_password = $1 WHERE id = $2")
+        .bind(old_key.encrypt_string("secret").unwrap())
+        .bind(document)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Stored before encryption was enabled
+    let plain = settings.documents_storage_path().join("plain.pdf");
+    std::fs::write(&plain, PDF).unwrap();
+
+    let new_key = SecretKey::from_hex(&"42".repeat(32)).unwrap();
+    let rotation = rotate_key(&app.db_pool, &settings, &new_key)
+        .await
+        .expect("Failed to rotate the key");
+    assert_eq!(rotation.passwords, 1);
+    assert!(rotation.rewrapped >= 2);
+    assert!(rotation.encrypted >= 1);
+    assert!(!std::fs::read(&plain).unwrap().starts_with(b"%PDF"));
+
+    // Running it again finds nothing left to do
+    let again = rotate_key(&app.db_pool, &settings, &new_key).await.unwrap();
+    assert_eq!(
+        (again.rewrapped, again.encrypted, again.passwords),
+        (0, 0, 0)
+    );
+
+    let (encrypted,): (String,) =
+        database::query_as("SELECT encrypted_password FROM Documents WHERE id = $1")
+            .bind(document)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(new_key.decrypt_string(&encrypted).unwrap(), "secret");
+    assert!(old_key.decrypt_string(&encrypted).is_err());
+
+    let expected = hex::encode(Sha256::digest(PDF));
+    settings.secret_key = Some("42".repeat(32));
+    let storage = FileSystemStorage::new(
+        settings.documents_storage_path(),
+        FileEncryption::load(&settings).unwrap(),
+    );
+    assert_eq!(content_hash(&storage, &document).await.unwrap(), expected);
+    let storage = FileSystemStorage::new(
+        settings.documents_storage_path(),
+        FileEncryption::new(Some(old_key), true).unwrap(),
+    );
+    assert!(content_hash(&storage, &document).await.is_err());
+}
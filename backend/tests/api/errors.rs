@@ -0,0 +1,93 @@
+use pdf_reader::models::UpdateDocumentRequest;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+async fn error_body(response: reqwest::Response) -> Value {
+    let content_type = response.headers().get("Content-Type").unwrap();
+    assert_eq!(content_type, "application/json");
+    response.json().await.expect("Error body is not JSON")
+}
+
+#[actix_rt::test]
+async fn handler_errors_have_a_code_message_and_request_id() {
+    let app = spawn_app().await;
+
+    let response = app
+        .client
+        .get(format!("{}/api/documents/{}", &app.address, Uuid::new_v4()))
+        .header("X-Request-Id", "missing-document")
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body = error_body(response).await;
+    assert_eq!(body["code"], "not_found");
+    assert_eq!(body["message"], "Document not found");
+    assert_eq!(body["details"], Value::Null);
+    assert_eq!(body["request_id"], "missing-document");
+}
+
+#[actix_rt::test]
+async fn validation_errors_name_the_invalid_field() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("file.pdf").await;
+
+    let request = UpdateDocumentRequest {
+        current_page: Some(0),
+        ..Default::default()
+    };
+    let response = app.patch_document(document_id, &request).await;
+
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body = error_body(response).await;
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(body["details"]["field"], "current_page");
+}
+
+#[actix_rt::test]
+async fn authentication_errors_use_the_same_format() {
+    let app = spawn_app().await;
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/documents", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let body = error_body(response).await;
+    assert_eq!(body["code"], "unauthorized");
+    assert!(body["request_id"].is_string());
+}
+
+#[actix_rt::test]
+async fn framework_errors_are_converted() {
+    let app = spawn_app().await;
+
+    let response = app
+        .client
+        .get(format!("{}/api/no_such_route", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body = error_body(response).await;
+    assert_eq!(body["code"], "not_found");
+    assert!(body["message"].is_string());
+
+    let response = app
+        .client
+        .patch(format!("{}/api/documents/{}", &app.address, Uuid::new_v4()))
+        .header("Content-Type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body = error_body(response).await;
+    assert_eq!(body["code"], "bad_request");
+    assert!(body["message"].is_string());
+}
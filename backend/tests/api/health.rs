@@ -0,0 +1,62 @@
+use serde_json::Value;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+async fn get_health(app: &TestApp, probe: &str) -> (reqwest::StatusCode, Value) {
+    let response = TestApp::new_client()
+        .get(format!("{}/api/health/{probe}", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    let status = response.status();
+    (status, response.json().await.expect("Body is not JSON"))
+}
+
+#[actix_rt::test]
+async fn liveness_does_not_require_authentication() {
+    let app = spawn_app().await;
+
+    let (status, body) = get_health(&app, "live").await;
+
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body["status"], "up");
+}
+
+#[actix_rt::test]
+async fn readiness_reports_every_component() {
+    let app = spawn_app().await;
+
+    let (status, body) = get_health(&app, "ready").await;
+
+    assert_eq!(status, reqwest::StatusCode::OK, "{body}");
+    assert_eq!(body["status"], "up");
+    for component in [
+        "database",
+        "migrations",
+        "documents_storage",
+        "documents_contents",
+        "index",
+        "pdfium",
+    ] {
+        assert_eq!(body["components"][component]["status"], "up", "{component}");
+        assert!(body["components"][component]["latency_ms"].is_number());
+    }
+}
+
+#[actix_rt::test]
+async fn readiness_fails_when_storage_is_not_writable() {
+    let app = spawn_app().await;
+    std::fs::remove_dir_all(app.config.documents_storage_path()).unwrap();
+
+    let (status, body) = get_health(&app, "ready").await;
+
+    assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "down");
+    let storage = &body["components"]["documents_storage"];
+    assert_eq!(storage["status"], "down");
+    assert!(storage["message"]
+        .as_str()
+        .unwrap()
+        .contains("not writable"));
+    assert_eq!(body["components"]["database"]["status"], "up");
+}
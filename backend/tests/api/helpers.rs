@@ -1,48 +1,122 @@
 use once_cell::sync::Lazy;
 use pdf_reader::telemetry::{get_subscriber, init_subscriber};
-use std::str::FromStr;
 
 use pdf_reader::configuration::{get_configuration, Settings};
 use pdf_reader::database;
-use pdf_reader::models::AddBookmarkRequest;
+use pdf_reader::database::{Backend, DbPool, SELECT_DOCUMENTS};
+use pdf_reader::models::{
+    AddBookmarkRequest, CreateUserRequest, Credentials, Document, UpdateDocumentRequest,
+};
 use pdf_reader::startup::Application;
 use sqlx::postgres::PgConnectOptions;
-use sqlx::{Connection, Executor, PgConnection, PgPool};
+use sqlx::{Connection, Executor, PgConnection};
+use std::str::FromStr;
 use tempfile::TempDir;
 use uuid::Uuid;
 
 pub struct TestApp {
     pub address: String,
-    pub db_pool: PgPool,
+    pub db_pool: DbPool,
     pub config: Settings,
-    pub test_id: Uuid,
     pub client: reqwest::Client,
+    pub user_id: Uuid,
 }
 
 impl Drop for TestApp {
     fn drop(&mut self) {
         std::fs::remove_dir_all(self.config.storage_location.clone())
             .expect("Failed to delete temporary storage directory");
-
-        let database_name = self.config.get_database_name();
-        let command = format!(r#"DROP DATABASE "{}" WITH (FORCE);"#, database_name);
-        let result = std::process::Command::new("psql")
-            .env("PGUSER", "postgres")
-            .env("PGPASSWORD", "password")
-            .env("PGHOST", "localhost")
-            .arg("-c")
-            .arg(command)
-            .output()
-            .unwrap();
-
-        if !result.status.success() {
-            println!("{:?}", std::str::from_utf8(&result.stderr));
-            panic!("Failed to delete database after test");
-        }
+        drop_database(&self.config);
     }
 }
 
 impl TestApp {
+    /// Creates a client with its own cookie store, which is not logged in
+    pub fn new_client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .cookie_store(true)
+            .build()
+            .expect("Failed to build client")
+    }
+
+    pub async fn login(
+        &self,
+        client: &reqwest::Client,
+        username: &str,
+        password: &str,
+    ) -> reqwest::Response {
+        let credentials = Credentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        };
+        client
+            .post(format!("{}/api/auth/login", &self.address))
+            .json(&credentials)
+            .send()
+            .await
+            .expect("Failed to send login request")
+    }
+
+    /// Creates a new user, and returns a client which is logged in as that user
+    pub async fn create_user(&self, username: &str, is_admin: bool) -> (Uuid, reqwest::Client) {
+        let request = CreateUserRequest {
+            username: username.to_owned(),
+            password: TEST_PASSWORD.to_owned(),
+            is_admin,
+        };
+        let response = self
+            .client
+            .post(format!("{}/api/users", &self.address))
+            .json(&request)
+            .send()
+            .await
+            .expect("Failed to send create user request");
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let user = response
+            .json::<pdf_reader::models::User>()
+            .await
+            .expect("Failed to deserialize user");
+
+        let client = TestApp::new_client();
+        let response = self.login(&client, username, TEST_PASSWORD).await;
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+        (user.id, client)
+    }
+
+    /// Fetches the documents as seen by the default test user
+    pub async fn fetch_documents(&self) -> Vec<Document> {
+        database::query_as(SELECT_DOCUMENTS)
+            .bind(self.user_id)
+            .fetch_all(&self.db_pool)
+            .await
+            .expect("Failed to fetch documents")
+    }
+
+    /// Builds a second server next to the one of the test app, sharing its
+    /// database. Its storage is kept within the one of the test app.
+    pub async fn build_server(&self, configure: impl FnOnce(&mut Settings)) -> Application {
+        let mut configuration = self.config.clone();
+        configuration.storage_location = self.config.storage_location.join("server");
+        configure(&mut configuration);
+        Application::build(configuration, Some(self.db_pool.clone()))
+            .await
+            .expect("Failed to build application")
+    }
+
+    pub async fn insert_document(&self, name: &str) -> Uuid {
+        let document_id = Uuid::new_v4();
+        database::query("INSERT INTO Documents (id, name, owner) VALUES ($1, $2, $3)")
+            .bind(document_id)
+            .bind(name)
+            .bind(self.user_id)
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to insert preseeded document");
+
+        document_id
+    }
+
     pub async fn post_bookmark(
         &self,
         document_id: Uuid,
@@ -52,6 +126,7 @@ impl TestApp {
         let body = AddBookmarkRequest {
             description: description.to_owned(),
             page,
+            highlight_area: None,
         };
 
         let url = format!(
@@ -68,6 +143,20 @@ impl TestApp {
             .expect("Failed to send bookmark request")
     }
 
+    pub async fn patch_document(
+        &self,
+        document_id: Uuid,
+        request: &UpdateDocumentRequest,
+    ) -> reqwest::Response {
+        let url = format!("{}/api/documents/{}", &self.address, document_id);
+        self.client
+            .patch(url)
+            .json(request)
+            .send()
+            .await
+            .expect("Failed to send update request")
+    }
+
     pub async fn post_document(&self, form_contents: &[u8]) -> reqwest::Response {
         let body = reqwest::multipart::Part::bytes(form_contents.to_owned()).file_name("file.pdf");
         let form = reqwest::multipart::Form::new().part("field1", body);
@@ -81,21 +170,34 @@ impl TestApp {
     }
 }
 
-pub async fn configure_database(config: &mut Settings, test_id: &Uuid) -> PgPool {
+/// Creates an empty database for the test, named after it, and migrates it.
+/// SQLite databases are kept within the storage location of the test.
+pub async fn configure_database(config: &mut Settings, test_id: &Uuid) -> DbPool {
     config.database_name = Some(test_id.as_hyphenated().to_string());
 
-    let options = PgConnectOptions::from_str(&config.get_database_location())
-        .expect("Failed to parse connection string");
+    match Backend::of_url(&config.get_database_location()) {
+        Some(Backend::Postgres) => {
+            let options = PgConnectOptions::from_str(&config.get_database_location())
+                .expect("Failed to parse connection string");
 
-    PgConnection::connect_with(&options)
-        .await
-        .expect("Failed to connect to Postgres")
-        .execute(&*format!(
-            r#"CREATE DATABASE "{}""#,
-            test_id.as_hyphenated()
-        ))
-        .await
-        .expect("Failed to create database");
+            PgConnection::connect_with(&options)
+                .await
+                .expect("Failed to connect to Postgres")
+                .execute(&*format!(
+                    r#"CREATE DATABASE "{}""#,
+                    test_id.as_hyphenated()
+                ))
+                .await
+                .expect("Failed to create database");
+        }
+        Some(Backend::Sqlite) => {
+            std::fs::create_dir_all(&config.storage_location)
+                .expect("Failed to create temporary storage directory");
+            config.database_location =
+                Some(format!("sqlite://{}", config.storage_location.display()));
+        }
+        None => panic!("Unsupported database location"),
+    }
 
     let connection_pool = database::get_connection_pool(config);
     database::initialize_database(&connection_pool).await;
@@ -103,12 +205,47 @@ pub async fn configure_database(config: &mut Settings, test_id: &Uuid) -> PgPool
     connection_pool
 }
 
+/// Drops the database of a test. The test runtime may be shutting down when this
+/// is called, so it runs on a runtime of its own. The file of an SQLite database
+/// is within the storage location, so it is already gone.
+fn drop_database(config: &Settings) {
+    if Backend::of_url(&config.get_database_location()) != Some(Backend::Postgres) {
+        return;
+    }
+
+    let options = PgConnectOptions::from_str(&config.get_database_location())
+        .expect("Failed to parse connection string");
+    let command = format!(
+        r#"DROP DATABASE "{}" WITH (FORCE)"#,
+        config.get_database_name()
+    );
+    std::thread::spawn(move || {
+        actix_rt::System::new().block_on(async move {
+            PgConnection::connect_with(&options)
+                .await
+                .expect("Failed to connect to Postgres")
+                .execute(&*command)
+                .await
+                .expect("Failed to delete database after test");
+        })
+    })
+    .join()
+    .expect("Failed to delete database after test");
+}
+
+pub const TEST_USERNAME: &str = "reader";
+pub const TEST_PASSWORD: &str = "correct horse battery staple";
+pub const TEST_SECRET_KEY: &str =
+    "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
 pub async fn spawn_app() -> TestApp {
     Lazy::force(&TRACING);
     let test_id = Uuid::new_v4();
     let mut configuration = get_configuration();
     configuration.port = 0;
+    configuration.session_cookie_secure = Some(false);
     configuration.storage_location = TempDir::new().unwrap().path().to_path_buf();
+    configuration.secret_key = Some(TEST_SECRET_KEY.to_owned());
 
     let db = configure_database(&mut configuration, &test_id).await;
 
@@ -118,14 +255,33 @@ pub async fn spawn_app() -> TestApp {
 
     let address = format!("http://localhost:{}", app.port);
 
-    let _ = tokio::spawn(app.run_until_stopped());
+    tokio::spawn(app.run_until_stopped());
+
+    let client = TestApp::new_client();
+    let credentials = Credentials {
+        username: TEST_USERNAME.to_owned(),
+        password: TEST_PASSWORD.to_owned(),
+    };
+    let response = client
+        .post(format!("{}/api/auth/setup", &address))
+        .json(&credentials)
+        .send()
+        .await
+        .expect("Failed to set up initial user");
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let (user_id,): (Uuid,) = database::query_as("SELECT id FROM Users WHERE username = $1")
+        .bind(TEST_USERNAME)
+        .fetch_one(&db)
+        .await
+        .expect("Failed to fetch initial user");
 
     TestApp {
         address,
         db_pool: db,
         config: configuration,
-        test_id,
-        client: reqwest::Client::new(),
+        client,
+        user_id,
     }
 }
 
@@ -134,10 +290,10 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     let subscriber_name = "test".to_string();
 
     if let Ok(log_level) = std::env::var("TEST_LOG") {
-        let subscriber = get_subscriber(subscriber_name, log_level, std::io::stdout);
+        let subscriber = get_subscriber(subscriber_name, log_level, std::io::stdout, None);
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink, None);
         init_subscriber(subscriber);
     }
 });
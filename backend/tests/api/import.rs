@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use pdf_reader::import::{ImportOutcome, ImportReport};
+use pdf_reader::indexer::SearchResult;
+use pdf_reader::models::ImportRequest;
+use tempfile::TempDir;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+const PDF: &[u8] = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+
+async fn import(app: &TestApp, client: &reqwest::Client, path: &Path) -> reqwest::Response {
+    let request = ImportRequest {
+        path: path.to_string_lossy().into_owned(),
+        jobs: Some(2),
+    };
+    client
+        .post(format!("{}/api/admin/import", &app.address))
+        .json(&request)
+        .send()
+        .await
+        .expect("Failed to send import request")
+}
+
+/// Lays out a directory with two different PDFs in nested folders, a copy of one
+/// of them, a broken PDF and a file which is not a PDF
+fn create_import_directory() -> TempDir {
+    let directory = TempDir::new().unwrap();
+    let root = directory.path();
+    std::fs::create_dir_all(root.join("papers/2023")).unwrap();
+    std::fs::create_dir_all(root.join("copies")).unwrap();
+    std::fs::write(root.join("sample.pdf"), PDF).unwrap();
+    // Anything after the end of a PDF is ignored by readers, but changes the hash
+    let mut other = PDF.to_vec();
+    other.extend_from_slice(b"\n% another document\n");
+    std::fs::write(root.join("papers/2023/other.PDF"), other).unwrap();
+    std::fs::write(root.join("copies/sample.pdf"), PDF).unwrap();
+    std::fs::write(root.join("papers/broken.pdf"), b"not a pdf").unwrap();
+    std::fs::write(root.join("papers/notes.txt"), b"not imported").unwrap();
+    directory
+}
+
+#[actix_rt::test]
+async fn import_adds_every_pdf_with_its_folder_as_collection() {
+    let app = spawn_app().await;
+    let directory = create_import_directory();
+
+    let response = import(&app, &app.client, directory.path()).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let report = response.json::<ImportReport>().await.unwrap();
+
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.failed, 1);
+    let outcomes = report
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.outcome))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("copies/sample.pdf", ImportOutcome::Imported),
+            ("papers/2023/other.PDF", ImportOutcome::Imported),
+            ("papers/broken.pdf", ImportOutcome::Failed),
+            ("sample.pdf", ImportOutcome::Skipped),
+        ]
+    );
+
+    let mut documents = app.fetch_documents().await;
+    documents.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[0].name, "other.PDF");
+    assert_eq!(documents[0].collection.as_deref(), Some("papers/2023"));
+    assert!(documents[0].page_count.is_some());
+    assert_eq!(documents[1].name, "sample.pdf");
+    assert_eq!(documents[1].collection.as_deref(), Some("copies"));
+    assert_eq!(documents[1].owner, Some(app.user_id));
+
+    let stored = std::fs::read_dir(app.config.documents_storage_path())
+        .unwrap()
+        .count();
+    assert_eq!(stored, 2);
+
+    let results = app
+        .client
+        .get(format!(
+            "{}/api/documents/{}/search?q=test",
+            &app.address, documents[0].id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<SearchResult>>()
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[actix_rt::test]
+async fn import_skips_documents_which_are_already_in_the_library() {
+    let app = spawn_app().await;
+    let directory = create_import_directory();
+    assert_eq!(
+        app.post_document(PDF).await.status(),
+        reqwest::StatusCode::CREATED
+    );
+    let uploaded = app.fetch_documents().await.remove(0);
+
+    let report = import(&app, &app.client, directory.path())
+        .await
+        .json::<ImportReport>()
+        .await
+        .unwrap();
+    assert_eq!(report.imported, 1);
+    assert_eq!(report.skipped, 2);
+    let copy = report
+        .files
+        .iter()
+        .find(|f| f.path == "copies/sample.pdf")
+        .unwrap();
+    assert_eq!(copy.outcome, ImportOutcome::Skipped);
+    assert_eq!(copy.document, Some(uploaded.id));
+
+    let report = import(&app, &app.client, directory.path())
+        .await
+        .json::<ImportReport>()
+        .await
+        .unwrap();
+    assert_eq!(report.imported, 0);
+    assert_eq!(report.skipped, 3);
+    assert_eq!(app.fetch_documents().await.len(), 2);
+}
+
+#[actix_rt::test]
+async fn import_rejects_a_path_which_is_not_a_directory() {
+    let app = spawn_app().await;
+    let directory = create_import_directory();
+
+    let response = import(&app, &app.client, &directory.path().join("sample.pdf")).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = import(&app, &app.client, &directory.path().join("missing")).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn import_requires_an_administrator() {
+    let app = spawn_app().await;
+    let directory = create_import_directory();
+    let (_, client) = app.create_user("visitor", false).await;
+
+    let response = import(&app, &client, directory.path()).await;
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    assert!(app.fetch_documents().await.is_empty());
+}
@@ -0,0 +1,207 @@
+use std::path::Path;
+use std::time::Duration;
+
+use pdf_reader::configuration::Settings;
+use pdf_reader::import::ImportOutcome;
+use pdf_reader::models::{Bookmark, IngestionEvent};
+use tempfile::TempDir;
+
+use crate::api::helpers::{spawn_app, TestApp, TEST_USERNAME};
+
+const PDF: &[u8] = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+
+async fn fetch_history(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/api/admin/inbox", &app.address))
+        .send()
+        .await
+        .expect("Failed to send inbox request")
+}
+
+/// Waits for the inbox to have handled `count` files
+async fn wait_for_history(app: &TestApp, count: usize) -> Vec<IngestionEvent> {
+    for _ in 0..60 {
+        let history = fetch_history(app, &app.client)
+            .await
+            .json::<Vec<IngestionEvent>>()
+            .await
+            .unwrap();
+        if history.len() >= count {
+            return history;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    panic!("The inbox did not handle {count} files in time");
+}
+
+/// Starts a server watching `inbox` on the database of `app`
+async fn start_inbox(app: &TestApp, inbox: &Path) {
+    start_inbox_with(app, inbox, |_| {}).await;
+}
+
+async fn start_inbox_with(app: &TestApp, inbox: &Path, configure: impl FnOnce(&mut Settings)) {
+    let server = app
+        .build_server(|configuration| {
+            configuration.inbox_location = Some(inbox.to_path_buf());
+            configuration.inbox_owner = Some(TEST_USERNAME.to_owned());
+            configuration.inbox_settle_seconds = 1;
+            configure(configuration);
+        })
+        .await;
+    tokio::spawn(server.run_until_stopped());
+}
+
+#[actix_rt::test]
+async fn pdfs_dropped_into_the_inbox_are_added_and_moved_away() {
+    let app = spawn_app().await;
+    let inbox = TempDir::new().unwrap();
+    // Files which arrived while the server was down are picked up as well
+    std::fs::write(inbox.path().join("broken.pdf"), b"not a pdf").unwrap();
+    start_inbox(&app, inbox.path()).await;
+
+    std::fs::write(inbox.path().join("sample.pdf"), PDF).unwrap();
+    std::fs::write(inbox.path().join("notes.txt"), b"left alone").unwrap();
+    let history = wait_for_history(&app, 2).await;
+
+    let mut outcomes = history
+        .iter()
+        .map(|e| (e.file_name.as_str(), e.outcome))
+        .collect::<Vec<_>>();
+    outcomes.sort_by_key(|(name, _)| *name);
+    assert_eq!(
+        outcomes,
+        vec![
+            ("broken.pdf", ImportOutcome::Failed),
+            ("sample.pdf", ImportOutcome::Imported),
+        ]
+    );
+    let failed = history
+        .iter()
+        .find(|e| e.file_name == "broken.pdf")
+        .unwrap();
+    assert!(failed.reason.is_some());
+    assert!(failed.document.is_none());
+
+    let documents = app.fetch_documents().await;
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].name, "sample.pdf");
+    assert_eq!(documents[0].owner, Some(app.user_id));
+    let imported = history
+        .iter()
+        .find(|e| e.file_name == "sample.pdf")
+        .unwrap();
+    assert_eq!(imported.document, Some(documents[0].id));
+
+    assert!(inbox.path().join("processed/sample.pdf").is_file());
+    assert!(inbox.path().join("failed/broken.pdf").is_file());
+    assert!(!inbox.path().join("sample.pdf").exists());
+    assert!(!inbox.path().join("broken.pdf").exists());
+    assert!(inbox.path().join("notes.txt").is_file());
+}
+
+#[actix_rt::test]
+async fn a_document_already_in_the_library_is_skipped() {
+    let app = spawn_app().await;
+    assert_eq!(
+        app.post_document(PDF).await.status(),
+        reqwest::StatusCode::CREATED
+    );
+    let uploaded = app.fetch_documents().await.remove(0);
+    let inbox = TempDir::new().unwrap();
+    std::fs::create_dir_all(inbox.path().join("processed")).unwrap();
+    std::fs::write(inbox.path().join("processed/copy.pdf"), b"an earlier file").unwrap();
+    start_inbox(&app, inbox.path()).await;
+
+    std::fs::write(inbox.path().join("copy.pdf"), PDF).unwrap();
+    let history = wait_for_history(&app, 1).await;
+
+    assert_eq!(history[0].outcome, ImportOutcome::Skipped);
+    assert_eq!(history[0].document, Some(uploaded.id));
+    assert_eq!(app.fetch_documents().await.len(), 1);
+    // The earlier file with the same name is kept
+    let processed = std::fs::read_dir(inbox.path().join("processed"))
+        .unwrap()
+        .count();
+    assert_eq!(processed, 2);
+    assert_eq!(
+        std::fs::read(inbox.path().join("processed/copy.pdf")).unwrap(),
+        b"an earlier file"
+    );
+}
+
+#[actix_rt::test]
+async fn files_over_the_quota_of_the_owner_are_refused() {
+    let app = spawn_app().await;
+    app.insert_document("already.pdf").await;
+    let inbox = TempDir::new().unwrap();
+    start_inbox_with(&app, inbox.path(), |configuration| {
+        configuration.quota_documents = Some(1);
+    })
+    .await;
+
+    std::fs::write(inbox.path().join("sample.pdf"), PDF).unwrap();
+    let history = wait_for_history(&app, 1).await;
+
+    assert_eq!(history[0].outcome, ImportOutcome::Failed);
+    assert_eq!(
+        history[0].reason.as_deref(),
+        Some("Storing more than 1 documents is not allowed")
+    );
+    assert_eq!(app.fetch_documents().await.len(), 1);
+    assert!(inbox.path().join("failed/sample.pdf").is_file());
+}
+
+#[actix_rt::test]
+async fn notes_in_inbox_files_are_added_as_bookmarks() {
+    let app = spawn_app().await;
+    let inbox = TempDir::new().unwrap();
+    start_inbox_with(&app, inbox.path(), |configuration| {
+        configuration.inbox_import_outline = false;
+    })
+    .await;
+
+    std::fs::write(
+        inbox.path().join("annotated.pdf"),
+        include_bytes!("../../tests/test_files/annotated.pdf"),
+    )
+    .unwrap();
+    let history = wait_for_history(&app, 1).await;
+    assert_eq!(history[0].outcome, ImportOutcome::Imported);
+
+    let mut bookmarks = app
+        .client
+        .get(format!(
+            "{}/api/documents/{}/bookmarks",
+            &app.address,
+            history[0].document.unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<Bookmark>>()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|b| (b.page, b.description, b.imported))
+        .collect::<Vec<_>>();
+    bookmarks.sort();
+    assert_eq!(
+        bookmarks,
+        vec![
+            (1, "Read this first".to_owned(), true),
+            (2, "Drawing".to_owned(), true),
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn inbox_history_requires_an_administrator() {
+    let app = spawn_app().await;
+    let (_, client) = app.create_user("visitor", false).await;
+
+    let response = fetch_history(&app, &client).await;
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let response = fetch_history(&app, &app.client).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
@@ -0,0 +1,130 @@
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use pdf_reader::tls::ReloadableCertificate;
+
+use crate::api::helpers::spawn_app;
+
+fn test_certificate(name: &str) -> (PathBuf, PathBuf) {
+    let directory = PathBuf::from("tests/test_files/tls");
+    (
+        directory.join(format!("{name}.crt")),
+        directory.join(format!("{name}.key")),
+    )
+}
+
+#[actix_rt::test]
+async fn https_is_served_when_a_certificate_is_configured() {
+    let app = spawn_app().await;
+    let server = app
+        .build_server(|configuration| {
+            let (certificate, private_key) = test_certificate("localhost");
+            configuration.tls_certificate = Some(certificate);
+            configuration.tls_private_key = Some(private_key);
+        })
+        .await;
+    let port = server.port;
+    tokio::spawn(server.run_until_stopped());
+
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("https://localhost:{port}/api/health_check"))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn plain_http_is_redirected_to_https() {
+    let app = spawn_app().await;
+    let server = app
+        .build_server(|configuration| {
+            let (certificate, private_key) = test_certificate("localhost");
+            configuration.tls_certificate = Some(certificate);
+            configuration.tls_private_key = Some(private_key);
+            configuration.http_redirect_port = Some(0);
+        })
+        .await;
+    let https_port = server.port;
+    let redirect_port = server.redirect_port.expect("No redirect listener");
+    tokio::spawn(server.run_until_stopped());
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!(
+            "http://localhost:{redirect_port}/api/documents?sort=name"
+        ))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        &format!("https://localhost:{https_port}/api/documents?sort=name")
+    );
+}
+
+#[actix_rt::test]
+async fn server_can_listen_on_a_unix_socket() {
+    let app = spawn_app().await;
+    let socket = app.config.storage_location.join("pdfreader.sock");
+    let server = app
+        .build_server(|configuration| {
+            configuration.bind_address = format!("unix:{}", socket.display());
+        })
+        .await;
+    assert_eq!(server.port, 0);
+    tokio::spawn(server.run_until_stopped());
+
+    let response = tokio::task::spawn_blocking(move || {
+        let mut stream = UnixStream::connect(socket).expect("Failed to connect to socket");
+        stream
+            .write_all(
+                b"GET /api/health_check HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    })
+    .await
+    .unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+}
+
+#[test]
+fn certificate_is_replaced_on_reload() {
+    let directory = tempfile::TempDir::new().unwrap();
+    let certificate = directory.path().join("server.crt");
+    let private_key = directory.path().join("server.key");
+    let install = |name: &str| {
+        let (name_certificate, name_private_key) = test_certificate(name);
+        std::fs::copy(name_certificate, &certificate).unwrap();
+        std::fs::copy(name_private_key, &private_key).unwrap();
+    };
+
+    install("localhost");
+    let reloadable = ReloadableCertificate::load(&certificate, &private_key).unwrap();
+    let original = reloadable.current().cert.clone();
+
+    install("renewed");
+    reloadable.reload().expect("Failed to reload certificate");
+    let renewed = reloadable.current().cert.clone();
+    assert_ne!(original, renewed);
+
+    // A broken renewal keeps the certificate which is in use
+    std::fs::write(&certificate, "not a certificate").unwrap();
+    assert!(reloadable.reload().is_err());
+    assert_eq!(reloadable.current().cert, renewed);
+}
@@ -0,0 +1,35 @@
+use crate::api::helpers::{spawn_app, TestApp};
+
+#[actix_rt::test]
+async fn metrics_are_exposed_in_prometheus_format() {
+    let app = spawn_app().await;
+    app.insert_document("adocument").await;
+    app.client
+        .get(format!("{}/api/documents", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let response = TestApp::new_client()
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains(
+        r#"pdfreader_http_requests_total{method="GET",route="/api/documents",status="200"}"#
+    ));
+    assert!(body.contains("pdfreader_http_request_duration_seconds_bucket"));
+    assert!(body.contains("pdfreader_documents "));
+    assert!(body.contains("pdfreader_index_size_bytes"));
+    assert!(body.contains("pdfreader_db_pool_connections"));
+}
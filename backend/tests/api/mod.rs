@@ -1,4 +1,20 @@
+mod backup;
 mod bookmarks;
 mod documents;
+mod encryption;
+mod errors;
+mod health;
 mod helpers;
+mod import;
+mod inbox;
+mod listeners;
+mod metrics;
+mod openapi;
+mod request_id;
 mod search;
+mod shares;
+mod shutdown;
+mod stats;
+mod tokens;
+mod usage;
+mod users;
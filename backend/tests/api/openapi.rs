@@ -0,0 +1,51 @@
+use serde_json::Value;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+#[actix_rt::test]
+async fn openapi_specification_is_served_without_a_session() {
+    let app = spawn_app().await;
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/openapi.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let spec = response.json::<Value>().await.unwrap();
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+
+    let paths = spec["paths"].as_object().unwrap();
+    for path in [
+        "/api/documents",
+        "/api/documents/{id}",
+        "/api/documents/{document_id}/bookmarks",
+        "/api/documents/{document_id}/search",
+        "/api/health/ready",
+    ] {
+        assert!(paths.contains_key(path), "{path} is not documented");
+    }
+    assert!(paths["/api/documents/{id}"]["patch"].is_object());
+
+    let schemas = &spec["components"]["schemas"];
+    for schema in ["Document", "Bookmark", "SearchResult", "ErrorResponse"] {
+        assert!(schemas[schema].is_object(), "{schema} is not documented");
+    }
+    assert!(spec["components"]["securitySchemes"]["api_token"].is_object());
+}
+
+#[actix_rt::test]
+async fn api_docs_are_served() {
+    let app = spawn_app().await;
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/docs/", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("swagger-ui"));
+}
@@ -0,0 +1,88 @@
+use uuid::Uuid;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+#[actix_rt::test]
+async fn request_id_is_generated_when_missing() {
+    let app = spawn_app().await;
+
+    let response = app
+        .client
+        .get(format!("{}/api/documents", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let request_id = response
+        .headers()
+        .get("X-Request-Id")
+        .expect("Request id was not echoed")
+        .to_str()
+        .unwrap();
+    assert!(Uuid::parse_str(request_id).is_ok());
+}
+
+#[actix_rt::test]
+async fn request_id_from_client_is_echoed() {
+    let app = spawn_app().await;
+
+    let response = app
+        .client
+        .get(format!("{}/api/documents", &app.address))
+        .header("X-Request-Id", "client-chosen-id.1")
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(
+        response.headers().get("X-Request-Id").unwrap(),
+        "client-chosen-id.1"
+    );
+}
+
+#[actix_rt::test]
+async fn unsafe_request_id_is_replaced() {
+    let app = spawn_app().await;
+
+    let response = app
+        .client
+        .get(format!("{}/api/documents", &app.address))
+        .header("X-Request-Id", "no spaces or \"quotes\" please")
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[actix_rt::test]
+async fn error_responses_include_request_id() {
+    let app = spawn_app().await;
+
+    let requests = [
+        app.client
+            .get(format!("{}/api/documents/{}", &app.address, Uuid::new_v4())),
+        TestApp::new_client().get(format!("{}/api/documents", &app.address)),
+        app.client
+            .get(format!("{}/api/no_such_route", &app.address)),
+    ];
+    for request in requests {
+        let response = request
+            .header("X-Request-Id", "failing-request")
+            .send()
+            .await
+            .expect("Failed to send request");
+
+        assert!(response.status().is_client_error());
+        assert_eq!(
+            response.headers().get("X-Request-Id").unwrap(),
+            "failing-request"
+        );
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["request_id"], "failing-request");
+        assert!(body["code"].is_string());
+        assert!(body["message"].is_string());
+    }
+}
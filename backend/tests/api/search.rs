@@ -1,4 +1,4 @@
-use pdf_reader::{indexer::SearchResult, models::Document};
+use pdf_reader::indexer::SearchResult;
 
 use crate::api::helpers::spawn_app;
 
@@ -9,15 +9,12 @@ async fn upload_document() {
     let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
     let response = app.post_document(pdf).await;
 
-    let document = sqlx::query_as!(Document, "SELECT * FROM Documents")
-        .fetch_one(&app.db_pool)
-        .await
-        .unwrap();
+    let document = app.fetch_documents().await.remove(0);
 
     assert_eq!(response.status(), reqwest::StatusCode::CREATED);
 
-    let client = reqwest::Client::new();
-    let search_response = client
+    let search_response = app
+        .client
         .get(format!(
             "{}/api/documents/{}/search?q=test",
             app.address, document.id
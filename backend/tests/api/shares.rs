@@ -0,0 +1,239 @@
+use std::io::Write;
+
+use chrono::{Duration, Utc};
+use pdf_reader::database;
+use pdf_reader::models::{CreateShareRequest, CreatedShare, ShareLink, SharedDocument};
+use uuid::Uuid;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+fn write_document(app: &TestApp, document_id: Uuid, contents: &[u8]) {
+    let documents_location = &app.config.storage_location.join("documents");
+    let document_path = documents_location.join(format!("{}.pdf", document_id));
+
+    let mut file = std::fs::File::create(document_path).unwrap();
+    file.write_all(contents).unwrap();
+    file.flush().unwrap();
+}
+
+async fn create_share(app: &TestApp, request: &CreateShareRequest) -> reqwest::Response {
+    app.client
+        .post(format!("{}/api/shares", &app.address))
+        .json(request)
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[actix_rt::test]
+async fn shared_document_can_be_read_without_logging_in() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    write_document(&app, document_id, b"pdfcontents");
+
+    let request = CreateShareRequest {
+        document: document_id,
+        page: Some(3),
+        ..Default::default()
+    };
+    let response = create_share(&app, &request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let created = response.json::<CreatedShare>().await.unwrap();
+    assert!(!created.details.has_password);
+
+    let client = TestApp::new_client();
+    let response = client
+        .get(format!("{}/api/shared/{}", &app.address, created.token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        response.headers().get("Content-Disposition").unwrap(),
+        "inline; filename=\"adocument\""
+    );
+    assert_eq!(response.text().await.unwrap(), "pdfcontents");
+
+    let info = client
+        .get(format!(
+            "{}/api/shared/{}/info",
+            &app.address, created.token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<SharedDocument>()
+        .await
+        .unwrap();
+    assert_eq!(info.name, "adocument");
+    assert_eq!(info.page, Some(3));
+}
+
+#[actix_rt::test]
+async fn shared_page_is_rendered_as_an_image() {
+    let app = spawn_app().await;
+    let pdf = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+    app.post_document(pdf).await;
+    let document_id = app.fetch_documents().await[0].id;
+
+    let request = CreateShareRequest {
+        document: document_id,
+        ..Default::default()
+    };
+    let created = create_share(&app, &request)
+        .await
+        .json::<CreatedShare>()
+        .await
+        .unwrap();
+
+    let url = format!("{}/api/shared/{}/pages", &app.address, created.token);
+    let response = TestApp::new_client()
+        .get(format!("{url}/1?width=200"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), "image/png");
+    let body = response.bytes().await.unwrap();
+    assert!(body.starts_with(b"\x89PNG"));
+
+    let response = TestApp::new_client()
+        .get(format!("{url}/1000"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn password_protected_share_requires_password() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    write_document(&app, document_id, b"pdfcontents");
+
+    let request = CreateShareRequest {
+        document: document_id,
+        password: Some("hunter2".to_owned()),
+        ..Default::default()
+    };
+    let created = create_share(&app, &request)
+        .await
+        .json::<CreatedShare>()
+        .await
+        .unwrap();
+    assert!(created.details.has_password);
+
+    let url = format!("{}/api/shared/{}", &app.address, created.token);
+    let client = TestApp::new_client();
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .get(&url)
+        .header("X-Share-Password", "wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // The correct password is remembered for later requests, a wrong one never is
+    for (password, status) in [
+        ("hunter2", reqwest::StatusCode::OK),
+        ("hunter2", reqwest::StatusCode::OK),
+        ("wrong", reqwest::StatusCode::UNAUTHORIZED),
+    ] {
+        let response = client
+            .get(&url)
+            .header("X-Share-Password", password)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+    }
+}
+
+#[actix_rt::test]
+async fn revoked_and_expired_shares_are_not_served() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    write_document(&app, document_id, b"pdfcontents");
+    let request = CreateShareRequest {
+        document: document_id,
+        ..Default::default()
+    };
+
+    let revoked = create_share(&app, &request)
+        .await
+        .json::<CreatedShare>()
+        .await
+        .unwrap();
+    let response = app
+        .client
+        .delete(format!(
+            "{}/api/shares/{}",
+            &app.address, revoked.details.id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let expired = create_share(&app, &request)
+        .await
+        .json::<CreatedShare>()
+        .await
+        .unwrap();
+    database::query("UPDATE ShareLinks SET expires_on = $1 WHERE id = $2")
+        .bind(Utc::now() - Duration::minutes(1))
+        .bind(expired.details.id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    for share in [revoked, expired] {
+        let response = TestApp::new_client()
+            .get(format!("{}/api/shared/{}", &app.address, share.token))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    let shares = app
+        .client
+        .get(format!("{}/api/shares", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<ShareLink>>()
+        .await
+        .unwrap();
+    assert_eq!(shares.len(), 2);
+    assert!(shares[0].revoked_on.is_some());
+}
+
+#[actix_rt::test]
+async fn only_the_owner_can_revoke_a_share() {
+    let app = spawn_app().await;
+    let document_id = app.insert_document("adocument").await;
+    let request = CreateShareRequest {
+        document: document_id,
+        ..Default::default()
+    };
+    let created = create_share(&app, &request)
+        .await
+        .json::<CreatedShare>()
+        .await
+        .unwrap();
+
+    let (_, client) = app.create_user("someone", false).await;
+    let response = client
+        .delete(format!(
+            "{}/api/shares/{}",
+            &app.address, created.details.id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
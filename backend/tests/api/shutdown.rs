@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use crate::api::helpers::spawn_app;
+
+#[actix_rt::test]
+async fn server_stops_and_closes_the_database_when_shutdown_is_requested() {
+    let app = spawn_app().await;
+    let server = app.build_server(|_| {}).await;
+    let address = format!("http://localhost:{}", server.port);
+    let shutdown = server.shutdown.clone();
+    let stopped = tokio::spawn(server.run_until_stopped());
+
+    let response = reqwest::get(format!("{address}/api/health_check"))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    shutdown.request();
+    tokio::time::timeout(Duration::from_secs(10), stopped)
+        .await
+        .expect("Server did not stop")
+        .unwrap()
+        .expect("Server failed");
+
+    assert!(app.db_pool.is_closed());
+    assert!(reqwest::get(format!("{address}/api/health_check"))
+        .await
+        .is_err());
+}
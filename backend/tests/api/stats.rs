@@ -0,0 +1,170 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use pdf_reader::database;
+use pdf_reader::models::{
+    DailyPages, DocumentStatistics, LibraryStatistics, UpdateDocumentRequest,
+};
+use uuid::Uuid;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+async fn insert_document(app: &TestApp, current_page: i32, page_count: i32) -> Uuid {
+    let document_id = app.insert_document("adocument").await;
+    database::query("UPDATE Documents SET page_count = $1 WHERE id = $2")
+        .bind(page_count)
+        .bind(document_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set page count");
+    database::query(
+        "INSERT INTO ReadingProgress (reader, document, current_page) VALUES ($1, $2, $3)",
+    )
+    .bind(app.user_id)
+    .bind(document_id)
+    .bind(current_page)
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert reading progress");
+
+    document_id
+}
+
+async fn insert_page_event(app: &TestApp, document_id: Uuid, page: i32, at: DateTime<Utc>) {
+    database::query(
+        "INSERT INTO PageEvents (id, document, page, occurred_on, reader) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(Uuid::new_v4())
+    .bind(document_id)
+    .bind(page)
+    .bind(at)
+    .bind(app.user_id)
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert page event");
+}
+
+#[actix_rt::test]
+async fn changing_page_records_reading_history() {
+    let app = spawn_app().await;
+    let document_id = insert_document(&app, 1, 20).await;
+
+    for page in [2, 3] {
+        let request = UpdateDocumentRequest {
+            current_page: Some(page),
+            ..Default::default()
+        };
+        let response = app.patch_document(document_id, &request).await;
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    }
+
+    let request = UpdateDocumentRequest {
+        title: Some("A title".to_owned()),
+        ..Default::default()
+    };
+    app.patch_document(document_id, &request).await;
+
+    let pages: Vec<(i32,)> =
+        database::query_as("SELECT page FROM PageEvents WHERE document = $1 ORDER BY occurred_on")
+            .bind(document_id)
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(pages, vec![(2,), (3,)]);
+
+    let url = format!("{}/api/documents/{}/stats", app.address, document_id);
+    let response = app.client.get(url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let stats = response.json::<DocumentStatistics>().await.unwrap();
+    assert_eq!(stats.current_page, 3);
+    assert_eq!(stats.pages_read, 2);
+    assert!(stats.last_opened.is_some());
+}
+
+#[actix_rt::test]
+async fn document_statistics() {
+    let app = spawn_app().await;
+    let document_id = insert_document(&app, 5, 100).await;
+
+    let start = Utc.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap();
+    insert_page_event(&app, document_id, 1, start).await;
+    insert_page_event(&app, document_id, 2, start + Duration::seconds(60)).await;
+    insert_page_event(&app, document_id, 3, start + Duration::seconds(120)).await;
+    // The reader took a break here, which should not count as reading time
+    insert_page_event(&app, document_id, 4, start + Duration::days(1)).await;
+    let last = start + Duration::days(1) + Duration::seconds(60);
+    insert_page_event(&app, document_id, 5, last).await;
+
+    let url = format!("{}/api/documents/{}/stats", app.address, document_id);
+    let stats = app
+        .client
+        .get(url)
+        .send()
+        .await
+        .unwrap()
+        .json::<DocumentStatistics>()
+        .await
+        .unwrap();
+
+    assert_eq!(stats.reading_time_seconds, 180);
+    assert_eq!(stats.pages_read, 5);
+    assert_eq!(stats.last_opened, Some(last));
+    assert_eq!(
+        stats.pages_per_day,
+        vec![
+            DailyPages {
+                date: start.date_naive(),
+                pages: 3
+            },
+            DailyPages {
+                date: last.date_naive(),
+                pages: 2
+            }
+        ]
+    );
+    // 36 seconds per page, with 95 pages left
+    assert_eq!(stats.estimated_seconds_to_finish, Some(3420));
+}
+
+#[actix_rt::test]
+async fn statistics_for_document_which_does_not_exist() {
+    let app = spawn_app().await;
+
+    let url = format!("{}/api/documents/{}/stats", app.address, Uuid::new_v4());
+    let response = app.client.get(url).send().await.unwrap();
+
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn library_statistics() {
+    let app = spawn_app().await;
+    let started = insert_document(&app, 2, 10).await;
+    let finished = insert_document(&app, 10, 10).await;
+    insert_document(&app, 1, 10).await;
+
+    let start = Utc.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap();
+    insert_page_event(&app, started, 1, start).await;
+    insert_page_event(&app, started, 2, start + Duration::seconds(30)).await;
+    insert_page_event(&app, finished, 9, start + Duration::seconds(40)).await;
+    insert_page_event(&app, finished, 10, start + Duration::seconds(100)).await;
+
+    let url = format!("{}/api/stats", app.address);
+    let response = app.client.get(url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let stats = response.json::<LibraryStatistics>().await.unwrap();
+    assert_eq!(stats.document_count, 3);
+    assert_eq!(stats.documents_started, 2);
+    assert_eq!(stats.documents_finished, 1);
+    assert_eq!(stats.reading_time_seconds, 90);
+    assert_eq!(
+        stats.pages_per_day,
+        vec![DailyPages {
+            date: start.date_naive(),
+            pages: 4
+        }]
+    );
+    // 15 seconds per page with 8 pages left, and nothing left of the finished document
+    assert_eq!(stats.estimated_seconds_to_finish, Some(120));
+    assert_eq!(stats.documents.len(), 3);
+}
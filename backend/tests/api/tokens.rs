@@ -0,0 +1,213 @@
+use chrono::{Duration, Utc};
+use pdf_reader::authentication::TokenScope;
+use pdf_reader::database;
+use pdf_reader::models::{
+    ApiToken, CreateTokenRequest, CreateUserRequest, CreatedToken, UpdateDocumentRequest, User,
+};
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+async fn create_token(
+    app: &TestApp,
+    client: &reqwest::Client,
+    scopes: Vec<TokenScope>,
+) -> reqwest::Response {
+    let request = CreateTokenRequest {
+        name: "script".to_owned(),
+        scopes,
+        expires_on: None,
+    };
+    client
+        .post(format!("{}/api/tokens", &app.address))
+        .json(&request)
+        .send()
+        .await
+        .expect("Failed to send request")
+}
+
+#[actix_rt::test]
+async fn token_can_be_used_instead_of_a_session() {
+    let app = spawn_app().await;
+    let response = create_token(&app, &app.client, vec![TokenScope::Read]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let created = response.json::<CreatedToken>().await.unwrap();
+    assert!(created.token.starts_with("pdfr_"));
+    assert!(created.details.last_used_on.is_none());
+
+    let me = TestApp::new_client()
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(&created.token)
+        .send()
+        .await
+        .unwrap()
+        .json::<User>()
+        .await
+        .unwrap();
+    assert_eq!(me.id, app.user_id);
+
+    let tokens = app
+        .client
+        .get(format!("{}/api/tokens", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<ApiToken>>()
+        .await
+        .unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].scopes, vec!["read"]);
+    assert!(tokens[0].last_used_on.is_some());
+}
+
+#[actix_rt::test]
+async fn tokens_are_stored_hashed() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &app.client, vec![TokenScope::Read])
+        .await
+        .json::<CreatedToken>()
+        .await
+        .unwrap();
+
+    let (token_hash,): (String,) = database::query_as("SELECT token_hash FROM ApiTokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(token_hash, created.token);
+    assert!(!token_hash.contains(&created.token));
+}
+
+#[actix_rt::test]
+async fn read_only_token_can_not_make_changes() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &app.client, vec![TokenScope::Read])
+        .await
+        .json::<CreatedToken>()
+        .await
+        .unwrap();
+    let document_id = app.insert_document("Some document").await;
+
+    let response = TestApp::new_client()
+        .patch(format!("{}/api/documents/{}", &app.address, document_id))
+        .bearer_auth(&created.token)
+        .json(&UpdateDocumentRequest {
+            current_page: Some(2),
+            ..Default::default()
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/documents", &app.address))
+        .bearer_auth(&created.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn revoked_and_expired_tokens_are_rejected() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &app.client, vec![TokenScope::Read])
+        .await
+        .json::<CreatedToken>()
+        .await
+        .unwrap();
+
+    let response = app
+        .client
+        .delete(format!(
+            "{}/api/tokens/{}",
+            &app.address, created.details.id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/documents", &app.address))
+        .bearer_auth(&created.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let created = create_token(&app, &app.client, vec![TokenScope::Read])
+        .await
+        .json::<CreatedToken>()
+        .await
+        .unwrap();
+    database::query("UPDATE ApiTokens SET expires_on = $1 WHERE id = $2")
+        .bind(Utc::now() - Duration::minutes(1))
+        .bind(created.details.id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/documents", &app.address))
+        .bearer_auth(&created.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn tokens_can_not_exceed_the_permissions_of_their_owner() {
+    let app = spawn_app().await;
+    let (_, client) = app.create_user("someone", false).await;
+
+    let response = create_token(&app, &client, vec![TokenScope::Admin]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let created = create_token(&app, &app.client, vec![TokenScope::Read, TokenScope::Write])
+        .await
+        .json::<CreatedToken>()
+        .await
+        .unwrap();
+    let response = TestApp::new_client()
+        .post(format!("{}/api/users", &app.address))
+        .bearer_auth(&created.token)
+        .json(&CreateUserRequest {
+            username: "other".to_owned(),
+            password: "long enough password".to_owned(),
+            is_admin: false,
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+}
+
+#[actix_rt::test]
+async fn tokens_can_not_be_created_with_a_token() {
+    let app = spawn_app().await;
+    let created = create_token(&app, &app.client, vec![TokenScope::Read, TokenScope::Write])
+        .await
+        .json::<CreatedToken>()
+        .await
+        .unwrap();
+
+    let response = TestApp::new_client()
+        .post(format!("{}/api/tokens", &app.address))
+        .bearer_auth(&created.token)
+        .json(&CreateTokenRequest {
+            name: "forever".to_owned(),
+            scopes: vec![TokenScope::Read],
+            expires_on: None,
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let (count,): (i64,) = database::query_as("SELECT COUNT(*) FROM ApiTokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
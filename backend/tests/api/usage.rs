@@ -0,0 +1,186 @@
+use pdf_reader::database;
+use pdf_reader::encryption::FileEncryption;
+use pdf_reader::models::Usage;
+use pdf_reader::storage::FileSystemStorage;
+use pdf_reader::usage::record_missing_sizes;
+use serde_json::Value;
+
+use crate::api::helpers::{spawn_app, TestApp};
+
+const PDF: &[u8] = include_bytes!("../../tests/test_files/pdf-sample.pdf");
+
+async fn fetch_usage(address: &str, client: &reqwest::Client) -> Usage {
+    let response = client
+        .get(format!("{address}/api/usage"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    response
+        .json::<Usage>()
+        .await
+        .expect("Failed to deserialize usage")
+}
+
+/// Uploads every file in a single request
+async fn upload(app: &TestApp, address: &str, files: usize) -> reqwest::Response {
+    let mut form = reqwest::multipart::Form::new();
+    for i in 0..files {
+        let file = reqwest::multipart::Part::bytes(PDF).file_name(format!("file{i}.pdf"));
+        form = form.part("file", file);
+    }
+    app.client
+        .post(format!("{address}/api/documents"))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Starts a server with the given quotas, sharing the database of the test app
+async fn start_server_with_quotas(
+    app: &TestApp,
+    quota_documents: Option<u64>,
+    quota_bytes: Option<u64>,
+) -> String {
+    let server = app
+        .build_server(|configuration| {
+            configuration.quota_documents = quota_documents;
+            configuration.quota_bytes = quota_bytes;
+        })
+        .await;
+    let address = format!("http://localhost:{}", server.port);
+    tokio::spawn(server.run_until_stopped());
+    address
+}
+
+#[actix_rt::test]
+async fn usage_is_counted_per_collection() {
+    let app = spawn_app().await;
+    app.post_document(PDF).await;
+    app.post_document(PDF).await;
+    let documents = app.fetch_documents().await;
+    database::query("UPDATE Documents SET collection = 'papers' WHERE id = $1")
+        .bind(documents[0].id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let (_, other_client) = app.create_user("other", false).await;
+
+    let usage = fetch_usage(&app.address, &app.client).await;
+
+    let size = PDF.len() as i64;
+    assert_eq!(usage.document_count, 2);
+    assert_eq!(usage.byte_size, 2 * size);
+    assert_eq!(usage.quota_documents, None);
+    assert_eq!(usage.quota_bytes, None);
+    let collections = usage
+        .collections
+        .iter()
+        .map(|c| (c.collection.as_deref(), c.document_count, c.byte_size))
+        .collect::<Vec<_>>();
+    assert_eq!(collections, [(None, 1, size), (Some("papers"), 1, size)]);
+
+    let usage = fetch_usage(&app.address, &other_client).await;
+    assert_eq!(usage.document_count, 0);
+    assert_eq!(usage.byte_size, 0);
+    assert!(usage.collections.is_empty());
+}
+
+#[actix_rt::test]
+async fn sizes_of_older_documents_are_read_from_storage() {
+    let app = spawn_app().await;
+    app.post_document(PDF).await;
+    database::query("UPDATE Documents SET byte_size = NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Its file is missing, so it only counts as a document
+    app.insert_document("missing.pdf").await;
+
+    let usage = fetch_usage(&app.address, &app.client).await;
+    assert_eq!(usage.byte_size, 0);
+
+    let storage = FileSystemStorage::new(
+        app.config.documents_storage_path(),
+        FileEncryption::load(&app.config).unwrap(),
+    );
+    record_missing_sizes(&app.db_pool, &storage).await.unwrap();
+    let usage = fetch_usage(&app.address, &app.client).await;
+
+    assert_eq!(usage.document_count, 2);
+    assert_eq!(usage.byte_size, PDF.len() as i64);
+    let (missing,): (i64,) =
+        database::query_as("SELECT COUNT(*) FROM Documents WHERE byte_size IS NULL")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(missing, 1);
+}
+
+#[actix_rt::test]
+async fn uploads_over_the_byte_quota_are_refused() {
+    let app = spawn_app().await;
+    let quota = PDF.len() as u64 + PDF.len() as u64 / 2;
+    let address = start_server_with_quotas(&app, None, Some(quota)).await;
+
+    let response = upload(&app, &address, 1).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let response = upload(&app, &address, 1).await;
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(body["code"], "payload_too_large");
+
+    assert_eq!(app.fetch_documents().await.len(), 1);
+    let stored = std::fs::read_dir(app.config.storage_location.join("server/documents"))
+        .unwrap()
+        .count();
+    assert_eq!(stored, 1);
+    let usage = fetch_usage(&address, &app.client).await;
+    assert_eq!(usage.quota_bytes, Some(quota));
+    assert_eq!(usage.byte_size, PDF.len() as i64);
+}
+
+#[actix_rt::test]
+async fn uploads_over_the_document_quota_are_refused_as_a_whole() {
+    let app = spawn_app().await;
+    let address = start_server_with_quotas(&app, Some(2), None).await;
+
+    let response = upload(&app, &address, 1).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    // The first file fits, but the second does not, so neither is added
+    let response = upload(&app, &address, 2).await;
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(app.fetch_documents().await.len(), 1);
+    let stored = std::fs::read_dir(app.config.storage_location.join("server/documents"))
+        .unwrap()
+        .count();
+    assert_eq!(stored, 1);
+
+    let response = upload(&app, &address, 1).await;
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let usage = fetch_usage(&address, &app.client).await;
+    assert_eq!(usage.document_count, 2);
+    assert_eq!(usage.quota_documents, Some(2));
+}
+
+#[actix_rt::test]
+async fn parallel_uploads_cannot_go_over_the_quota_together() {
+    let app = spawn_app().await;
+    let address = start_server_with_quotas(&app, Some(1), None).await;
+
+    let (first, second) = tokio::join!(upload(&app, &address, 1), upload(&app, &address, 1));
+
+    let mut statuses = [first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(
+        statuses,
+        [
+            reqwest::StatusCode::CREATED,
+            reqwest::StatusCode::PAYLOAD_TOO_LARGE
+        ]
+    );
+    assert_eq!(app.fetch_documents().await.len(), 1);
+}
@@ -0,0 +1,142 @@
+use pdf_reader::database;
+use pdf_reader::models::{CreateUserRequest, Credentials, User};
+
+use crate::api::helpers::{spawn_app, TestApp, TEST_PASSWORD, TEST_USERNAME};
+
+#[actix_rt::test]
+async fn requests_without_a_session_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/documents", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn health_check_does_not_require_a_session() {
+    let app = spawn_app().await;
+
+    let response = TestApp::new_client()
+        .get(format!("{}/api/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn login_and_logout() {
+    let app = spawn_app().await;
+    let client = TestApp::new_client();
+
+    let response = app.login(&client, TEST_USERNAME, "not the password").await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = app.login(&client, "nobody", TEST_PASSWORD).await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = app.login(&client, TEST_USERNAME, TEST_PASSWORD).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    let cookie = response
+        .headers()
+        .get("Set-Cookie")
+        .expect("Session cookie was not set")
+        .to_str()
+        .unwrap();
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("SameSite=Strict"));
+
+    let me = client
+        .get(format!("{}/api/users/me", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json::<User>()
+        .await
+        .unwrap();
+    assert_eq!(me.id, app.user_id);
+    assert_eq!(me.username, TEST_USERNAME);
+    assert!(me.is_admin);
+
+    let response = client
+        .post(format!("{}/api/auth/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let response = client
+        .get(format!("{}/api/users/me", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn passwords_are_stored_hashed() {
+    let app = spawn_app().await;
+
+    let (hash,): (String,) = database::query_as("SELECT password_hash FROM Users WHERE id = $1")
+        .bind(app.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert!(hash.starts_with("$argon2"));
+    assert!(!hash.contains(TEST_PASSWORD));
+}
+
+#[actix_rt::test]
+async fn setup_can_only_be_done_once() {
+    let app = spawn_app().await;
+
+    let response = TestApp::new_client()
+        .post(format!("{}/api/auth/setup", &app.address))
+        .json(&Credentials {
+            username: "intruder".to_owned(),
+            password: TEST_PASSWORD.to_owned(),
+        })
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+}
+
+#[actix_rt::test]
+async fn only_administrators_can_create_users() {
+    let app = spawn_app().await;
+    let (_, client) = app.create_user("another.reader", false).await;
+
+    let request = CreateUserRequest {
+        username: "third.reader".to_owned(),
+        password: TEST_PASSWORD.to_owned(),
+        is_admin: false,
+    };
+    let response = client
+        .post(format!("{}/api/users", &app.address))
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let response = app
+        .client
+        .post(format!("{}/api/users", &app.address))
+        .json(&CreateUserRequest {
+            username: "another.reader".to_owned(),
+            password: TEST_PASSWORD.to_owned(),
+            is_admin: false,
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+}
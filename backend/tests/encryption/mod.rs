@@ -0,0 +1,138 @@
+use std::io::Write;
+
+use futures::TryStreamExt;
+use pdf_reader::encryption::{FileEncryption, CHUNK_SIZE};
+use pdf_reader::secrets::SecretKey;
+use pdf_reader::storage::{DocumentStorage, FileSystemStorage};
+use tempfile::TempDir;
+use uuid::Uuid;
+
+fn encryption() -> FileEncryption {
+    let key = SecretKey::from_hex(&"5a".repeat(32)).unwrap();
+    FileEncryption::new(Some(key), true).unwrap()
+}
+
+/// Stores `contents` as a document, encrypted
+fn store(directory: &TempDir, encryption: &FileEncryption, contents: &[u8]) -> Uuid {
+    let id = Uuid::new_v4();
+    let path = directory.path().join(format!("{id}.pdf"));
+    let mut writer = encryption.create(&path).unwrap();
+    writer.write_all(contents).unwrap();
+    writer.finish().unwrap();
+    id
+}
+
+async fn read(storage: &dyn DocumentStorage, id: &Uuid, offset: u64, length: u64) -> Vec<u8> {
+    let chunks = storage
+        .read(id, offset, length)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    chunks.concat()
+}
+
+#[actix_rt::test]
+async fn encrypted_documents_are_read_in_ranges() {
+    let directory = TempDir::new().expect("Failed to create temp dir");
+    let encryption = encryption();
+    let contents = (0..3 * CHUNK_SIZE + 123)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let id = store(&directory, &encryption, &contents);
+    let storage = FileSystemStorage::new(directory.path().to_owned(), encryption);
+
+    let stored = std::fs::read(directory.path().join(format!("{id}.pdf"))).unwrap();
+    assert!(stored.len() > contents.len());
+    assert!(!stored.windows(64).any(|w| w == &contents[..64]));
+    assert_eq!(storage.size(&id).await.unwrap(), contents.len() as u64);
+
+    let size = contents.len() as u64;
+    let ranges = [
+        (0, size),
+        (0, 10),
+        (CHUNK_SIZE - 5, 10),
+        (CHUNK_SIZE, CHUNK_SIZE),
+        (CHUNK_SIZE + 1, 2 * CHUNK_SIZE),
+        (size - 1, 1),
+    ];
+    for (offset, length) in ranges {
+        let range = offset as usize..(offset + length) as usize;
+        assert_eq!(read(&storage, &id, offset, length).await, &contents[range]);
+    }
+}
+
+#[actix_rt::test]
+async fn empty_and_chunk_sized_documents_can_be_encrypted() {
+    let directory = TempDir::new().expect("Failed to create temp dir");
+    let encryption = encryption();
+    let storage = FileSystemStorage::new(directory.path().to_owned(), encryption.clone());
+
+    for size in [0, 1, CHUNK_SIZE, 2 * CHUNK_SIZE] {
+        let contents = vec![7; size as usize];
+        let id = store(&directory, &encryption, &contents);
+        assert_eq!(storage.size(&id).await.unwrap(), size);
+        assert_eq!(read(&storage, &id, 0, size).await, contents);
+    }
+}
+
+#[actix_rt::test]
+async fn plain_documents_are_still_read() {
+    let directory = TempDir::new().expect("Failed to create temp dir");
+    let id = Uuid::new_v4();
+    std::fs::write(directory.path().join(format!("{id}.pdf")), b"0123456789").unwrap();
+    let storage = FileSystemStorage::new(directory.path().to_owned(), encryption());
+
+    assert_eq!(storage.size(&id).await.unwrap(), 10);
+    assert_eq!(read(&storage, &id, 2, 4).await, b"2345");
+}
+
+#[actix_rt::test]
+async fn encrypted_documents_need_the_right_key() {
+    let directory = TempDir::new().expect("Failed to create temp dir");
+    let id = store(&directory, &encryption(), b"confidential");
+
+    let storage = FileSystemStorage::new(directory.path().to_owned(), FileEncryption::default());
+    assert!(storage.size(&id).await.is_err());
+
+    let other_key = SecretKey::from_hex(&"a5".repeat(32)).unwrap();
+    let other = FileEncryption::new(Some(other_key), true).unwrap();
+    let storage = FileSystemStorage::new(directory.path().to_owned(), other);
+    assert!(storage.read(&id, 0, 12).await.is_err());
+}
+
+#[actix_rt::test]
+async fn tampered_documents_are_not_read() {
+    let directory = TempDir::new().expect("Failed to create temp dir");
+    let encryption = encryption();
+    let contents = vec![1; 2 * CHUNK_SIZE as usize];
+    let id = store(&directory, &encryption, &contents);
+    let path = directory.path().join(format!("{id}.pdf"));
+    let storage = FileSystemStorage::new(directory.path().to_owned(), encryption);
+
+    let mut stored = std::fs::read(&path).unwrap();
+    let last = stored.len() - 1;
+    stored[last] ^= 1;
+    std::fs::write(&path, &stored).unwrap();
+    let result = storage
+        .read(&id, CHUNK_SIZE, 10)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await;
+    assert!(result.is_err());
+    // The chunks before the damaged one are still readable
+    assert_eq!(read(&storage, &id, 0, 10).await, &contents[..10]);
+
+    // Dropping the last chunk is noticed as well
+    stored.truncate(stored.len() - (CHUNK_SIZE as usize + 16));
+    std::fs::write(&path, &stored).unwrap();
+    let result = storage
+        .read(&id, 0, 10)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await;
+    assert!(result.is_err());
+}
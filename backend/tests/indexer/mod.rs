@@ -1,4 +1,6 @@
+use pdf_reader::encryption::FileEncryption;
 use pdf_reader::indexer::Indexer;
+use pdf_reader::secrets::SecretKey;
 use tempfile::TempDir;
 use uuid::Uuid;
 
@@ -7,7 +9,7 @@ async fn test_index_and_search() {
     let index_path = TempDir::new().expect("Failed to create temp dir");
     let id = Uuid::new_v4();
 
-    let indexer = Indexer::new(index_path.into_path()).expect("Failed to create indexer");
+    let indexer = Indexer::new(index_path.keep()).expect("Failed to create indexer");
     let mut writer = indexer.get_writer().await.expect("Failed to create writer");
     writer
         .index_page(&id, 4, "These are the contents of the page")
@@ -19,5 +21,78 @@ async fn test_index_and_search() {
         .expect("Failed to run search");
 
     assert_eq!(1, result.len());
-    assert_eq!(4, result.get(0).unwrap().page);
+    assert_eq!(4, result.first().unwrap().page);
+}
+
+#[actix_rt::test]
+async fn uncommitted_pages_are_rolled_back() {
+    let index_path = TempDir::new().expect("Failed to create temp dir");
+    let interrupted = Uuid::new_v4();
+    let completed = Uuid::new_v4();
+
+    let indexer = Indexer::new(index_path.keep()).expect("Failed to create indexer");
+    let mut writer = indexer.get_writer().await.expect("Failed to create writer");
+    writer
+        .index_page(&interrupted, 1, "These are the contents of the page")
+        .expect("Failed to index page");
+    drop(writer);
+
+    let mut writer = indexer.get_writer().await.expect("Failed to create writer");
+    writer
+        .index_page(&completed, 1, "These are the contents of the page")
+        .expect("Failed to index page");
+    writer.commit().unwrap();
+
+    assert!(indexer
+        .search_document(&interrupted, "contents")
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        1,
+        indexer
+            .search_document(&completed, "contents")
+            .unwrap()
+            .len()
+    );
+}
+
+#[actix_rt::test]
+async fn closed_index_cannot_be_written() {
+    let index_path = TempDir::new().expect("Failed to create temp dir");
+    let indexer = Indexer::new(index_path.keep()).expect("Failed to create indexer");
+
+    indexer.close().await.expect("Failed to close index");
+
+    assert!(indexer.get_writer().await.is_err());
+}
+
+#[actix_rt::test]
+async fn encrypted_index_can_be_reopened_with_its_key() {
+    let index_path = TempDir::new().expect("Failed to create temp dir");
+    let key = SecretKey::from_hex(&"ab".repeat(32)).unwrap();
+    let encryption = FileEncryption::new(Some(key), true).unwrap();
+    let id = Uuid::new_v4();
+
+    let indexer = Indexer::with_encryption(index_path.path().to_owned(), encryption.clone())
+        .expect("Failed to create indexer");
+    let mut writer = indexer.get_writer().await.expect("Failed to create writer");
+    writer
+        .index_page(&id, 2, "These are the confidential contents of the page")
+        .expect("Failed to index page");
+    writer.commit().unwrap();
+    drop(indexer);
+
+    for entry in std::fs::read_dir(index_path.path()).unwrap() {
+        let contents = std::fs::read(entry.unwrap().path()).unwrap();
+        assert!(!contents
+            .windows(b"confidential".len())
+            .any(|w| w == b"confidential"));
+    }
+    assert!(Indexer::new(index_path.path().to_owned()).is_err());
+
+    let indexer = Indexer::with_encryption(index_path.path().to_owned(), encryption)
+        .expect("Failed to reopen indexer");
+    let result = indexer.search_document(&id, "confidential").unwrap();
+    assert_eq!(1, result.len());
+    assert_eq!(2, result[0].page);
 }
@@ -1,2 +1,6 @@
 pub mod api;
+pub mod configuration;
+pub mod encryption;
 pub mod indexer;
+pub mod shutdown;
+pub mod telemetry;
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use pdf_reader::error::ErrorCode;
+use pdf_reader::shutdown::Shutdown;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+#[test]
+fn uploads_are_refused_once_shutdown_is_requested() {
+    let shutdown = Arc::new(Shutdown::new());
+    let user = Uuid::new_v4();
+
+    let upload = shutdown.begin_upload(user).expect("Upload was refused");
+    assert_eq!(shutdown.uploads_in_flight(), 1);
+
+    shutdown.request();
+    let Err(error) = shutdown.begin_upload(user) else {
+        panic!("Upload was accepted during shutdown");
+    };
+    assert_eq!(error.code, ErrorCode::ServiceUnavailable);
+    assert_eq!(shutdown.uploads_in_flight(), 1);
+
+    upload.finish();
+    assert_eq!(shutdown.uploads_in_flight(), 0);
+    assert!(shutdown.aborted_uploads().is_empty());
+}
+
+#[test]
+fn unfinished_uploads_delete_their_files_and_are_reported() {
+    let directory = TempDir::new().expect("Failed to create temp dir");
+    let shutdown = Arc::new(Shutdown::new());
+    let user = Uuid::new_v4();
+
+    let kept = directory.path().join("kept.pdf");
+    std::fs::write(&kept, "complete").unwrap();
+    let mut upload = shutdown.begin_upload(user).unwrap();
+    upload.add_file(kept.clone());
+    upload.finish();
+
+    let partial = directory.path().join("partial.pdf");
+    std::fs::write(&partial, "half of a").unwrap();
+    let mut upload = shutdown.begin_upload(user).unwrap();
+    upload.add_file(partial.clone());
+    upload.add_file(directory.path().join("not-written-yet.pdf"));
+    shutdown.request();
+    drop(upload);
+
+    assert!(kept.exists());
+    assert!(!partial.exists());
+    let aborted = shutdown.aborted_uploads();
+    assert_eq!(aborted.len(), 1);
+    assert_eq!(aborted[0].user, user);
+    assert_eq!(aborted[0].documents, 2);
+}
+
+#[actix_rt::test]
+async fn waiting_for_uploads_times_out() {
+    let shutdown = Arc::new(Shutdown::new());
+    let upload = shutdown.begin_upload(Uuid::new_v4()).unwrap();
+
+    assert!(!shutdown.wait_for_uploads(Duration::from_millis(50)).await);
+
+    let waiting = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { shutdown.wait_for_uploads(Duration::from_secs(5)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    upload.finish();
+    assert!(waiting.await.unwrap());
+}
@@ -0,0 +1,88 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use pdf_reader::indexer::Indexer;
+use pdf_reader::telemetry::{get_subscriber, shutdown_subscriber};
+use tempfile::TempDir;
+use uuid::Uuid;
+
+/// Stands in for an OpenTelemetry collector by accepting OTLP/HTTP requests,
+/// and handing the body of each of them to the test.
+fn start_collector() -> (String, mpsc::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind collector");
+    let endpoint = format!(
+        "http://{}/v1/traces",
+        listener.local_addr().expect("Collector has no address")
+    );
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            std::thread::spawn(move || handle_connection(stream, sender));
+        }
+    });
+
+    (endpoint, receiver)
+}
+
+fn handle_connection(stream: TcpStream, sender: mpsc::Sender<Vec<u8>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        writer
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        let _ = sender.send(body);
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+// The exporter schedules its batches on the runtime it is set up from
+#[actix_rt::test]
+async fn spans_are_exported_to_collector() {
+    let (endpoint, exports) = start_collector();
+    let index_path = TempDir::new().expect("Failed to create temp dir");
+    let indexer = Indexer::new(index_path.keep()).expect("Failed to create indexer");
+    let document_id = Uuid::new_v4();
+
+    let subscriber = get_subscriber("test".into(), "info".into(), std::io::sink, Some(endpoint));
+    tracing::subscriber::with_default(subscriber, || {
+        indexer
+            .search_document(&document_id, "contents")
+            .expect("Failed to run search");
+    });
+    shutdown_subscriber();
+
+    let export = exports
+        .recv_timeout(Duration::from_secs(10))
+        .expect("No spans were exported");
+    assert!(contains(&export, b"search_document"));
+    assert!(contains(&export, b"document_id"));
+    assert!(contains(&export, document_id.to_string().as_bytes()));
+    assert!(contains(&export, b"result_count"));
+}
@@ -83,7 +83,7 @@ function AddBookmarkDialog(props: AddBookmarkDialogProps): JSX.Element {
           {addError && (
             <Alert severity="error">
               <AlertTitle>{addError?.message}</AlertTitle>
-              {addError?.response?.data}
+              {addError?.response?.data?.message}
             </Alert>
           )}
           <TextField
@@ -208,7 +208,7 @@ export default function BookmarkDrawer({
           <ListItem>
             <ListItemText>
               <Typography>
-                {bookmarksError.message} - {bookmarksError.response?.data}
+                {bookmarksError.message} - {bookmarksError.response?.data?.message}
               </Typography>
             </ListItemText>
           </ListItem>
@@ -0,0 +1,68 @@
+import React, { useState } from "react";
+import axios from "axios";
+import Box from "@mui/material/Box";
+import Button from "@mui/material/Button";
+import TextField from "@mui/material/TextField";
+import Typography from "@mui/material/Typography";
+
+interface LoginProps {
+  loggedInCallback: () => void;
+}
+
+export function Login({ loggedInCallback }: LoginProps): React.ReactElement {
+  const [username, setUsername] = useState("");
+  const [password, setPassword] = useState("");
+  const [error, setError] = useState<string | null>(null);
+
+  const submit = (endpoint: string) => {
+    axios
+      .post(endpoint, { username, password })
+      .then(() => {
+        setError(null);
+        loggedInCallback();
+      })
+      .catch((e) => {
+        setError(e.response?.data?.message ?? e.message);
+      });
+  };
+
+  return (
+    <Box
+      component="form"
+      sx={{
+        display: "flex",
+        flexDirection: "column",
+        gap: 2,
+        maxWidth: 320,
+        m: "auto",
+        mt: 10,
+      }}
+      onSubmit={(e: React.FormEvent) => {
+        e.preventDefault();
+        submit("/api/auth/login");
+      }}
+    >
+      <Typography variant="h5">Log in</Typography>
+      <TextField
+        label="Username"
+        autoComplete="username"
+        value={username}
+        onChange={(e) => setUsername(e.target.value)}
+      />
+      <TextField
+        label="Password"
+        type="password"
+        autoComplete="current-password"
+        value={password}
+        onChange={(e) => setPassword(e.target.value)}
+      />
+      {error && <Typography color="error">{error}</Typography>}
+      <Button type="submit" variant="contained">
+        Log in
+      </Button>
+      <Button onClick={() => submit("/api/auth/setup")}>
+        Create first account
+      </Button>
+    </Box>
+  );
+}
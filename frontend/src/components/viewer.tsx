@@ -91,7 +91,7 @@ export default function Viewer(props: ViewerProps) {
       style={props.drawerOpen ? { marginLeft: `${props.drawerWidth}px` } : {}}
     >
       <Document
-        file={`/api/documents/${props.document}`}
+        file={`/api/documents/${props.document}?inline=true`}
         onLoadSuccess={onDocumentLoadSuccess}
         externalLinkTarget="_blank"
         renderMode="canvas"
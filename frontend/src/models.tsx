@@ -2,6 +2,11 @@ export interface Document {
   id: string;
   name: string;
   current_page: number;
+  title: string | null;
+  author: string | null;
+  notes: string | null;
+  page_count: number | null;
+  content_hash: string | null;
 }
 
 export interface Bookmark {
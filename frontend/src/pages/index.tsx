@@ -7,6 +7,7 @@ import { Document } from "../models";
 import axios from "axios";
 import { Box, Toolbar } from "@mui/material";
 import Drawer, { SubDrawer } from "../components/Drawer/drawer";
+import { Login } from "../components/login";
 
 const Home: NextPage = () => {
   return (
@@ -37,6 +38,7 @@ function Main() {
   const [fitToHeight, setFitToHeight] = useState<boolean>(true);
   const [drawerMode, setDrawerMode] = useState<SubDrawer>(SubDrawer.Bookmarks);
   const [drawerWidth, setDrawerWidth] = useState(300);
+  const [needsLogin, setNeedsLogin] = useState<boolean>(false);
 
   const _setCurrentPage = (page: number) => {
     setPreviousPage(currentPage);
@@ -98,7 +100,11 @@ function Main() {
         setCurrentDocument(lastDocument, docs.data);
         setCorrectCurrentPage(lastDocument, docs.data);
       })
-      .catch((e: Error) => {
+      .catch((e) => {
+        if (axios.isAxiosError(e) && e.response?.status === 401) {
+          setNeedsLogin(true);
+          return;
+        }
         setDocumentsFetchError(e.message);
       });
   };
@@ -108,6 +114,17 @@ function Main() {
     if (numPages != numberOfPages) setNumPages(numberOfPages);
   };
 
+  if (needsLogin) {
+    return (
+      <Login
+        loggedInCallback={() => {
+          setNeedsLogin(false);
+          updateDocuments();
+        }}
+      />
+    );
+  }
+
   return (
     <Box>
       <TopMenu